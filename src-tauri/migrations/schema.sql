@@ -20,7 +20,23 @@ CREATE TABLE IF NOT EXISTS ai_configs (
     is_default       INTEGER NOT NULL DEFAULT 0,
     concurrent_limit INTEGER NOT NULL DEFAULT 5,
     request_timeout  INTEGER NOT NULL DEFAULT 180,
-    rate_limit       INTEGER NOT NULL DEFAULT 60
+    rate_limit       INTEGER NOT NULL DEFAULT 60,
+    max_input_tokens  INTEGER NOT NULL DEFAULT 0,
+    max_output_tokens INTEGER NOT NULL DEFAULT 0,
+    tpm_limit         INTEGER NOT NULL DEFAULT 0,
+    proxy             TEXT NOT NULL DEFAULT '',
+    capabilities      TEXT NOT NULL DEFAULT ''
+);
+
+-- Additional API keys of an AI config; requests are spread over all keys
+CREATE TABLE IF NOT EXISTS ai_config_keys (
+    config_id        TEXT NOT NULL,
+    position         INTEGER NOT NULL,
+    api_key          TEXT NOT NULL,
+    concurrent_limit INTEGER NOT NULL DEFAULT 0,
+    rate_limit       INTEGER NOT NULL DEFAULT 0,
+    tpm_limit        INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (config_id, position)
 );
 
 CREATE TABLE IF NOT EXISTS translation_progress (
@@ -28,10 +44,78 @@ CREATE TABLE IF NOT EXISTS translation_progress (
     subtitle_index INTEGER NOT NULL,
     phase          TEXT NOT NULL,
     result_text    TEXT NOT NULL,
+    status         TEXT NOT NULL DEFAULT 'translated',
+    error          TEXT,
+    source_hash    TEXT NOT NULL DEFAULT '',
+    prompt_hash    TEXT NOT NULL DEFAULT '',
     created_at     TEXT NOT NULL DEFAULT (datetime('now')),
     PRIMARY KEY (project_dir, subtitle_index, phase)
 );
 
+CREATE TABLE IF NOT EXISTS translation_reviews (
+    project_dir    TEXT NOT NULL,
+    subtitle_index INTEGER NOT NULL,
+    accuracy       REAL NOT NULL,
+    fluency        REAL NOT NULL,
+    terminology    REAL NOT NULL,
+    score          REAL NOT NULL,
+    reason         TEXT NOT NULL DEFAULT '',
+    content_hash   TEXT NOT NULL DEFAULT '',
+    error          TEXT,
+    created_at     TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (project_dir, subtitle_index)
+);
+
+CREATE TABLE IF NOT EXISTS correction_reviews (
+    project_dir    TEXT NOT NULL,
+    subtitle_index INTEGER NOT NULL,
+    original       TEXT NOT NULL,
+    corrected      TEXT NOT NULL,
+    decision       TEXT NOT NULL DEFAULT 'pending',
+    PRIMARY KEY (project_dir, subtitle_index)
+);
+
+CREATE TABLE IF NOT EXISTS translation_memory (
+    project_dir     TEXT NOT NULL,
+    source_text     TEXT NOT NULL,
+    target_language TEXT NOT NULL,
+    target_text     TEXT NOT NULL,
+    updated_at      TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (project_dir, source_text, target_language)
+);
+
+CREATE TABLE IF NOT EXISTS translation_line_choices (
+    project_dir     TEXT NOT NULL,
+    subtitle_index  INTEGER NOT NULL,
+    target_language TEXT NOT NULL,
+    source_text     TEXT NOT NULL,
+    target_text     TEXT NOT NULL,
+    updated_at      TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (project_dir, subtitle_index, target_language)
+);
+
+CREATE TABLE IF NOT EXISTS glossary_terms (
+    project_dir TEXT NOT NULL,
+    source      TEXT NOT NULL,
+    target      TEXT NOT NULL DEFAULT '',
+    category    TEXT NOT NULL DEFAULT '',
+    note        TEXT NOT NULL DEFAULT '',
+    occurrences INTEGER NOT NULL DEFAULT 0,
+    approved    INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (project_dir, source)
+);
+
+CREATE TABLE IF NOT EXISTS prompt_templates (
+    id          TEXT NOT NULL,
+    version     INTEGER NOT NULL,
+    name        TEXT NOT NULL,
+    kind        TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    content     TEXT NOT NULL,
+    created_at  TEXT NOT NULL,
+    PRIMARY KEY (id, version)
+);
+
 CREATE TABLE IF NOT EXISTS dubbing_jobs (
     id                   TEXT PRIMARY KEY,
     project_dir          TEXT NOT NULL UNIQUE,
@@ -117,7 +201,8 @@ CREATE TABLE IF NOT EXISTS workbench_step_translate (
     config_json               TEXT NOT NULL DEFAULT '{}',
     translated_subtitles_path TEXT,
     subtitle_count            INTEGER NOT NULL DEFAULT 0,
-    completed_at              TEXT
+    completed_at              TEXT,
+    content_summary           TEXT NOT NULL DEFAULT ''
 );
 
 CREATE INDEX IF NOT EXISTS idx_workbench_tasks_created ON workbench_tasks(created_at DESC);
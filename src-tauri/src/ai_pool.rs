@@ -1,10 +1,15 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use crate::db::queries::AiConfig;
+use crate::http::{self, ProxyConfig};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
 use std::time::{Duration, Instant};
-use tokio::sync::{Mutex, OwnedSemaphorePermit, RwLock, Semaphore};
+use tokio::sync::{oneshot, Mutex};
 
 // ── TokenBucket ──────────────────────────────────────────────────────────────
 
@@ -31,14 +36,18 @@ impl TokenBucket {
         }
     }
 
+    fn refill(&mut self) {
+        let elapsed = self.last_update.elapsed().as_secs_f64();
+        self.last_update = Instant::now();
+        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+    }
+
     /// Returns Ok(()) if a token is available, or Err(wait_ms) if we need to wait.
     fn try_acquire(&mut self) -> Result<(), u64> {
         if self.refill_rate == 0.0 {
             return Ok(());
         }
-        let elapsed = self.last_update.elapsed().as_secs_f64();
-        self.last_update = Instant::now();
-        self.tokens = (self.tokens + elapsed * self.refill_rate).min(self.capacity);
+        self.refill();
         if self.tokens >= 1.0 {
             self.tokens -= 1.0;
             Ok(())
@@ -47,105 +56,1150 @@ impl TokenBucket {
             Err((wait_secs * 1000.0).ceil() as u64)
         }
     }
+
+    /// Return a token taken for a request that was never sent.
+    fn refund(&mut self) {
+        self.credit(1.0);
+    }
+
+    /// Take `amount` tokens even if the bucket runs into debt, and return how long
+    /// the caller must wait until the debt is paid off. Later callers queue behind
+    /// it, so large reservations are not starved by small ones.
+    fn reserve(&mut self, amount: f64) -> Duration {
+        if self.refill_rate == 0.0 {
+            return Duration::ZERO;
+        }
+        self.refill();
+        self.tokens -= amount.min(self.capacity);
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.refill_rate)
+        }
+    }
+
+    /// Tokens currently available (negative while in debt); `None` when unlimited.
+    fn available(&mut self) -> Option<f64> {
+        if self.refill_rate == 0.0 {
+            return None;
+        }
+        self.refill();
+        Some(self.tokens)
+    }
+
+    /// Give back `amount` tokens (negative to take more).
+    fn credit(&mut self, amount: f64) {
+        if self.refill_rate > 0.0 {
+            self.tokens = (self.tokens + amount).min(self.capacity);
+        }
+    }
+}
+
+// ── Stats ────────────────────────────────────────────────────────────────────
+
+/// Durations kept per config for the wait and latency figures.
+const STAT_SAMPLES: usize = 200;
+
+/// The most recent `STAT_SAMPLES` durations in milliseconds.
+#[derive(Default)]
+struct Samples(VecDeque<u64>);
+
+impl Samples {
+    fn push(&mut self, d: Duration) {
+        if self.0.len() >= STAT_SAMPLES {
+            self.0.pop_front();
+        }
+        self.0.push_back(d.as_millis() as u64);
+    }
+
+    fn avg(&self) -> u64 {
+        if self.0.is_empty() {
+            return 0;
+        }
+        self.0.iter().sum::<u64>() / self.0.len() as u64
+    }
+
+    fn p95(&self) -> u64 {
+        let mut sorted: Vec<u64> = self.0.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (sorted.len() * 95).div_ceil(100);
+        sorted.get(rank.saturating_sub(1)).copied().unwrap_or(0)
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    /// Time from queueing to getting a slot.
+    waits: Samples,
+    /// Time from sending a request to its response headers.
+    latencies: Samples,
+    successes: u64,
+    errors: u64,
+    throttles: u64,
+}
+
+/// Runtime state of one config's pool, for telling local queueing, provider
+/// throttling and slow models apart.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AiPoolStats {
+    pub config_id: String,
+    /// Set on the entries of the individual keys of a multi-key config (masked).
+    pub api_key: Option<String>,
+    pub in_flight: usize,
+    pub queued: usize,
+    /// Effective concurrency (lowered after 429s) and the configured one.
+    pub concurrent_limit: usize,
+    pub max_concurrent_limit: usize,
+    /// Time left of a provider-requested pause, and why ("429", "requests",
+    /// "tokens", or "auth"/"quota" for a disabled key).
+    pub paused_ms: u64,
+    pub pause_reason: Option<String>,
+    pub avg_wait_ms: u64,
+    pub p95_wait_ms: u64,
+    pub avg_latency_ms: u64,
+    pub p95_latency_ms: u64,
+    pub successes: u64,
+    pub errors: u64,
+    pub throttles: u64,
+    /// Request and token bucket levels; `None` when that limit is off.
+    pub request_tokens: Option<f64>,
+    pub tpm_tokens: Option<f64>,
+    /// Circuit breaker state: "closed", "open" or "half-open".
+    pub circuit: &'static str,
+}
+
+// ── Circuit breaker ──────────────────────────────────────────────────────────
+
+/// Consecutive failures that open the circuit.
+const BREAKER_THRESHOLD: u32 = 5;
+/// First open period; doubled after each failed probe, up to `BREAKER_MAX_COOLDOWN`.
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+const BREAKER_MAX_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Start of the error returned while a circuit is open.
+pub const ENDPOINT_UNAVAILABLE: &str = "接口不可用";
+
+/// Whether `err` was returned by an open circuit rather than by the endpoint.
+pub fn is_endpoint_unavailable(err: &str) -> bool {
+    err.starts_with(ENDPOINT_UNAVAILABLE)
+}
+
+/// Whether `err` is a request that gave up on a pause too long to wait out.
+pub fn is_paused(err: &str) -> bool {
+    err.starts_with(PAUSED_PREFIX)
+}
+
+/// Failed chat request, with the HTTP status when the endpoint answered with one.
+#[derive(Debug, Clone)]
+pub struct ChatError {
+    pub message: String,
+    pub status: Option<StatusCode>,
+}
+
+impl ChatError {
+    pub fn status(status: StatusCode, message: String) -> Self {
+        ChatError { message, status: Some(status) }
+    }
+
+    /// A 429 answer, or a throttling pause too long to wait out.
+    pub fn rate_limited(&self) -> bool {
+        self.status == Some(StatusCode::TOO_MANY_REQUESTS) || is_paused(&self.message)
+    }
+
+    /// Returned by an open circuit.
+    pub fn unavailable(&self) -> bool {
+        is_endpoint_unavailable(&self.message)
+    }
+
+    /// Errors after which a request moves on to the next config of the chain right
+    /// away: 429s and open circuits.
+    pub fn hands_over(&self) -> bool {
+        self.rate_limited() || self.unavailable()
+    }
+}
+
+impl From<String> for ChatError {
+    fn from(message: String) -> Self {
+        ChatError { message, status: None }
+    }
+}
+
+impl From<ChatError> for String {
+    fn from(e: ChatError) -> Self {
+        e.message
+    }
+}
+
+/// Stops requests to an endpoint that keeps failing (connection errors, 5xx).
+/// Opens after `BREAKER_THRESHOLD` failures in a row and fails fast while open;
+/// after the cooldown a single probe goes through (half-open), and its outcome
+/// closes the circuit or opens it again for twice as long.
+#[derive(Default)]
+pub struct CircuitBreaker {
+    failures: u32,
+    open_until: Option<Instant>,
+    cooldown: Duration,
+    /// When the half-open probe was let through.
+    probe_started: Option<Instant>,
+    last_error: String,
+}
+
+impl CircuitBreaker {
+    /// Ok if a request may go out, otherwise the error to fail with.
+    pub fn allow(&mut self) -> Result<(), String> {
+        let Some(until) = self.open_until else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        // A probe that never reported back (its request was dropped) is replaced
+        let probing = self
+            .probe_started
+            .is_some_and(|t| now.duration_since(t) < BREAKER_MAX_COOLDOWN);
+        if now >= until && !probing {
+            self.probe_started = Some(now);
+            return Ok(());
+        }
+        Err(self.error(until))
+    }
+
+    fn error(&self, until: Instant) -> String {
+        let wait = until
+            .saturating_duration_since(Instant::now())
+            .as_secs()
+            .max(1);
+        format!(
+            "{ENDPOINT_UNAVAILABLE}：连续 {} 次请求失败，{wait} 秒后重试（{}）",
+            self.failures, self.last_error
+        )
+    }
+
+    /// The error to fail with while the circuit is open (not yet half-open).
+    pub fn open_error(&self) -> Option<String> {
+        let until = self.open_until?;
+        (Instant::now() < until).then(|| self.error(until))
+    }
+
+    pub fn on_success(&mut self) {
+        *self = CircuitBreaker::default();
+    }
+
+    pub fn on_failure(&mut self, error: &str) {
+        self.failures += 1;
+        self.last_error = error.chars().take(200).collect();
+        let now = Instant::now();
+        if self.probe_started.take().is_some() {
+            self.cooldown = (self.cooldown * 2).min(BREAKER_MAX_COOLDOWN);
+            self.open_until = Some(now + self.cooldown);
+        } else if self.open_until.is_none() && self.failures >= BREAKER_THRESHOLD {
+            self.cooldown = BREAKER_COOLDOWN;
+            self.open_until = Some(now + self.cooldown);
+        }
+    }
+
+    /// "closed", "open" or "half-open".
+    pub fn state(&self) -> &'static str {
+        match self.open_until {
+            None => "closed",
+            Some(until) if Instant::now() < until => "open",
+            Some(_) => "half-open",
+        }
+    }
+}
+
+/// Circuit breakers of TTS providers, by plugin id ("ncn" for the built-in one).
+#[derive(Default)]
+pub struct TtsBreakers(StdMutex<HashMap<String, CircuitBreaker>>);
+
+impl TtsBreakers {
+    fn with<T>(&self, provider: &str, f: impl FnOnce(&mut CircuitBreaker) -> T) -> T {
+        let mut map = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        f(map.entry(provider.to_string()).or_default())
+    }
+
+    pub fn allow(&self, provider: &str) -> Result<(), String> {
+        self.with(provider, CircuitBreaker::allow)
+    }
+
+    pub fn report(&self, provider: &str, result: Result<(), &str>) {
+        self.with(provider, |breaker| match result {
+            Ok(()) => breaker.on_success(),
+            Err(e) => breaker.on_failure(e),
+        });
+    }
 }
 
 // ── ConfigController ─────────────────────────────────────────────────────────
 
+/// Scheduling class of a request. Interactive requests (a single line the user is
+/// waiting on) are served before bulk ones (batch translation, preprocessing).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Priority {
+    Interactive,
+    Bulk,
+}
+
+struct Waiter {
+    grant: oneshot::Sender<()>,
+    abort_flag: Arc<AtomicBool>,
+}
+
+struct ControllerState {
+    /// Configured concurrency; `limit` climbs back to it after throttling.
+    max_limit: usize,
+    /// Effective concurrency, halved on 429 and raised by one per `limit` successes.
+    limit: usize,
+    success_streak: usize,
+    in_use: usize,
+    /// Set while the provider asks us to back off; no slot is granted until then.
+    paused_until: Option<Instant>,
+    pause_reason: String,
+    rate_limit: u32,
+    bucket: TokenBucket,
+    /// Tokens per minute; reserved per request by `RateFeedback::reserve_tokens`.
+    tpm_limit: u32,
+    token_bucket: TokenBucket,
+    /// FIFO queues, indexed by `Priority as usize`.
+    queues: [VecDeque<Waiter>; 2],
+    /// A wake-up for the rate limiter is already scheduled.
+    timer_armed: bool,
+    counters: Counters,
+    breaker: CircuitBreaker,
+}
+
+/// Concurrency slots and request rate of one AI config. Waiters queue in FIFO
+/// order per priority class and are woken when a slot is released or the rate
+/// limiter refills; nobody polls.
 pub(crate) struct ConfigController {
-    semaphore: RwLock<Arc<Semaphore>>,
-    rate_limiter: Mutex<TokenBucket>,
+    state: StdMutex<ControllerState>,
+}
+
+/// A concurrency slot; released when dropped. Remembers how it was requested, so
+/// the key slot leased under it queues the same way.
+pub struct PoolPermit {
+    ctrl: Arc<ConfigController>,
+    priority: Priority,
+    abort_flag: Arc<AtomicBool>,
+}
+
+impl PoolPermit {
+    /// The cancel flag the slot was requested with.
+    pub fn abort_flag(&self) -> &AtomicBool {
+        &self.abort_flag
+    }
+}
+
+impl Drop for PoolPermit {
+    fn drop(&mut self) {
+        self.ctrl.release();
+    }
+}
+
+/// Receiving end of a queued request. If the request is dropped after its slot
+/// was granted but before it was picked up, the slot goes back to the pool.
+struct PendingGrant {
+    ctrl: Arc<ConfigController>,
+    rx: oneshot::Receiver<()>,
+}
+
+impl Drop for PendingGrant {
+    fn drop(&mut self) {
+        if self.rx.try_recv().is_ok() {
+            self.ctrl.release();
+        }
+    }
 }
 
 impl ConfigController {
     fn new(concurrent_limit: u32, rate_limit: u32) -> Self {
         ConfigController {
-            semaphore: RwLock::new(Arc::new(Semaphore::new(concurrent_limit as usize))),
-            rate_limiter: Mutex::new(TokenBucket::new(rate_limit)),
+            state: StdMutex::new(ControllerState {
+                max_limit: concurrent_limit.max(1) as usize,
+                limit: concurrent_limit.max(1) as usize,
+                success_streak: 0,
+                in_use: 0,
+                paused_until: None,
+                pause_reason: String::new(),
+                rate_limit,
+                bucket: TokenBucket::new(rate_limit),
+                tpm_limit: 0,
+                token_bucket: TokenBucket::new(0),
+                queues: [VecDeque::new(), VecDeque::new()],
+                timer_armed: false,
+                counters: Counters::default(),
+                breaker: CircuitBreaker::default(),
+            }),
         }
     }
 
-    /// Acquire rate-limit + semaphore slot, polling every 100ms while checking abort.
+    fn lock(&self) -> std::sync::MutexGuard<'_, ControllerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Hand free slots to waiters, interactive first, as far as the rate limiter
+    /// allows. When it runs dry a timer calls back once a token is due.
+    fn dispatch(self: &Arc<Self>, state: &mut ControllerState) {
+        if let Some(until) = state.paused_until {
+            let now = Instant::now();
+            if until > now {
+                if state.queues.iter().any(|q| !q.is_empty()) {
+                    self.arm_timer(state, until - now);
+                }
+                return;
+            }
+            state.paused_until = None;
+        }
+        while state.in_use < state.limit {
+            let Some(queue) = state.queues.iter_mut().find(|q| !q.is_empty()) else {
+                return;
+            };
+            // Drop waiters that gave up before consuming a rate token
+            if queue.front().is_some_and(|w| w.grant.is_closed()) {
+                queue.pop_front();
+                continue;
+            }
+            if let Err(wait_ms) = state.bucket.try_acquire() {
+                self.arm_timer(state, Duration::from_millis(wait_ms));
+                return;
+            }
+            let Some(waiter) = state.queues.iter_mut().find_map(|q| q.pop_front()) else {
+                return;
+            };
+            if waiter.grant.send(()).is_ok() {
+                state.in_use += 1;
+            } else {
+                state.bucket.refund();
+            }
+        }
+    }
+
+    /// Schedule a `dispatch` after `wait`, unless one is already pending.
+    fn arm_timer(self: &Arc<Self>, state: &mut ControllerState, wait: Duration) {
+        if state.timer_armed {
+            return;
+        }
+        state.timer_armed = true;
+        let ctrl = Arc::clone(self);
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(wait).await;
+            let mut state = ctrl.lock();
+            state.timer_armed = false;
+            ctrl.dispatch(&mut state);
+        });
+    }
+
+    fn release(self: &Arc<Self>) {
+        let mut state = self.lock();
+        state.in_use = state.in_use.saturating_sub(1);
+        self.dispatch(&mut state);
+    }
+
+    /// Wait for a slot in FIFO order within `priority`. Fails with "已取消" once
+    /// `abort_flag` is set and the waiter is purged by `purge_cancelled`.
     async fn acquire(
-        &self,
+        self: &Arc<Self>,
+        priority: Priority,
         abort_flag: &Arc<AtomicBool>,
-    ) -> Result<OwnedSemaphorePermit, String> {
-        // 1. Rate limit
-        loop {
+    ) -> Result<PoolPermit, String> {
+        let queued_at = Instant::now();
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut state = self.lock();
+            // Checked under the lock so a concurrent purge cannot miss this waiter
             if abort_flag.load(Ordering::Relaxed) {
                 return Err("已取消".to_string());
             }
-            let wait_ms = {
-                let mut bucket = self.rate_limiter.lock().await;
-                match bucket.try_acquire() {
-                    Ok(()) => break,
-                    Err(ms) => ms,
-                }
+            state.queues[priority as usize].push_back(Waiter {
+                grant: tx,
+                abort_flag: Arc::clone(abort_flag),
+            });
+            self.dispatch(&mut state);
+        }
+        let mut pending = PendingGrant {
+            ctrl: Arc::clone(self),
+            rx,
+        };
+        // Only a purge drops the sender without granting
+        (&mut pending.rx).await.map_err(|_| "已取消".to_string())?;
+        self.lock().counters.waits.push(queued_at.elapsed());
+        Ok(PoolPermit {
+            ctrl: Arc::clone(self),
+            priority,
+            abort_flag: Arc::clone(abort_flag),
+        })
+    }
+
+    /// Remove queued waiters whose abort flag is set; their `acquire` returns an error.
+    fn purge_cancelled(&self) {
+        let mut state = self.lock();
+        for queue in &mut state.queues {
+            queue.retain(|w| !w.abort_flag.load(Ordering::Relaxed));
+        }
+    }
+
+    /// Change the concurrency limit; permits in flight count against the new one.
+    fn update_limit(self: &Arc<Self>, concurrent_limit: u32) {
+        let mut state = self.lock();
+        state.max_limit = concurrent_limit.max(1) as usize;
+        state.limit = state.max_limit;
+        state.success_streak = 0;
+        self.dispatch(&mut state);
+    }
+
+    /// Additive increase: one more slot after `limit` successful requests in a row.
+    fn on_success(self: &Arc<Self>) {
+        let mut state = self.lock();
+        if state.limit >= state.max_limit {
+            return;
+        }
+        state.success_streak += 1;
+        if state.success_streak >= state.limit {
+            state.limit += 1;
+            state.success_streak = 0;
+            self.dispatch(&mut state);
+        }
+    }
+
+    /// Pause the config for `pause`. With `backoff` the concurrency is halved, once
+    /// per pause, so a burst of 429s from requests already in flight counts as one.
+    /// Returns the resulting concurrency limit.
+    fn on_throttled(&self, pause: Duration, backoff: bool, reason: &str) -> usize {
+        let mut state = self.lock();
+        let now = Instant::now();
+        let paused = state.paused_until.is_some_and(|until| until > now);
+        if backoff && !paused {
+            state.limit = (state.limit / 2).max(1);
+        }
+        state.success_streak = 0;
+        let until = now + pause;
+        if state.paused_until.is_none_or(|t| t < until) {
+            state.paused_until = Some(until);
+            state.pause_reason = reason.to_string();
+        }
+        state.limit
+    }
+
+    /// Time left until a key taken out of rotation (`KEY_DISABLE_REASONS`) returns.
+    fn disabled_remaining(&self) -> Option<Duration> {
+        let state = self.lock();
+        if !KEY_DISABLE_REASONS.contains(&state.pause_reason.as_str()) {
+            return None;
+        }
+        state.paused_until?.checked_duration_since(Instant::now())
+    }
+
+    /// Time left until the pause ends, if the config is paused.
+    fn pause_remaining(&self) -> Option<Duration> {
+        let until = self.lock().paused_until?;
+        until.checked_duration_since(Instant::now())
+    }
+
+    /// Replace rate limiter.
+    fn update_rate_limit(self: &Arc<Self>, rate_per_min: u32) {
+        let mut state = self.lock();
+        state.rate_limit = rate_per_min;
+        state.bucket = TokenBucket::new(rate_per_min);
+        self.dispatch(&mut state);
+    }
+
+    /// Apply limits that differ from the current ones, keeping the state of the rest.
+    fn reconfigure(self: &Arc<Self>, concurrent_limit: u32, rate_limit: u32, tpm_limit: u32) {
+        let (max_limit, current_rate) = {
+            let state = self.lock();
+            (state.max_limit, state.rate_limit)
+        };
+        if max_limit != concurrent_limit.max(1) as usize {
+            self.update_limit(concurrent_limit);
+        }
+        if current_rate != rate_limit {
+            self.update_rate_limit(rate_limit);
+        }
+        self.update_tpm_limit(tpm_limit);
+    }
+
+    /// Count a response towards the stats.
+    fn count(&self, status: StatusCode, latency: Duration) {
+        let mut state = self.lock();
+        let counters = &mut state.counters;
+        counters.latencies.push(latency);
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            counters.throttles += 1;
+        } else if status.is_success() {
+            counters.successes += 1;
+        } else {
+            counters.errors += 1;
+        }
+    }
+
+    /// Ordering key for picking the least loaded key: unpaused first, then by
+    /// used and queued slots relative to the limit.
+    fn load(&self) -> (Duration, usize) {
+        let state = self.lock();
+        let paused = state
+            .paused_until
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+            .unwrap_or_default();
+        let queued: usize = state.queues.iter().map(VecDeque::len).sum();
+        (paused, (state.in_use + queued) * 1000 / state.limit.max(1))
+    }
+
+    fn snapshot(&self, config_id: &str, api_key: Option<String>) -> AiPoolStats {
+        let mut state = self.lock();
+        let paused_ms = state
+            .paused_until
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+            .map_or(0, |d| d.as_millis() as u64);
+        AiPoolStats {
+            config_id: config_id.to_string(),
+            api_key,
+            pause_reason: (paused_ms > 0).then(|| state.pause_reason.clone()),
+            in_flight: state.in_use,
+            queued: state.queues.iter().map(VecDeque::len).sum(),
+            concurrent_limit: state.limit,
+            max_concurrent_limit: state.max_limit,
+            paused_ms,
+            avg_wait_ms: state.counters.waits.avg(),
+            p95_wait_ms: state.counters.waits.p95(),
+            avg_latency_ms: state.counters.latencies.avg(),
+            p95_latency_ms: state.counters.latencies.p95(),
+            successes: state.counters.successes,
+            errors: state.counters.errors,
+            throttles: state.counters.throttles,
+            request_tokens: state.bucket.available(),
+            tpm_tokens: state.token_bucket.available(),
+            circuit: state.breaker.state(),
+        }
+    }
+
+    /// Replace the token budget, unless it is unchanged.
+    fn update_tpm_limit(&self, tpm_limit: u32) {
+        let mut state = self.lock();
+        if state.tpm_limit != tpm_limit {
+            state.tpm_limit = tpm_limit;
+            state.token_bucket = TokenBucket::new(tpm_limit);
+        }
+    }
+}
+
+// ── Throttling feedback ──────────────────────────────────────────────────────
+
+/// Start of the error of a request that won't wait out a long pause.
+pub const PAUSED_PREFIX: &str = "接口限流暂停中";
+/// Pause after a 429 that says nothing about when to come back.
+const DEFAULT_THROTTLE_PAUSE: Duration = Duration::from_secs(5);
+/// Longest pause taken from rate-limit headers; providers asking for more (or for
+/// nonsense like "inf") are retried after this.
+const MAX_THROTTLE_PAUSE: Duration = Duration::from_secs(900);
+/// Longest pause a request waits out before sending. A longer one fails the request
+/// so the caller can hand it to another config instead of stalling.
+const MAX_PAUSE_WAIT: Duration = Duration::from_secs(120);
+/// How often a request waiting out a pause checks whether it was cancelled.
+const PAUSE_POLL: Duration = Duration::from_millis(500);
+/// Throttling events kept for diagnostics.
+const THROTTLE_EVENT_CAP: usize = 200;
+
+/// A time the provider throttled a config, or reported its quota as used up.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThrottleEvent {
+    pub config_id: String,
+    /// The key concerned (masked), for multi-key configs.
+    pub api_key: Option<String>,
+    pub time: String,
+    pub status: u16,
+    /// "429", "requests" or "tokens" (the exhausted `x-ratelimit-*` quota), or
+    /// "auth"/"quota" when a key was disabled.
+    pub reason: String,
+    pub pause_ms: u64,
+    /// Concurrency limit after the event.
+    pub concurrent_limit: usize,
+}
+
+/// `secs` as a pause of at most `MAX_THROTTLE_PAUSE`; `None` if negative or NaN.
+fn pause_from_secs(secs: f64) -> Option<Duration> {
+    if secs.is_nan() || secs < 0.0 {
+        return None;
+    }
+    Duration::try_from_secs_f64(secs.min(MAX_THROTTLE_PAUSE.as_secs_f64())).ok()
+}
+
+/// Parse a duration like "20ms", "1.5s", "6m0s" or "1h2m3s" (OpenAI reset headers);
+/// a bare number counts as seconds. At most `MAX_THROTTLE_PAUSE`.
+fn parse_reset(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return pause_from_secs(secs);
+    }
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest.len()
+            - rest
+                .trim_start_matches(|c: char| c.is_ascii_digit() || c == '.')
+                .len();
+        let number: f64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit = rest.len()
+            - rest
+                .trim_start_matches(|c: char| c.is_ascii_alphabetic())
+                .len();
+        total += number
+            * match &rest[..unit] {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return None,
             };
-            // Sleep in 100ms chunks so we can check abort
-            let chunks = (wait_ms / 100).max(1);
-            for _ in 0..chunks {
-                if abort_flag.load(Ordering::Relaxed) {
-                    return Err("已取消".to_string());
-                }
-                tokio::time::sleep(Duration::from_millis(100)).await;
+        rest = &rest[unit..];
+    }
+    pause_from_secs(total)
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name)?.to_str().ok()
+}
+
+/// Wait requested by `retry-after-ms` or `retry-after` (seconds or an HTTP date),
+/// at most `MAX_THROTTLE_PAUSE`.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = header(headers, "retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return pause_from_secs(ms.max(0.0) / 1000.0);
+    }
+    let value = header(headers, "retry-after")?;
+    parse_reset(value).or_else(|| {
+        let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+        let wait = (at.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()?;
+        Some(wait.min(MAX_THROTTLE_PAUSE))
+    })
+}
+
+/// Handle through which requests of one config report how the provider answered.
+/// 429s and exhausted quotas pause the whole config and shrink its concurrency.
+/// A config with several keys hands out a key per request instead, and throttling
+/// only affects the key that was throttled.
+#[derive(Clone)]
+pub struct RateFeedback {
+    id: String,
+    ctrl: Arc<ConfigController>,
+    events: Arc<StdMutex<VecDeque<ThrottleEvent>>>,
+    /// Masked key of a key-level handle.
+    key_label: Option<String>,
+    /// Keys of a multi-key config; empty for a single key.
+    keys: Arc<Vec<KeySlot>>,
+    cursor: Arc<AtomicUsize>,
+}
+
+impl RateFeedback {
+    /// Pick the key for the next request and wait for one of its slots, queued with
+    /// the priority and abort flag of the config slot `permit` the caller holds. With
+    /// a single key this only waits out a pause of the config and uses `default_key`.
+    /// Fails fast while the config's circuit is open or every key is disabled.
+    pub async fn lease(&self, default_key: &str, permit: &PoolPermit) -> Result<KeyLease, String> {
+        self.ctrl.lock().breaker.allow()?;
+        if self.keys.is_empty() {
+            self.wait_if_paused(&permit.abort_flag).await?;
+            return Ok(KeyLease {
+                api_key: default_key.to_string(),
+                feedback: self.clone(),
+                config: None,
+                _permit: None,
+            });
+        }
+        // Waiting out a key's disable pause would stall the request for minutes
+        let disabled: Option<Vec<Duration>> =
+            self.keys.iter().map(|slot| slot.feedback.ctrl.disabled_remaining()).collect();
+        if let Some(soonest) = disabled.and_then(|d| d.into_iter().min()) {
+            return Err(format!(
+                "所有 API Key 均因鉴权失败或额度用尽暂停使用，约 {} 分钟后恢复",
+                soonest.as_secs().div_ceil(60)
+            ));
+        }
+        // Least loaded key; the rotating start makes ties round-robin
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let n = self.keys.len();
+        let slot = (0..n)
+            .map(|i| &self.keys[(start + i) % n])
+            .min_by_key(|slot| slot.feedback.ctrl.load())
+            .expect("multi-key config has keys");
+        let key_permit =
+            slot.feedback.ctrl.acquire(permit.priority, &permit.abort_flag).await?;
+        slot.feedback.wait_if_paused(&permit.abort_flag).await?;
+        Ok(KeyLease {
+            api_key: slot.api_key.clone(),
+            feedback: slot.feedback.clone(),
+            config: Some(Arc::clone(&self.ctrl)),
+            _permit: Some(key_permit),
+        })
+    }
+
+    /// The error of the config's open circuit, without taking the probe slot.
+    pub fn circuit_open(&self) -> Option<String> {
+        self.ctrl.lock().breaker.open_error()
+    }
+
+    /// Sleep while the config is paused. Call right before sending a request, so
+    /// retries of requests already holding a slot respect the pause too. Fails when
+    /// `abort_flag` is set meanwhile, or right away if the pause is longer than
+    /// `MAX_PAUSE_WAIT`.
+    async fn wait_if_paused(&self, abort_flag: &AtomicBool) -> Result<(), String> {
+        while let Some(wait) = self.ctrl.pause_remaining() {
+            if abort_flag.load(Ordering::Relaxed) {
+                return Err("已取消".to_string());
+            }
+            if wait > MAX_PAUSE_WAIT {
+                let reason = self.ctrl.lock().pause_reason.clone();
+                return Err(format!(
+                    "{PAUSED_PREFIX}（{reason}），约 {} 秒后恢复",
+                    wait.as_secs()
+                ));
             }
+            tokio::time::sleep(wait.min(PAUSE_POLL)).await;
         }
+        Ok(())
+    }
 
-        // 2. Semaphore — poll with 10ms interval, 300s timeout
-        let deadline = Instant::now() + Duration::from_secs(300);
+    /// Reserve `estimate` tokens of the per-minute budget, waiting while the budget
+    /// is overdrawn. Settle the reservation with the actual usage afterwards. Fails,
+    /// returning the tokens, when `abort_flag` is set during the wait.
+    pub async fn reserve_tokens(
+        &self,
+        estimate: usize,
+        abort_flag: &AtomicBool,
+    ) -> Result<TokenReservation, String> {
+        let wait = self.ctrl.lock().token_bucket.reserve(estimate as f64);
+        let reservation = TokenReservation {
+            ctrl: Arc::clone(&self.ctrl),
+            reserved: estimate,
+        };
+        let until = tokio::time::Instant::now() + wait;
         loop {
             if abort_flag.load(Ordering::Relaxed) {
+                reservation.settle(0);
                 return Err("已取消".to_string());
             }
-            if Instant::now() >= deadline {
-                return Err("等待并发槽超时（300s）".to_string());
+            let left = until.saturating_duration_since(tokio::time::Instant::now());
+            if left.is_zero() {
+                return Ok(reservation);
             }
-            let sem = {
-                let guard = self.semaphore.read().await;
-                Arc::clone(&*guard)
-            };
-            match sem.try_acquire_owned() {
-                Ok(permit) => {
-                    return Ok(permit);
-                }
-                Err(_) => {
-                    tokio::time::sleep(Duration::from_millis(10)).await;
-                }
+            tokio::time::sleep(left.min(PAUSE_POLL)).await;
+        }
+    }
+
+    /// Feed a response's status, rate-limit headers and latency back into the pool.
+    pub fn observe(&self, status: StatusCode, headers: &HeaderMap, latency: Duration) {
+        self.ctrl.count(status, latency);
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let pause = retry_after(headers).unwrap_or(DEFAULT_THROTTLE_PAUSE);
+            let limit = self.ctrl.on_throttled(pause, true, "429");
+            self.record(status, "429", pause, limit);
+            return;
+        }
+        if !status.is_success() {
+            return;
+        }
+        self.ctrl.on_success();
+        for quota in ["requests", "tokens"] {
+            let remaining = header(headers, &format!("x-ratelimit-remaining-{quota}"))
+                .and_then(|v| v.trim().parse::<u64>().ok());
+            if remaining != Some(0) {
+                continue;
+            }
+            if let Some(pause) =
+                header(headers, &format!("x-ratelimit-reset-{quota}")).and_then(parse_reset)
+            {
+                let limit = self.ctrl.on_throttled(pause, false, quota);
+                self.record(status, quota, pause, limit);
             }
         }
     }
 
-    /// Replace semaphore with a new one (in-flight permits keep the old semaphore alive).
-    async fn update_semaphore(&self, concurrent_limit: u32) {
-        let mut guard = self.semaphore.write().await;
-        *guard = Arc::new(Semaphore::new(concurrent_limit as usize));
+    /// Count a request that got no response at all (connection error, timeout).
+    pub fn observe_failure(&self) {
+        self.ctrl.lock().counters.errors += 1;
     }
 
-    /// Replace rate limiter.
-    async fn update_rate_limit(&self, rate_per_min: u32) {
-        let mut guard = self.rate_limiter.lock().await;
-        *guard = TokenBucket::new(rate_per_min);
+    fn record(&self, status: StatusCode, reason: &str, pause: Duration, concurrent_limit: usize) {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        if events.len() >= THROTTLE_EVENT_CAP {
+            events.pop_front();
+        }
+        events.push_back(ThrottleEvent {
+            config_id: self.id.clone(),
+            api_key: self.key_label.clone(),
+            time: chrono::Local::now()
+                .format("%Y-%m-%dT%H:%M:%S%.3f")
+                .to_string(),
+            status: status.as_u16(),
+            reason: reason.to_string(),
+            pause_ms: pause.as_millis() as u64,
+            concurrent_limit,
+        });
+    }
+}
+
+/// How long a key stays out of rotation after an auth failure or exhausted quota.
+const KEY_DISABLE_PAUSE: Duration = Duration::from_secs(600);
+/// Pause reasons of keys taken out of rotation, as set by `observe_rejection`.
+const KEY_DISABLE_REASONS: &[&str] = &["auth", "quota"];
+
+/// One API key of a config with its own limits.
+#[derive(Clone)]
+pub struct ApiKeySpec {
+    pub api_key: String,
+    pub concurrent_limit: u32,
+    pub rate_limit: u32,
+    pub tpm_limit: u32,
+}
+
+/// All keys of `cfg`: `api_key` with the config's limits, then the extra keys,
+/// whose limits of 0 fall back to the config's.
+pub fn key_specs(cfg: &AiConfig) -> Vec<ApiKeySpec> {
+    let or_config = |own: i32, config: i32| if own > 0 { own } else { config }.max(0) as u32;
+    let primary = ApiKeySpec {
+        api_key: cfg.api_key.clone(),
+        concurrent_limit: cfg.concurrent_limit.max(1) as u32,
+        rate_limit: cfg.rate_limit.max(0) as u32,
+        tpm_limit: cfg.tpm_limit.max(0) as u32,
+    };
+    let extra = cfg.api_keys.iter().map(|k| ApiKeySpec {
+        api_key: k.api_key.clone(),
+        concurrent_limit: or_config(k.concurrent_limit, cfg.concurrent_limit).max(1),
+        rate_limit: or_config(k.rate_limit, cfg.rate_limit),
+        tpm_limit: or_config(k.tpm_limit, cfg.tpm_limit),
+    });
+    std::iter::once(primary).chain(extra).collect()
+}
+
+/// Concurrency, requests and tokens per minute of `cfg` as a whole: its own
+/// limits with one key, otherwise the sum over its keys (0 = unlimited wins).
+pub fn config_limits(cfg: &AiConfig) -> (u32, u32, u32) {
+    let keys = key_specs(cfg);
+    let sum = |limit: fn(&ApiKeySpec) -> u32| {
+        if keys.iter().any(|k| limit(k) == 0) {
+            0
+        } else {
+            keys.iter().map(limit).sum()
+        }
+    };
+    (
+        keys.iter().map(|k| k.concurrent_limit).sum(),
+        sum(|k| k.rate_limit),
+        sum(|k| k.tpm_limit),
+    )
+}
+
+/// `…` plus the last four characters, for showing a key in stats and events.
+fn mask_key(key: &str) -> String {
+    let tail: Vec<char> = key.chars().rev().take(4).collect();
+    format!("…{}", tail.into_iter().rev().collect::<String>())
+}
+
+#[derive(Clone)]
+struct KeySlot {
+    api_key: String,
+    feedback: RateFeedback,
+}
+
+/// The key a request is sent with, holding one of its slots until dropped. Reports
+/// go to the key (and count towards the config's stats) or, with a single key, to
+/// the config.
+pub struct KeyLease {
+    api_key: String,
+    feedback: RateFeedback,
+    /// Config-level controller of a multi-key config.
+    config: Option<Arc<ConfigController>>,
+    _permit: Option<PoolPermit>,
+}
+
+impl KeyLease {
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    pub async fn reserve_tokens(
+        &self,
+        estimate: usize,
+        abort_flag: &AtomicBool,
+    ) -> Result<TokenReservation, String> {
+        self.feedback.reserve_tokens(estimate, abort_flag).await
+    }
+
+    /// Controller of the config as a whole, which owns the circuit breaker.
+    fn config_ctrl(&self) -> &Arc<ConfigController> {
+        self.config.as_ref().unwrap_or(&self.feedback.ctrl)
+    }
+
+    pub fn observe(&self, status: StatusCode, headers: &HeaderMap, latency: Duration) {
+        self.feedback.observe(status, headers, latency);
+        if let Some(config) = &self.config {
+            config.count(status, latency);
+        }
+        // Any answer but a server error shows the endpoint is up
+        let mut state = self.config_ctrl().lock();
+        if status.is_server_error() {
+            state.breaker.on_failure(&format!("HTTP {status}"));
+        } else {
+            state.breaker.on_success();
+        }
+    }
+
+    pub fn observe_failure(&self, error: &str) {
+        self.feedback.observe_failure();
+        if let Some(config) = &self.config {
+            config.lock().counters.errors += 1;
+        }
+        self.config_ctrl().lock().breaker.on_failure(error);
+    }
+
+    /// Take the key out of rotation for a while if an error response says it is
+    /// invalid (401/403) or out of quota. Only keys of multi-key configs are
+    /// disabled; a single key keeps failing visibly instead.
+    pub fn observe_rejection(&self, status: StatusCode, body: &str) {
+        if self.config.is_none() {
+            return;
+        }
+        let reason = match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => "auth",
+            StatusCode::TOO_MANY_REQUESTS if body.contains("quota") => "quota",
+            _ => return,
+        };
+        let limit = self
+            .feedback
+            .ctrl
+            .on_throttled(KEY_DISABLE_PAUSE, false, reason);
+        self.feedback
+            .record(status, reason, KEY_DISABLE_PAUSE, limit);
+    }
+}
+
+/// Tokens taken from a config's per-minute budget for one request.
+pub struct TokenReservation {
+    ctrl: Arc<ConfigController>,
+    reserved: usize,
+}
+
+impl TokenReservation {
+    /// Correct the budget by the difference between estimate and `used` tokens
+    /// (the response's `usage`, or 0 when the request was rejected).
+    pub fn settle(self, used: usize) {
+        let delta = self.reserved as f64 - used as f64;
+        self.ctrl.lock().token_bucket.credit(delta);
     }
 }
 
 // ── AiPoolManager ─────────────────────────────────────────────────────────────
 
-/// Per-config timeout stored alongside client.
+/// Per-config timeout and proxy stored alongside client.
 struct CachedClient {
     client: reqwest::Client,
     timeout_secs: u64,
+    proxy: ProxyConfig,
 }
 
 pub struct AiPoolManager {
     controllers: Mutex<HashMap<String, Arc<ConfigController>>>,
+    /// Per-key controllers of multi-key configs, by (config id, key).
+    key_controllers: Mutex<HashMap<(String, String), Arc<ConfigController>>>,
     clients: Mutex<HashMap<String, CachedClient>>,
+    events: Arc<StdMutex<VecDeque<ThrottleEvent>>>,
+    pub tts_breakers: TtsBreakers,
 }
 
 impl AiPoolManager {
     pub fn new() -> Self {
         AiPoolManager {
             controllers: Mutex::new(HashMap::new()),
+            key_controllers: Mutex::new(HashMap::new()),
             clients: Mutex::new(HashMap::new()),
+            events: Arc::new(StdMutex::new(VecDeque::new())),
+            tts_breakers: TtsBreakers::default(),
+        }
+    }
+
+    /// Feedback handle for requests of config `id`, creating controller on first use.
+    /// With more than one of `keys`, each key gets a controller of its own.
+    pub async fn feedback(
+        &self,
+        id: &str,
+        concurrent_limit: u32,
+        rate_limit: u32,
+        tpm_limit: u32,
+        keys: &[ApiKeySpec],
+    ) -> RateFeedback {
+        let ctrl = self
+            .ensure_controller(id, concurrent_limit, rate_limit)
+            .await;
+        ctrl.reconfigure(concurrent_limit, rate_limit, tpm_limit);
+        let mut slots = Vec::new();
+        if keys.len() > 1 {
+            let mut map = self.key_controllers.lock().await;
+            for spec in keys {
+                let key_ctrl = map
+                    .entry((id.to_string(), spec.api_key.clone()))
+                    .or_insert_with(|| {
+                        Arc::new(ConfigController::new(
+                            spec.concurrent_limit,
+                            spec.rate_limit,
+                        ))
+                    })
+                    .clone();
+                key_ctrl.reconfigure(spec.concurrent_limit, spec.rate_limit, spec.tpm_limit);
+                slots.push(KeySlot {
+                    api_key: spec.api_key.clone(),
+                    feedback: RateFeedback {
+                        id: id.to_string(),
+                        ctrl: key_ctrl,
+                        events: Arc::clone(&self.events),
+                        key_label: Some(mask_key(&spec.api_key)),
+                        keys: Arc::new(Vec::new()),
+                        cursor: Arc::new(AtomicUsize::new(0)),
+                    },
+                });
+            }
+        }
+        RateFeedback {
+            id: id.to_string(),
+            ctrl,
+            events: Arc::clone(&self.events),
+            key_label: None,
+            keys: Arc::new(slots),
+            cursor: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Runtime stats of every config used since startup, each followed by its
+    /// keys if it has several.
+    pub async fn stats(&self) -> Vec<AiPoolStats> {
+        let mut stats: Vec<AiPoolStats> = {
+            let map = self.controllers.lock().await;
+            map.iter()
+                .map(|(id, ctrl)| ctrl.snapshot(id, None))
+                .collect()
+        };
+        let keys = self.key_controllers.lock().await;
+        stats.extend(
+            keys.iter()
+                .map(|((id, key), ctrl)| ctrl.snapshot(id, Some(mask_key(key)))),
+        );
+        stats.sort_by(|a, b| (&a.config_id, &a.api_key).cmp(&(&b.config_id, &b.api_key)));
+        stats
+    }
+
+    /// Recent throttling events, oldest first.
+    pub fn throttle_events(&self) -> Vec<ThrottleEvent> {
+        let events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        events.iter().cloned().collect()
+    }
+
+    /// Call after setting an abort flag: requests still queued under it give up
+    /// instead of waiting for a slot of their config or key.
+    pub async fn wake_cancelled(&self) {
+        for ctrl in self.controllers.lock().await.values() {
+            ctrl.purge_cancelled();
+        }
+        for ctrl in self.key_controllers.lock().await.values() {
+            ctrl.purge_cancelled();
         }
     }
 
@@ -163,34 +1217,44 @@ impl AiPoolManager {
     }
 
     /// Get (or build) the cached HTTP client for this config id.
-    /// Recreates if timeout changed.
-    pub async fn get_or_create_client(&self, id: &str, timeout_secs: u64) -> reqwest::Client {
+    /// Recreates if timeout or proxy changed.
+    pub async fn get_or_create_client(
+        &self,
+        id: &str,
+        timeout_secs: u64,
+        proxy: &ProxyConfig,
+    ) -> Result<reqwest::Client, String> {
         let mut map = self.clients.lock().await;
         if let Some(cached) = map.get(id) {
-            if cached.timeout_secs == timeout_secs {
-                return cached.client.clone();
+            if cached.timeout_secs == timeout_secs && &cached.proxy == proxy {
+                return Ok(cached.client.clone());
             }
         }
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(timeout_secs))
-            .build()
-            .unwrap_or_default();
+        let client = http::client(proxy, Duration::from_secs(timeout_secs))?;
         map.insert(
             id.to_string(),
             CachedClient {
                 client: client.clone(),
                 timeout_secs,
+                proxy: proxy.clone(),
             },
         );
-        client
+        Ok(client)
     }
 
-    /// Update controller limits in-place (running tasks keep old semaphore).
-    pub async fn update_controller(&self, id: &str, concurrent_limit: u32, rate_limit: u32) {
+    /// Update controller limits in-place (running requests keep their slots).
+    pub async fn update_controller(
+        &self,
+        id: &str,
+        concurrent_limit: u32,
+        rate_limit: u32,
+        tpm_limit: u32,
+    ) {
         let map = self.controllers.lock().await;
         if let Some(ctrl) = map.get(id) {
-            ctrl.update_semaphore(concurrent_limit).await;
-            ctrl.update_rate_limit(rate_limit).await;
+            ctrl.update_limit(concurrent_limit);
+            ctrl.update_rate_limit(rate_limit);
+            ctrl.update_tpm_limit(tpm_limit);
         }
     }
 
@@ -203,18 +1267,85 @@ impl AiPoolManager {
     /// Remove controller and client for a deleted config.
     pub async fn remove(&self, id: &str) {
         self.controllers.lock().await.remove(id);
+        self.key_controllers
+            .lock()
+            .await
+            .retain(|(config_id, _), _| config_id != id);
         self.clients.lock().await.remove(id);
     }
 
-    /// Acquire semaphore permit for `id`, creating controller on first use.
+    /// Acquire a concurrency slot for `id`, creating controller on first use.
     pub async fn acquire(
         &self,
         id: &str,
         concurrent_limit: u32,
         rate_limit: u32,
+        priority: Priority,
         abort_flag: &Arc<AtomicBool>,
-    ) -> Result<OwnedSemaphorePermit, String> {
-        let ctrl = self.ensure_controller(id, concurrent_limit, rate_limit).await;
-        ctrl.acquire(abort_flag).await
+    ) -> Result<PoolPermit, String> {
+        let ctrl = self
+            .ensure_controller(id, concurrent_limit, rate_limit)
+            .await;
+        ctrl.acquire(priority, abort_flag).await
+    }
+}
+
+/// Interval of the `ai_pool:stats` event.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Emit `ai_pool:stats` every second while any config has requests in flight or
+/// queued, plus once more when the pool goes idle.
+pub async fn emit_stats(app: tauri::AppHandle) {
+    use tauri::{Emitter, Manager};
+    let mut was_busy = false;
+    loop {
+        tokio::time::sleep(STATS_INTERVAL).await;
+        let stats = app.state::<AiPoolManager>().stats().await;
+        let busy = stats.iter().any(|s| s.in_flight > 0 || s.queued > 0);
+        if busy || was_busy {
+            let _ = app.emit("ai_pool:stats", stats);
+        }
+        was_busy = busy;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn parse_reset_reads_provider_durations() {
+        assert_eq!(parse_reset("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_reset("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_reset("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_reset(" 12 "), Some(Duration::from_secs(12)));
+        assert_eq!(parse_reset("-3"), None);
+        assert_eq!(parse_reset("soon"), None);
+    }
+
+    #[test]
+    fn parse_reset_clamps_instead_of_panicking() {
+        for value in ["inf", "1e400", "NaN", "99999999999999999999h"] {
+            assert!(parse_reset(value).is_none_or(|d| d <= MAX_THROTTLE_PAUSE), "{value}");
+        }
+        assert_eq!(parse_reset("inf"), Some(MAX_THROTTLE_PAUSE));
+        assert_eq!(parse_reset("NaN"), None);
+        assert_eq!(parse_reset("2h"), Some(MAX_THROTTLE_PAUSE));
+    }
+
+    #[test]
+    fn retry_after_reads_headers_and_clamps() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+        headers.insert("retry-after-ms", HeaderValue::from_static("1e400"));
+        assert_eq!(retry_after(&headers), Some(MAX_THROTTLE_PAUSE));
+        headers.insert("retry-after-ms", HeaderValue::from_static("250"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_millis(250)));
+        let mut dated = HeaderMap::new();
+        dated.insert("retry-after", HeaderValue::from_static("Tue, 31 Dec 2999 23:59:59 GMT"));
+        assert_eq!(retry_after(&dated), Some(MAX_THROTTLE_PAUSE));
     }
 }
@@ -1,16 +1,31 @@
-use crate::ai_pool::AiPoolManager;
+use crate::ai_pool::{self, AiPoolManager, AiPoolStats, ThrottleEvent};
 use crate::db::connection::DbState;
 use crate::db::queries::{
-    create_ai_config, delete_ai_config, get_all_ai_configs, set_default_ai_config,
-    update_ai_config, AiConfig,
+    create_ai_config, delete_ai_config, get_all_ai_configs, set_ai_config_capabilities,
+    set_default_ai_config, update_ai_config, AiConfig,
 };
+use crate::http::{self, ProxyConfig};
+use crate::llm_cache::{LlmCacheInfo, LlmCacheState};
+use crate::model_probe::{self, ModelCapabilities, ModelInfo};
+use crate::secrets;
+use rusqlite::Connection;
+use std::time::Duration;
 use serde_json::json;
 use tauri::State;
 
+/// All configs, with their API keys masked.
 #[tauri::command]
 pub async fn cmd_get_ai_configs(db: State<'_, DbState>) -> Result<Vec<AiConfig>, String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    get_all_ai_configs(&conn).map_err(|e| e.to_string())
+    let configs = get_all_ai_configs(&conn).map_err(|e| e.to_string())?;
+    Ok(configs.into_iter().map(masked).collect())
+}
+
+/// Config `id` with its API keys in the clear, for showing them in the form.
+#[tauri::command]
+pub async fn cmd_reveal_ai_config(db: State<'_, DbState>, id: String) -> Result<AiConfig, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    stored_config(&conn, &id)?.ok_or_else(|| format!("AI 配置 {id} 不存在"))
 }
 
 #[tauri::command]
@@ -20,6 +35,7 @@ pub async fn cmd_create_ai_config(
 ) -> Result<String, String> {
     let id = config.id.clone();
     let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let config = unmasked(&conn, config)?;
     create_ai_config(&conn, &config).map_err(|e| e.to_string())?;
     Ok(id)
 }
@@ -30,17 +46,15 @@ pub async fn cmd_update_ai_config(
     pool: State<'_, AiPoolManager>,
     config: AiConfig,
 ) -> Result<(), String> {
-    {
+    let config = {
         let conn = db.0.lock().map_err(|e| e.to_string())?;
+        let config = unmasked(&conn, config)?;
         update_ai_config(&conn, &config).map_err(|e| e.to_string())?;
-    }
+        config
+    };
     // Keep pool in sync — update concurrency/rate limits and invalidate cached client
-    pool.update_controller(
-        &config.id,
-        config.concurrent_limit as u32,
-        config.rate_limit as u32,
-    )
-    .await;
+    let (concurrent_limit, rate_limit, tpm_limit) = ai_pool::config_limits(&config);
+    pool.update_controller(&config.id, concurrent_limit, rate_limit, tpm_limit).await;
     pool.update_timeout(&config.id).await;
     Ok(())
 }
@@ -68,16 +82,47 @@ pub async fn cmd_set_default_ai_config(
     set_default_ai_config(&conn, &id).map_err(|e| e.to_string())
 }
 
+/// In-flight and queued requests, wait/latency figures, outcome counts and rate
+/// limiter levels per config. The same data is emitted as `ai_pool:stats` while busy.
+#[tauri::command]
+pub async fn cmd_get_ai_pool_stats(
+    pool: State<'_, AiPoolManager>,
+) -> Result<Vec<AiPoolStats>, String> {
+    Ok(pool.stats().await)
+}
+
+/// Recent 429s and exhausted quotas across all configs, oldest first.
+#[tauri::command]
+pub async fn cmd_get_throttle_events(
+    pool: State<'_, AiPoolManager>,
+) -> Result<Vec<ThrottleEvent>, String> {
+    Ok(pool.throttle_events())
+}
+
+/// Entry count and size of the LLM response cache.
+#[tauri::command]
+pub async fn cmd_get_llm_cache_info(
+    cache: State<'_, LlmCacheState>,
+) -> Result<LlmCacheInfo, String> {
+    Ok(cache.0.info())
+}
+
+#[tauri::command]
+pub async fn cmd_clear_llm_cache(cache: State<'_, LlmCacheState>) -> Result<(), String> {
+    cache.0.clear()
+}
+
 #[tauri::command]
 pub async fn cmd_test_ai_connection(
+    db: State<'_, DbState>,
     base_url: String,
     api_key: String,
     model: String,
+    proxy: Option<ProxyConfig>,
+    config_id: Option<String>,
 ) -> Result<String, String> {
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(15))
-        .build()
-        .map_err(|e| e.to_string())?;
+    let api_key = form_api_key(&db, api_key, config_id.as_deref())?;
+    let client = form_client(&db, proxy, config_id.as_deref(), Duration::from_secs(15))?;
 
     let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
     let body = json!({
@@ -105,3 +150,110 @@ pub async fn cmd_test_ai_connection(
         Err(format!("HTTP {}: {}", status.as_u16(), text))
     }
 }
+
+/// The models the endpoint offers, from its `/models` listing.
+#[tauri::command]
+pub async fn cmd_list_ai_models(
+    db: State<'_, DbState>,
+    base_url: String,
+    api_key: String,
+    proxy: Option<ProxyConfig>,
+    config_id: Option<String>,
+) -> Result<Vec<ModelInfo>, String> {
+    let api_key = form_api_key(&db, api_key, config_id.as_deref())?;
+    let client = form_client(&db, proxy, config_id.as_deref(), Duration::from_secs(30))?;
+    model_probe::list_models(&client, &base_url, &api_key).await
+}
+
+/// Probe `model` for JSON mode, streaming, context hints and speed. With
+/// `config_id` the result is also stored on that config right away.
+#[tauri::command]
+pub async fn cmd_probe_ai_model(
+    db: State<'_, DbState>,
+    base_url: String,
+    api_key: String,
+    model: String,
+    proxy: Option<ProxyConfig>,
+    config_id: Option<String>,
+) -> Result<ModelCapabilities, String> {
+    let api_key = form_api_key(&db, api_key, config_id.as_deref())?;
+    let client = form_client(&db, proxy, config_id.as_deref(), Duration::from_secs(60))?;
+    let capabilities = model_probe::probe(&client, &base_url, &api_key, &model).await?;
+    if let Some(id) = config_id.filter(|id| !id.is_empty()) {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        set_ai_config_capabilities(&conn, &id, &capabilities).map_err(|e| e.to_string())?;
+    }
+    Ok(capabilities)
+}
+
+/// A client for the settings form's endpoint, through its proxy override; a masked
+/// password is the one stored for `config_id`.
+fn form_client(
+    db: &DbState,
+    proxy: Option<ProxyConfig>,
+    config_id: Option<&str>,
+    timeout: Duration,
+) -> Result<reqwest::Client, String> {
+    let proxy = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        let stored = match config_id {
+            Some(id) => stored_config(&conn, id)?.map(|c| c.proxy).unwrap_or_default(),
+            None => ProxyConfig::default(),
+        };
+        proxy.unwrap_or_default().unmasked(&stored).resolve(&conn)?
+    };
+    http::client(&proxy, timeout)
+}
+
+/// `config` with its API keys and proxy password masked for the frontend.
+fn masked(mut config: AiConfig) -> AiConfig {
+    config.api_key = secrets::mask(&config.api_key);
+    for key in &mut config.api_keys {
+        key.api_key = secrets::mask(&key.api_key);
+    }
+    config.proxy = config.proxy.masked();
+    config
+}
+
+fn stored_config(conn: &Connection, id: &str) -> Result<Option<AiConfig>, String> {
+    let configs = get_all_ai_configs(conn).map_err(|e| e.to_string())?;
+    Ok(configs.into_iter().find(|c| c.id == id))
+}
+
+/// `config` as submitted by the form, with keys left masked swapped back for the
+/// stored keys they stand for: the main key for the main key, extra keys by their
+/// stored position; likewise the proxy password. A new config has nothing stored,
+/// so masks there yield "".
+fn unmasked(conn: &Connection, mut config: AiConfig) -> Result<AiConfig, String> {
+    let stored = stored_config(conn, &config.id)?;
+    let stored_proxy = stored.as_ref().map(|c| c.proxy.clone()).unwrap_or_default();
+    config.proxy = config.proxy.unmasked(&stored_proxy);
+    let stored_main = stored.as_ref().map(|c| c.api_key.as_str());
+    config.api_key = secrets::unmask(&config.api_key, stored_main);
+    for key in &mut config.api_keys {
+        let stored_key = stored.as_ref().and_then(|c| {
+            c.api_keys.iter().find(|k| key.position.is_some() && k.position == key.position)
+        });
+        key.api_key = secrets::unmask(&key.api_key, stored_key.map(|k| k.api_key.as_str()));
+    }
+    Ok(config)
+}
+
+/// The form's main API key, or the stored main key of `config_id` it masks.
+fn form_api_key(
+    db: &DbState,
+    api_key: String,
+    config_id: Option<&str>,
+) -> Result<String, String> {
+    match config_id.filter(|_| secrets::is_masked(&api_key)) {
+        Some(id) => {
+            let conn = db.0.lock().map_err(|e| e.to_string())?;
+            let stored = stored_config(&conn, id)?;
+            if let Some(cfg) = &stored {
+                cfg.check_keys()?;
+            }
+            Ok(secrets::unmask(&api_key, stored.as_ref().map(|c| c.api_key.as_str())))
+        }
+        None => Ok(api_key),
+    }
+}
@@ -0,0 +1,206 @@
+use crate::db::connection::DbState;
+use crate::db::queries::{self, CorrectionReview};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::State;
+
+const DECISIONS: &[&str] = &["pending", "accepted", "rejected"];
+
+/// A run of characters that is unchanged, added or removed by the correction.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSegment {
+    /// "equal", "insert" or "delete".
+    op: &'static str,
+    text: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CorrectionDiff {
+    index: i32,
+    original: String,
+    corrected: String,
+    decision: String,
+    segments: Vec<DiffSegment>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CorrectionDecision {
+    index: i32,
+    decision: String,
+}
+
+/// Character-level diff of `old` → `new` from their longest common subsequence.
+fn diff_chars(old: &str, new: &str) -> Vec<DiffSegment> {
+    let a: Vec<char> = old.chars().collect();
+    let b: Vec<char> = new.chars().collect();
+    // lcs[i][j]: LCS length of a[i..] and b[j..]
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut segments: Vec<DiffSegment> = Vec::new();
+    let mut push = |op: &'static str, c: char| match segments.last_mut() {
+        Some(last) if last.op == op => last.text.push(c),
+        _ => segments.push(DiffSegment { op, text: c.to_string() }),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            push("equal", a[i]);
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            push("insert", b[j]);
+            j += 1;
+        } else {
+            push("delete", a[i]);
+            i += 1;
+        }
+    }
+    segments
+}
+
+/// Record what the correction phase changed and apply the reviewer's decisions.
+/// `corrected` holds the phase output; lines whose change was rejected get their
+/// original text back. A line whose original or corrected text differs from the
+/// stored one is reviewed again. Returns the number of changes still pending.
+pub(crate) fn record_corrections(
+    db: &DbState,
+    project_dir: &str,
+    originals: &HashMap<usize, String>,
+    corrected: &mut HashMap<usize, String>,
+) -> Result<usize, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let stored: HashMap<i32, CorrectionReview> = queries::get_correction_reviews(&conn, project_dir)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|r| (r.subtitle_index, r))
+        .collect();
+
+    let mut pending = 0;
+    for (idx, original) in originals {
+        let index = *idx as i32;
+        let Some(text) = corrected.get(idx) else { continue };
+        if text == original {
+            if stored.contains_key(&index) {
+                queries::delete_correction_review(&conn, project_dir, index)
+                    .map_err(|e| e.to_string())?;
+            }
+            continue;
+        }
+        let decision = match stored.get(&index) {
+            Some(r) if &r.original == original && &r.corrected == text => r.decision.clone(),
+            _ => {
+                let review = CorrectionReview {
+                    subtitle_index: index,
+                    original: original.clone(),
+                    corrected: text.clone(),
+                    decision: "pending".to_string(),
+                };
+                queries::save_correction_review(&conn, project_dir, &review)
+                    .map_err(|e| e.to_string())?;
+                review.decision
+            }
+        };
+        match decision.as_str() {
+            "rejected" => {
+                corrected.insert(*idx, original.clone());
+            }
+            "pending" => pending += 1,
+            _ => {}
+        }
+    }
+    Ok(pending)
+}
+
+/// Lines the correction phase changed, with a character diff and the reviewer's
+/// decision ("pending", "accepted" or "rejected").
+#[tauri::command]
+pub fn cmd_get_correction_diffs(
+    db: State<'_, DbState>,
+    project_dir: String,
+) -> Result<Vec<CorrectionDiff>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let reviews = queries::get_correction_reviews(&conn, &project_dir).map_err(|e| e.to_string())?;
+    Ok(reviews
+        .into_iter()
+        .map(|r| CorrectionDiff {
+            index: r.subtitle_index,
+            segments: diff_chars(&r.original, &r.corrected),
+            original: r.original,
+            corrected: r.corrected,
+            decision: r.decision,
+        })
+        .collect())
+}
+
+/// Accept or reject individual corrections. Takes effect on the next translation run.
+#[tauri::command]
+pub fn cmd_set_correction_decisions(
+    db: State<'_, DbState>,
+    project_dir: String,
+    decisions: Vec<CorrectionDecision>,
+) -> Result<(), String> {
+    if let Some(bad) = decisions.iter().find(|d| !DECISIONS.contains(&d.decision.as_str())) {
+        return Err(format!("未知的校正审核结果: {}", bad.decision));
+    }
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    for d in &decisions {
+        queries::set_correction_decision(&conn, &project_dir, Some(d.index), &d.decision)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Accept or reject every correction of the project at once.
+#[tauri::command]
+pub fn cmd_set_all_correction_decisions(
+    db: State<'_, DbState>,
+    project_dir: String,
+    decision: String,
+) -> Result<(), String> {
+    if !DECISIONS.contains(&decision.as_str()) {
+        return Err(format!("未知的校正审核结果: {decision}"));
+    }
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::set_correction_decision(&conn, &project_dir, None, &decision).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(segments: &[DiffSegment]) -> Vec<(&str, &str)> {
+        segments.iter().map(|s| (s.op, s.text.as_str())).collect()
+    }
+
+    #[test]
+    fn diff_chars_groups_runs_of_the_same_op() {
+        let segments = diff_chars("我们去公园", "我们去了公园吧");
+        assert_eq!(
+            ops(&segments),
+            [("equal", "我们去"), ("insert", "了"), ("equal", "公园"), ("insert", "吧")]
+        );
+        let segments = diff_chars("colour", "color");
+        assert_eq!(ops(&segments), [("equal", "colo"), ("delete", "u"), ("equal", "r")]);
+    }
+
+    #[test]
+    fn diff_chars_handles_replacements_and_empty_sides() {
+        let segments = diff_chars("猫", "狗");
+        assert_eq!(ops(&segments), [("insert", "狗"), ("delete", "猫")]);
+        assert_eq!(ops(&diff_chars("", "ab")), [("insert", "ab")]);
+        assert_eq!(ops(&diff_chars("ab", "")), [("delete", "ab")]);
+        assert!(diff_chars("", "").is_empty());
+    }
+}
@@ -1,8 +1,16 @@
 use crate::ai_pool::AiPoolManager;
+use crate::commands::prompt_template;
+use crate::commands::translate::{
+    build_routes, call_along_routes, open_cache, open_journal, project_template_vars,
+    resolve_chain, ChatPrompt,
+};
 use crate::db::connection::DbState;
+use crate::http::ProxyConfig;
+use crate::llm_cache::{CacheCounts, LlmCacheState};
 use crate::db::queries::{
     self, DubbingJob, DubbingStageState, DubbingTtsItem,
 };
+use crate::llm_journal::Journal;
 use crate::media::{aligner, composer, reference, separator};
 use crate::tts::TtsSynthRequest;
 use serde::{Deserialize, Serialize};
@@ -74,6 +82,9 @@ struct DubbingProgressEvent {
     stage: String,
     percent: f64,
     message: String,
+    /// LLM response cache hits and misses, for stages that call the LLM.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache: Option<CacheCounts>,
 }
 
 #[derive(Clone, Serialize)]
@@ -109,6 +120,7 @@ fn emit_progress(app: &AppHandle, stage: &str, percent: f64, message: &str) {
         stage: stage.to_string(),
         percent,
         message: message.to_string(),
+        cache: None,
     });
 }
 
@@ -157,63 +169,16 @@ fn set_stage_status(
 
 // ── AI Helpers (reuse translate.rs patterns) ──────────────────────────────────
 
-#[derive(serde::Deserialize)]
-struct ChatResponse { choices: Vec<ChatChoice> }
-#[derive(serde::Deserialize)]
-struct ChatChoice { message: ChatMessage }
-#[derive(serde::Deserialize)]
-struct ChatMessage { content: String }
-
-struct AiCfg {
-    id: String,
-    base_url: String,
-    api_key: String,
-    model: String,
-    concurrent_limit: u32,
-    request_timeout: u64,
-    rate_limit: u32,
-}
-
-async fn call_ai_json(
-    client: &reqwest::Client,
-    cfg: &AiCfg,
-    system_prompt: &str,
-    user_content: &str,
-) -> Result<String, String> {
-    let url = format!("{}/chat/completions", cfg.base_url.trim_end_matches('/'));
-    let body = serde_json::json!({
-        "model": cfg.model,
-        "messages": [
-            { "role": "system", "content": system_prompt },
-            { "role": "user", "content": user_content },
-        ],
-        "temperature": 0.1,
-    });
-    let resp = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", cfg.api_key))
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| format!("AI请求失败: {e}"))?;
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let text = resp.text().await.unwrap_or_default();
-        return Err(format!("AI API {status}: {text}"));
-    }
-    let chat: ChatResponse = resp.json().await.map_err(|e| format!("解析响应失败: {e}"))?;
-    chat.choices.into_iter().next()
-        .map(|c| c.message.content)
-        .ok_or_else(|| "AI返回空choices".to_string())
-}
-
-const PREPROCESS_PROMPT: &str = r#"将以下字幕文本处理成适合中文TTS朗读的口语化文本：
+const PREPROCESS_CORE: &str = r#"将以下字幕文本处理成适合中文TTS朗读的口语化文本：
 规则：
 1. 数字转中文读法（1024 → 一千零二十四）
 2. 英文缩写展开（如上下文明确则展开，否则保留）
 3. 删除括号内的舞台指示（[笑声]、(掌声) 等）
 4. 标点规范化（省略号统一为…，感叹号不重复）
-5. 保持原意，不翻译，不添加内容
+5. 保持原意，不翻译，不添加内容"#;
+
+/// Output format rules, appended to the default prompt and to templates alike.
+const PREPROCESS_RULES: &str = r#"
 ## Rules
 - Input/output format: JSON object {"index": "text", ...}
 - Output count MUST exactly match input count
@@ -375,8 +340,12 @@ pub fn cmd_reset_dubbing_job(
 
 /// Cancel ongoing dubbing operations.
 #[tauri::command]
-pub fn cmd_cancel_dubbing(cancel: State<'_, DubbingCancelState>) -> Result<(), String> {
+pub async fn cmd_cancel_dubbing(
+    cancel: State<'_, DubbingCancelState>,
+    pool: State<'_, AiPoolManager>,
+) -> Result<(), String> {
     cancel.0.store(true, Ordering::Relaxed);
+    pool.wake_cancelled().await;
     Ok(())
 }
 
@@ -386,31 +355,50 @@ pub async fn cmd_run_preprocess(
     app: AppHandle,
     db: State<'_, DbState>,
     pool: State<'_, AiPoolManager>,
+    llm_cache: State<'_, LlmCacheState>,
     cancel: State<'_, DubbingCancelState>,
     job_id: String,
     subtitles: Vec<SubtitleEntry>,
     batch_size: Option<u32>,
+    ai_config_id: Option<String>,
+    fallback_configs: Option<Vec<String>>,
+    prompt_template_id: Option<String>,
+    journal_mode: Option<String>,
 ) -> Result<Vec<String>, String> {
     cancel.0.store(false, Ordering::Relaxed);
     emit_stage_change(&app, "preprocess", "running");
     set_stage_status(&db, &job_id, "preprocess", "running", None, None)?;
 
-    let cfg = {
+    let chain = resolve_chain(
+        &db,
+        ai_config_id.as_deref(),
+        fallback_configs.as_deref().unwrap_or_default(),
+    )?;
+    let project_dir = {
         let conn = db.0.lock().map_err(|e| e.to_string())?;
-        let configs = queries::get_all_ai_configs(&conn).map_err(|e| e.to_string())?;
-        configs.into_iter().find(|c| c.is_default)
-            .ok_or_else(|| "未配置默认 AI 模型，请先在设置中添加".to_string())?
+        queries::get_dubbing_job_project_dir(&conn, &job_id).map_err(|e| e.to_string())?
+    };
+    // A "preprocess" template replaces the default instructions; the output rules stay
+    let core = match prompt_template_id.filter(|id| !id.is_empty()) {
+        Some(id) => {
+            let template = {
+                let conn = db.0.lock().map_err(|e| e.to_string())?;
+                prompt_template::load_for(&conn, &id, "preprocess")?
+            };
+            let vars = project_template_vars(&db, project_dir.as_deref())?;
+            prompt_template::render(&template.content, &vars).0
+        }
+        None => PREPROCESS_CORE.to_string(),
     };
-    let ai_cfg = AiCfg {
-        id: cfg.id.clone(),
-        base_url: cfg.base_url.clone(),
-        api_key: cfg.api_key.clone(),
-        model: cfg.model.clone(),
-        concurrent_limit: cfg.concurrent_limit as u32,
-        request_timeout: cfg.request_timeout as u64,
-        rate_limit: cfg.rate_limit as u32,
+    let system_prompt = format!("{core}{PREPROCESS_RULES}");
+    let journal = match &project_dir {
+        Some(project_dir) => {
+            open_journal(&db, project_dir, journal_mode.as_deref().unwrap_or_default())?
+        }
+        None => Arc::new(Journal::disabled()),
     };
-    let client = pool.get_or_create_client(&ai_cfg.id, ai_cfg.request_timeout).await;
+    let cache = open_cache(&db, &llm_cache)?;
+    let routes = build_routes(&pool, chain, "dubbing", "preprocess", &journal, &cache).await?;
     let bs = batch_size.unwrap_or(20) as usize;
     let total = subtitles.len();
     let mut results = vec![String::new(); total];
@@ -428,37 +416,25 @@ pub async fn cmd_run_preprocess(
             return Err("已取消".to_string());
         }
 
-        let _permit = pool.acquire(&ai_cfg.id, ai_cfg.concurrent_limit, ai_cfg.rate_limit, &cancel.0).await?;
         let user_obj: HashMap<String, &str> = batch.iter()
             .map(|&idx| (idx.to_string(), subtitles[idx].text.as_str()))
             .collect();
         let user_content = serde_json::to_string(&user_obj).unwrap_or_default();
 
-        let mut batch_ok = false;
-        for attempt in 0..3u32 {
-            if attempt > 0 {
-                tokio::time::sleep(std::time::Duration::from_secs(1 << attempt)).await;
-            }
-            match call_ai_json(&client, &ai_cfg, PREPROCESS_PROMPT, &user_content).await {
-                Ok(raw) => {
-                    if let Ok(map) = parse_json_map(&raw, batch.len()) {
-                        for &idx in batch {
-                            results[idx] = map.get(&idx.to_string())
-                                .cloned()
-                                .unwrap_or_else(|| subtitles[idx].text.clone());
-                        }
-                        batch_ok = true;
-                        break;
-                    }
-                }
-                Err(_) => {}
-            }
-        }
-        if !batch_ok {
-            // Fallback: keep original text
-            for &idx in batch {
-                results[idx] = subtitles[idx].text.clone();
+        let prompt = ChatPrompt::json(&system_prompt, &user_content, 0.1);
+        let parse = |raw: &str| parse_json_map(raw, batch.len());
+        let reply = call_along_routes(&pool, &routes, &cancel.0, "batch", prompt, parse).await;
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) => {
+                set_stage_status(&db, &job_id, "preprocess", "failed", None, Some(e.clone()))?;
+                return Err(e);
             }
+        };
+        // A batch that keeps failing keeps its original text
+        for &idx in batch {
+            let processed = reply.as_ref().ok().and_then(|map| map.get(&idx.to_string()));
+            results[idx] = processed.cloned().unwrap_or_else(|| subtitles[idx].text.clone());
         }
 
         // Emit per-batch result so frontend can update the dual-column list in real time
@@ -468,8 +444,12 @@ pub async fn cmd_run_preprocess(
         }).collect();
         let _ = app.emit("dubbing:preprocess_batch_result", DubbingPreprocessBatchResult { updates });
 
-        let percent = (bi as f64 + 1.0) / total_batches as f64 * 100.0;
-        emit_progress(&app, "preprocess", percent, &format!("字幕预处理: {}/{total_batches}", bi + 1));
+        let _ = app.emit("dubbing:progress", DubbingProgressEvent {
+            stage: "preprocess".to_string(),
+            percent: (bi as f64 + 1.0) / total_batches as f64 * 100.0,
+            message: format!("字幕预处理: {}/{total_batches}", bi + 1),
+            cache: Some(cache.counts()),
+        });
     }
 
     set_stage_status(&db, &job_id, "preprocess", "completed", None, None)?;
@@ -610,6 +590,7 @@ pub fn cmd_init_tts_items(
 pub async fn cmd_run_tts_generation(
     app: AppHandle,
     db: State<'_, DbState>,
+    pool: State<'_, AiPoolManager>,
     cancel: State<'_, DubbingCancelState>,
     job_id: String,
     plugin_id: Option<String>,
@@ -621,19 +602,29 @@ pub async fn cmd_run_tts_generation(
     set_stage_status(&db, &job_id, "tts", "running", None, None)?;
 
     // Build provider: plugin takes priority; fallback to built-in NCN
+    let breaker_key = plugin_id
+        .clone()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "ncn".to_string());
     let provider = match plugin_id.as_deref().filter(|s| !s.is_empty()) {
         Some(pid) => {
-            let plugin = {
+            let (plugin, proxy) = {
                 let conn = db.0.lock().map_err(|e| e.to_string())?;
-                queries::get_tts_plugin(&conn, pid)
+                let plugin = queries::get_tts_plugin(&conn, pid)
                     .map_err(|e| e.to_string())?
-                    .ok_or_else(|| format!("TTS 插件 {pid} 不存在"))?
+                    .ok_or_else(|| format!("TTS 插件 {pid} 不存在"))?;
+                let proxy = super::tts_plugin::plugin_proxy(&conn, &plugin)?;
+                (plugin, proxy)
             };
-            super::tts_plugin::build_provider(&plugin)?
+            super::tts_plugin::build_provider(&plugin, proxy)?
         }
         None => {
             use crate::tts::{NcnProvider, TtsProviderImpl};
-            TtsProviderImpl::Ncn(NcnProvider::new(ncn_voice_id.unwrap_or_default()))
+            let proxy = {
+                let conn = db.0.lock().map_err(|e| e.to_string())?;
+                ProxyConfig::global(&conn)?
+            };
+            TtsProviderImpl::Ncn(NcnProvider::new(ncn_voice_id.unwrap_or_default(), proxy))
         }
     };
 
@@ -675,12 +666,24 @@ pub async fn cmd_run_tts_generation(
             if attempt > 0 {
                 tokio::time::sleep(std::time::Duration::from_secs(2u64.pow(attempt))).await;
             }
-            match provider.synthesize(TtsSynthRequest {
+            // Stop the stage while the provider is down instead of failing every item
+            if let Err(e) = pool.tts_breakers.allow(&breaker_key) {
+                set_stage_status(&db, &job_id, "tts", "failed", None, Some(e.clone()))?;
+                return Err(e);
+            }
+            let result = provider.synthesize(TtsSynthRequest {
                 text: req.text.clone(),
                 voice_id: req.voice_id.clone(),
                 reference_audio_path: req.reference_audio_path.clone(),
                 output_path: req.output_path.clone(),
-            }).await {
+            }).await;
+            // A rejected request still shows the provider is up
+            let outcome = match &result {
+                Err(e) if e.endpoint => Err(e.message.as_str()),
+                _ => Ok(()),
+            };
+            pool.tts_breakers.report(&breaker_key, outcome);
+            match result {
                 Ok(resp) => {
                     {
                         let conn = db.0.lock().map_err(|e| e.to_string())?;
@@ -700,7 +703,7 @@ pub async fn cmd_run_tts_generation(
                     if attempt == 2 {
                         let conn = db.0.lock().map_err(|e| e.to_string())?;
                         let _ = queries::update_tts_item_failed(
-                            &conn, &job_id, item.subtitle_index, &e,
+                            &conn, &job_id, item.subtitle_index, &e.message,
                         );
                         emit_tts_item_done(&app, item.subtitle_index, "failed", None);
                     }
@@ -1,6 +1,9 @@
 pub mod transcribe;
 pub mod ai_config;
 pub mod translate;
+pub mod correction;
 pub mod dubbing;
 pub mod tts_plugin;
 pub mod workbench;
+pub mod prompt_template;
+pub mod secret_store;
@@ -0,0 +1,294 @@
+use crate::db::connection::DbState;
+use crate::db::queries::{self, PromptTemplate};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::State;
+
+/// Prompt slots a template can fill.
+const KINDS: &[&str] = &[
+    "correction", "standard", "reflective", "optimize", "review", "terminology", "summary",
+    "preprocess",
+];
+
+/// Placeholders filled in by the translation pipeline, with their meaning.
+const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("target_language", "目标语言"),
+    ("world_building", "背景 / 世界观设定"),
+    ("writing_style", "写作风格"),
+    ("glossary", "术语表"),
+    ("forbidden", "禁用词"),
+    ("examples", "翻译示例"),
+    ("custom_prompt", "附加指令"),
+    ("context", "以上所有非空设定合并后的完整上下文"),
+];
+
+/// Identifies an export file produced by `cmd_export_prompt_templates`.
+const BUNDLE_FORMAT: &str = "dubverse-prompt-templates/1";
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptTemplateBundle {
+    format: String,
+    templates: Vec<PromptTemplate>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptPlaceholder {
+    name: String,
+    description: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedPrompt {
+    text: String,
+    /// Placeholders in the template that had no value.
+    unknown: Vec<String>,
+}
+
+fn is_placeholder(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Replace `{name}` placeholders with `vars`. Braces around anything other than a
+/// lowercase identifier (JSON examples, for instance) are left as they are, and so
+/// are placeholders without a value; those are returned as the second element.
+pub(crate) fn render(content: &str, vars: &HashMap<String, String>) -> (String, Vec<String>) {
+    let mut out = String::with_capacity(content.len());
+    let mut unknown: Vec<String> = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}').map(|end| &after[..end]) {
+            Some(name) if is_placeholder(name) => {
+                match vars.get(name) {
+                    Some(value) => out.push_str(value),
+                    None => {
+                        out.push_str(&rest[start..start + name.len() + 2]);
+                        if !unknown.iter().any(|u| u == name) {
+                            unknown.push(name.to_string());
+                        }
+                    }
+                }
+                rest = &after[name.len() + 1..];
+            }
+            _ => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    (out, unknown)
+}
+
+/// The latest version of template `id`, which must be made for the prompt slot `kind`.
+pub(crate) fn load_for(conn: &Connection, id: &str, kind: &str) -> Result<PromptTemplate, String> {
+    let template = queries::get_prompt_template(conn, id, None)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("提示词模板 {id} 不存在"))?;
+    if template.kind != kind {
+        return Err(format!(
+            "提示词模板 {} 的类型为 {}，不能用于 {kind}",
+            template.name, template.kind
+        ));
+    }
+    Ok(template)
+}
+
+fn validate(template: &PromptTemplate) -> Result<(), String> {
+    if template.name.trim().is_empty() {
+        return Err("提示词模板名称不能为空".to_string());
+    }
+    if template.content.trim().is_empty() {
+        return Err("提示词模板内容不能为空".to_string());
+    }
+    if !KINDS.contains(&template.kind.as_str()) {
+        return Err(format!("未知的提示词类型: {}", template.kind));
+    }
+    Ok(())
+}
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+#[tauri::command]
+pub fn cmd_get_prompt_templates(db: State<'_, DbState>) -> Result<Vec<PromptTemplate>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::list_prompt_templates(&conn).map_err(|e| e.to_string())
+}
+
+/// All versions of a template, newest first.
+#[tauri::command]
+pub fn cmd_get_prompt_template_versions(
+    db: State<'_, DbState>,
+    id: String,
+) -> Result<Vec<PromptTemplate>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::get_prompt_template_versions(&conn, &id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn cmd_create_prompt_template(
+    db: State<'_, DbState>,
+    template: PromptTemplate,
+) -> Result<PromptTemplate, String> {
+    validate(&template)?;
+    let template = PromptTemplate {
+        id: uuid::Uuid::new_v4().to_string(),
+        version: 1,
+        created_at: now(),
+        ..template
+    };
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::insert_prompt_template(&conn, &template).map_err(|e| e.to_string())?;
+    Ok(template)
+}
+
+/// Save an edit as a new version of the template.
+#[tauri::command]
+pub fn cmd_update_prompt_template(
+    db: State<'_, DbState>,
+    template: PromptTemplate,
+) -> Result<PromptTemplate, String> {
+    validate(&template)?;
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let version = queries::next_prompt_template_version(&conn, &template.id)
+        .map_err(|e| e.to_string())?;
+    if version == 1 {
+        return Err(format!("提示词模板 {} 不存在", template.id));
+    }
+    let template = PromptTemplate { version, created_at: now(), ..template };
+    queries::insert_prompt_template(&conn, &template).map_err(|e| e.to_string())?;
+    Ok(template)
+}
+
+/// Make an older version current again by saving it as the newest version.
+#[tauri::command]
+pub fn cmd_restore_prompt_template_version(
+    db: State<'_, DbState>,
+    id: String,
+    version: i32,
+) -> Result<PromptTemplate, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let old = queries::get_prompt_template(&conn, &id, Some(version))
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("提示词模板 {id} 没有版本 {version}"))?;
+    let next = queries::next_prompt_template_version(&conn, &id).map_err(|e| e.to_string())?;
+    let template = PromptTemplate { version: next, created_at: now(), ..old };
+    queries::insert_prompt_template(&conn, &template).map_err(|e| e.to_string())?;
+    Ok(template)
+}
+
+#[tauri::command]
+pub fn cmd_delete_prompt_template(db: State<'_, DbState>, id: String) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::delete_prompt_template(&conn, &id).map_err(|e| e.to_string())
+}
+
+/// Export the latest version of the given templates (all when `ids` is omitted)
+/// as a JSON document that `cmd_import_prompt_templates` accepts.
+#[tauri::command]
+pub fn cmd_export_prompt_templates(
+    db: State<'_, DbState>,
+    ids: Option<Vec<String>>,
+) -> Result<String, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let mut templates = queries::list_prompt_templates(&conn).map_err(|e| e.to_string())?;
+    if let Some(ids) = ids {
+        templates.retain(|t| ids.contains(&t.id));
+    }
+    let bundle = PromptTemplateBundle { format: BUNDLE_FORMAT.to_string(), templates };
+    serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())
+}
+
+/// Import templates from an export. Unknown ids are added as they are; a template
+/// that already exists gets a new version unless its content is unchanged.
+/// Returns the imported or updated templates.
+#[tauri::command]
+pub fn cmd_import_prompt_templates(
+    db: State<'_, DbState>,
+    json: String,
+) -> Result<Vec<PromptTemplate>, String> {
+    let bundle: PromptTemplateBundle =
+        serde_json::from_str(&json).map_err(|e| format!("解析提示词模板文件失败: {e}"))?;
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(format!("不支持的提示词模板文件格式: {}", bundle.format));
+    }
+    for template in &bundle.templates {
+        validate(template)?;
+    }
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let mut imported = Vec::new();
+    for template in bundle.templates {
+        let id = if template.id.is_empty() {
+            uuid::Uuid::new_v4().to_string()
+        } else {
+            template.id.clone()
+        };
+        let existing = queries::get_prompt_template(&conn, &id, None).map_err(|e| e.to_string())?;
+        let version = match &existing {
+            Some(cur)
+                if cur.content == template.content
+                    && cur.name == template.name
+                    && cur.kind == template.kind =>
+            {
+                continue;
+            }
+            Some(cur) => cur.version + 1,
+            None => template.version.max(1),
+        };
+        let template = PromptTemplate { id, version, created_at: now(), ..template };
+        queries::insert_prompt_template(&conn, &template).map_err(|e| e.to_string())?;
+        imported.push(template);
+    }
+    Ok(imported)
+}
+
+#[tauri::command]
+pub fn cmd_get_prompt_placeholders() -> Vec<PromptPlaceholder> {
+    PLACEHOLDERS
+        .iter()
+        .map(|(name, description)| PromptPlaceholder {
+            name: name.to_string(),
+            description: description.to_string(),
+        })
+        .collect()
+}
+
+/// Preview a template with sample values for its placeholders.
+#[tauri::command]
+pub fn cmd_render_prompt_template(
+    content: String,
+    vars: HashMap<String, String>,
+) -> RenderedPrompt {
+    let (text, unknown) = render(&content, &vars);
+    RenderedPrompt { text, unknown }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_fills_known_placeholders_and_reports_the_rest() {
+        let vars = HashMap::from([("target_language".to_string(), "中文".to_string())]);
+        let (text, unknown) =
+            render("Translate to {target_language}. {tone} {tone} {\"a\": 1} {X}", &vars);
+        assert_eq!(text, "Translate to 中文. {tone} {tone} {\"a\": 1} {X}");
+        assert_eq!(unknown, ["tone"]);
+    }
+
+    #[test]
+    fn render_leaves_unclosed_braces() {
+        let (text, unknown) = render("a {target_language", &HashMap::new());
+        assert_eq!(text, "a {target_language");
+        assert!(unknown.is_empty());
+    }
+}
@@ -0,0 +1,47 @@
+use crate::db::connection::DbState;
+use crate::db::queries;
+use crate::secrets::{SecretStoreState, SecretStoreStatus};
+use tauri::State;
+
+/// Whether a passphrase protects the stored secrets, whether they're unlocked and
+/// whether the key file is unusable.
+#[tauri::command]
+pub async fn cmd_get_secret_store_status(
+    store: State<'_, SecretStoreState>,
+) -> Result<SecretStoreStatus, String> {
+    Ok(store.status())
+}
+
+/// Unlock the stored secrets at startup, then encrypt any still in plaintext.
+#[tauri::command]
+pub async fn cmd_unlock_secret_store(
+    db: State<'_, DbState>,
+    store: State<'_, SecretStoreState>,
+    passphrase: String,
+) -> Result<(), String> {
+    store.unlock(&passphrase)?;
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::seal_plaintext_secrets(&conn).map_err(|e| e.to_string())
+}
+
+/// Set, change or (with an empty `new_passphrase`) remove the passphrase.
+#[tauri::command]
+pub async fn cmd_set_secret_passphrase(
+    store: State<'_, SecretStoreState>,
+    current_passphrase: String,
+    new_passphrase: String,
+) -> Result<(), String> {
+    store.set_passphrase(&current_passphrase, &new_passphrase)
+}
+
+/// Replace an unusable key file with a fresh key. Secrets sealed under the old one
+/// are lost and show as needing to be entered again.
+#[tauri::command]
+pub async fn cmd_reset_secret_store(
+    db: State<'_, DbState>,
+    store: State<'_, SecretStoreState>,
+) -> Result<(), String> {
+    store.reset()?;
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::seal_plaintext_secrets(&conn).map_err(|e| e.to_string())
+}
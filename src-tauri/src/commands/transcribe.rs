@@ -1,6 +1,10 @@
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::db::connection::DbState;
+use crate::db::queries;
+use crate::http::{self, ProxyConfig};
+use crate::secrets;
 use serde::{Deserialize, Serialize};
 use tauri::State;
 
@@ -12,6 +16,17 @@ pub struct SubtitleItem {
     pub start_time: f64, // seconds
     pub end_time: f64,   // seconds
     pub text: String,
+    /// Word timestamps, when the transcription service returns them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<WordTiming>,
+}
+
+/// One spoken word of a cue and when it was said (seconds).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WordTiming {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
 }
 
 // ─── 1. Create project directory ────────────────────────────────────────────────
@@ -121,6 +136,7 @@ pub async fn cmd_extract_audio(
 /// Returns JSON-serialized `Vec<SubtitleItem>`.
 #[tauri::command]
 pub async fn cmd_transcribe_elevenlabs(
+    db: State<'_, DbState>,
     audio_path: String,
     model_id: String,
     language: String,
@@ -154,6 +170,17 @@ pub async fn cmd_transcribe_elevenlabs(
     }
     form = form.text("tag_audio_events", tag_audio_events.to_string());
 
+    // The settings only hold the masked key; the real one is the stored provider secret
+    let api_key = if secrets::is_masked(&api_key) {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        let stored = queries::get_provider_secret(&conn, "elevenlabs-paid")
+            .map_err(|e| e.to_string())?
+            .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok());
+        let stored = stored.as_ref().and_then(|v| v["apiKey"].as_str());
+        secrets::unmask(&api_key, stored)
+    } else {
+        api_key
+    };
     let is_free = api_key.is_empty();
 
     if is_free {
@@ -165,7 +192,12 @@ pub async fn cmd_transcribe_elevenlabs(
         form = form.text("diarize", enable_diarization.to_string());
     }
 
-    let mut builder = reqwest::Client::new()
+    let proxy = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        ProxyConfig::global(&conn)?
+    };
+    let client = http::client_builder(&proxy)?.build().map_err(|e| e.to_string())?;
+    let mut builder = client
         .post("https://api.elevenlabs.io/v1/speech-to-text")
         .multipart(form);
 
@@ -306,9 +338,12 @@ fn flush_seg(seg: &mut Vec<(f64, f64, String)>, seg_chars: &mut usize, id: u32)
     let start_time = seg.first().unwrap().0;
     let end_time = seg.last().unwrap().1;
     let text = join_words_smart(seg);
-    seg.clear();
+    let words = seg
+        .drain(..)
+        .map(|(start, end, text)| WordTiming { start, end, text })
+        .collect();
     *seg_chars = 0;
-    SubtitleItem { id, start_time, end_time, text }
+    SubtitleItem { id, start_time, end_time, text, words }
 }
 
 // ─── 4. Transcribe via bcut (bilibili) ──────────────────────────────────────────
@@ -320,6 +355,7 @@ const BCUT_BASE: &str = "https://member.bilibili.com/x/bcut/rubick-interface";
 /// Returns JSON-serialized `Vec<SubtitleItem>`.
 #[tauri::command]
 pub async fn cmd_transcribe_bcut(
+    db: State<'_, DbState>,
     audio_path: String,
     language: String,
 ) -> Result<String, String> {
@@ -335,7 +371,11 @@ pub async fn cmd_transcribe_bcut(
     .map_err(|e| format!("读取音频文件失败: {}", e))?;
 
     let file_size = audio_bytes.len();
-    let client = reqwest::Client::builder()
+    let proxy = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        ProxyConfig::global(&conn)?
+    };
+    let client = http::client_builder(&proxy)?
         .user_agent("Bilibili/1.0.0 (https://www.bilibili.com)")
         .build()
         .map_err(|e| e.to_string())?;
@@ -510,6 +550,7 @@ fn parse_bcut_result(result: &serde_json::Value) -> Result<Vec<SubtitleItem>, St
             start_time: start_ms as f64 / 1000.0,
             end_time: end_ms as f64 / 1000.0,
             text,
+            words: Vec::new(),
         });
     }
 
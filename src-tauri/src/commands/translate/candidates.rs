@@ -0,0 +1,158 @@
+//! Alternative translations for single lines, and the user's choices among them.
+
+use crate::ai_pool::AiPoolManager;
+use crate::db::connection::DbState;
+use crate::db::queries;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::{call_along_routes, parse_lenient, ChatPrompt, Route, SubtitleItem, TranslateOpts};
+
+pub(super) const CANDIDATE_JSON_RULES: &str = r#"
+## Task
+Re-translate each line following the user instruction below. Offer {count} alternative translations per line, each noticeably different from the others and from "current".
+
+## Rules
+- Input format: JSON object {"index": {"source": "...", "current": "...", "before": "...", "after": "..."}, ...}; "before" and "after" are the neighbouring source lines, for context only
+- Output format: JSON object {"index": ["candidate 1", "candidate 2", ...], ...}
+- Output ONLY the JSON object, no extra text or explanation"#;
+
+/// Candidates per line when the caller does not ask for a number.
+pub(super) const DEFAULT_CANDIDATE_COUNT: usize = 3;
+
+/// Alternative translations offered for one line.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineCandidates {
+    pub index: usize,
+    pub source: String,
+    pub current: String,
+    pub candidates: Vec<String>,
+}
+
+/// Which lines `cmd_retranslate_lines` offers alternatives for: `indices` into the
+/// source lines with their current `translations`, the user's instruction, and at
+/// most `count` alternatives per line.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetranslateRequest {
+    pub translations: Vec<SubtitleItem>,
+    pub indices: Vec<usize>,
+    #[serde(default)]
+    pub instruction: String,
+    #[serde(default)]
+    pub count: Option<usize>,
+}
+
+/// A candidate the user picked for a line.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineChoice {
+    pub index: usize,
+    pub source: String,
+    pub text: String,
+    /// Also use the text for every other line with the same source, in later runs.
+    #[serde(default)]
+    pub same_source: bool,
+}
+
+/// Lines to offer alternatives for: `indices` into the source lines and their
+/// current translation, and at most `count` alternatives per line.
+pub(super) struct CandidateLines<'a> {
+    pub(super) sources: &'a [SubtitleItem],
+    pub(super) translations: &'a [SubtitleItem],
+    pub(super) indices: &'a [usize],
+    pub(super) count: usize,
+}
+
+/// Ask for alternative translations of `lines`, batch by batch along the route
+/// chain. Lines the model skipped come back with no candidates.
+pub(super) async fn request_candidates(
+    pool: &AiPoolManager,
+    routes: &[Route],
+    cancel: &Arc<AtomicBool>,
+    system_prompt: &str,
+    lines: CandidateLines<'_>,
+    batch_size: usize,
+) -> Result<Vec<LineCandidates>, String> {
+    let CandidateLines { sources, translations, indices, count } = lines;
+    let text_at = |items: &[SubtitleItem], i: usize| items.get(i).map(|s| s.text.clone()).unwrap_or_default();
+    let mut result = Vec::with_capacity(indices.len());
+
+    for chunk in indices.chunks(batch_size.max(1)) {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("已取消".to_string());
+        }
+        let request: HashMap<String, serde_json::Value> = chunk
+            .iter()
+            .map(|&i| {
+                let item = serde_json::json!({
+                    "source": text_at(sources, i),
+                    "current": text_at(translations, i),
+                    "before": i.checked_sub(1).map(|p| text_at(sources, p)).unwrap_or_default(),
+                    "after": text_at(sources, i + 1),
+                });
+                (i.to_string(), item)
+            })
+            .collect();
+        let content = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+
+        let prompt = ChatPrompt::json(system_prompt, &content, 0.8);
+        let mut parsed: HashMap<String, Vec<String>> =
+            call_along_routes(pool, routes, cancel, "candidates", prompt, parse_lenient)
+                .await?
+                .map_err(|e| format!("生成候选译文失败: {e}"))?;
+
+        for &i in chunk {
+            let current = text_at(translations, i);
+            let mut candidates: Vec<String> = Vec::new();
+            for c in parsed.remove(&i.to_string()).unwrap_or_default() {
+                let c = c.trim().to_string();
+                if !c.is_empty() && c != current && !candidates.contains(&c) {
+                    candidates.push(c);
+                }
+            }
+            candidates.truncate(count);
+            result.push(LineCandidates { index: i, source: text_at(sources, i), current, candidates });
+        }
+    }
+    Ok(result)
+}
+
+/// Replace lines the user re-translated by hand with their chosen text. A choice
+/// holds for its own line while that line's original text or corrected source is
+/// unchanged; a memory entry for every line with that text.
+pub(super) fn apply_translation_memory(
+    db: &DbState,
+    project_dir: &str,
+    opts: &TranslateOpts,
+    subtitles: &[SubtitleItem],
+    sources: &HashMap<usize, String>,
+    current: &mut HashMap<usize, String>,
+) -> Result<(), String> {
+    let (memory, choices) = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        let memory = queries::get_translation_memory(&conn, project_dir, &opts.target_language)
+            .map_err(|e| e.to_string())?;
+        let choices = queries::get_line_choices(&conn, project_dir, &opts.target_language)
+            .map_err(|e| e.to_string())?;
+        (memory, choices)
+    };
+    if memory.is_empty() && choices.is_empty() {
+        return Ok(());
+    }
+    for (idx, src) in sources {
+        let original = subtitles.get(*idx).map(|s| &s.text);
+        let choice = choices
+            .get(&(*idx as i32))
+            .filter(|(source, _)| original == Some(source) || src == source)
+            .map(|(_, text)| text);
+        let remembered = || original.and_then(|o| memory.get(o)).or_else(|| memory.get(src));
+        if let Some(text) = choice.or_else(remembered) {
+            current.insert(*idx, text.clone());
+        }
+    }
+    Ok(())
+}
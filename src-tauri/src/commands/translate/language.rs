@@ -0,0 +1,162 @@
+//! Per-line checks that a phase answered in the expected language.
+
+use crate::db::queries;
+use crate::langid;
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter};
+
+use super::{
+    process_batches, LanguageIssue, LineStatus, Pass, Route, Run, TranslateLanguageCheck,
+    TranslateOpts,
+};
+
+/// Lines of `texts` confidently not in the language `expected` gives for them,
+/// with the detected language. Sorted by index.
+fn wrong_language_lines(
+    texts: &HashMap<usize, String>,
+    expected: impl Fn(usize) -> Option<whatlang::Lang>,
+) -> Vec<(usize, String)> {
+    let mut wrong: Vec<(usize, String)> = texts
+        .iter()
+        .filter_map(|(idx, text)| {
+            let lang = expected(*idx)?;
+            langid::mismatch(text, lang).map(|detected| (*idx, detected))
+        })
+        .collect();
+    wrong.sort_by_key(|(idx, _)| *idx);
+    wrong
+}
+
+/// Put back the previous text of every line a phase turned into the wrong language.
+pub(super) fn revert_wrong_language(
+    output: &mut HashMap<usize, String>,
+    previous: &HashMap<usize, String>,
+    expected: impl Fn(usize) -> Option<whatlang::Lang>,
+    phase: &str,
+) -> Vec<LanguageIssue> {
+    let wrong = wrong_language_lines(output, expected);
+    wrong
+        .into_iter()
+        .filter_map(|(idx, detected)| {
+            let text = previous.get(&idx)?;
+            output.insert(idx, text.clone());
+            Some(LanguageIssue {
+                index: idx,
+                phase: phase.to_string(),
+                detected,
+                action: "reverted".to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Re-request translated lines that are not in the target language, once. Lines
+/// still wrong afterwards are kept, saved as wrong-language and reported as
+/// unresolved; a resumed run reports them again without a new request. Nothing is
+/// checked when the target language is not one `langid` knows.
+pub(super) async fn retry_wrong_language(
+    run: Run<'_>,
+    routes: &[Route],
+    system_prompt: &str,
+    sources: &HashMap<usize, String>,
+    translated: &mut HashMap<usize, String>,
+    opts: &TranslateOpts,
+    percent: f64,
+) -> Result<Vec<LanguageIssue>, String> {
+    let Some(target) = langid::parse_lang(&opts.target_language) else {
+        return Ok(Vec::new());
+    };
+    let Run { db, project_dir, .. } = run;
+    let wrong = wrong_language_lines(translated, |_| Some(target));
+    if wrong.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let wrong_status = LineStatus::WrongLanguage.as_str();
+    let rows = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::get_translation_progress_rows(&conn, project_dir, "translation")
+            .map_err(|e| e.to_string())?
+    };
+    let unresolved = |idx: usize, detected: String| LanguageIssue {
+        index: idx,
+        phase: "translation".to_string(),
+        detected,
+        action: "unresolved".to_string(),
+    };
+    // Lines already re-requested in an earlier run stay as they are
+    let (settled, wrong): (Vec<_>, Vec<_>) = wrong.into_iter().partition(|(idx, _)| {
+        rows.get(&(*idx as i32)).is_some_and(|row| row.status == wrong_status)
+    });
+    let mut issues: Vec<LanguageIssue> =
+        settled.into_iter().map(|(idx, detected)| unresolved(idx, detected)).collect();
+    if wrong.is_empty() {
+        return Ok(issues);
+    }
+
+    // Drop the saved rows so these lines are requested again
+    let indices: Vec<i32> = wrong.iter().map(|(idx, _)| *idx as i32).collect();
+    {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::delete_translation_progress_lines(&conn, project_dir, "translation", &indices)
+            .map_err(|e| e.to_string())?;
+    }
+    let redo: HashMap<usize, String> = wrong
+        .iter()
+        .filter_map(|(idx, _)| sources.get(idx).map(|src| (*idx, src.clone())))
+        .collect();
+    // The first answers may sit in the response cache; ask the model again
+    let routes: Vec<Route> = routes.iter().map(Route::uncached).collect();
+    let pass = Pass {
+        phase: "translation",
+        label: "语言校验",
+        base_percent: percent,
+        weight: 0.0,
+        batch_size: opts.batch_size,
+        temperature: 0.3,
+        keep_lang: None,
+    };
+    let (retried, _) = process_batches(run, &routes, system_prompt, &redo, pass).await?;
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let mut rows = queries::get_translation_progress_rows(&conn, project_dir, "translation")
+        .map_err(|e| e.to_string())?;
+    for (idx, detected) in wrong {
+        let Some(text) = retried.get(&idx).cloned() else { continue };
+        match langid::mismatch(&text, target) {
+            Some(still) => {
+                // Remember the line so a resumed run does not request it again
+                if let Some(row) = rows.get_mut(&(idx as i32)) {
+                    row.status = wrong_status.to_string();
+                    queries::save_translation_progress(
+                        &conn, project_dir, idx as i32, "translation", row,
+                    )
+                    .map_err(|e| e.to_string())?;
+                }
+                issues.push(unresolved(idx, still));
+            }
+            None => issues.push(LanguageIssue {
+                index: idx,
+                phase: "translation".to_string(),
+                detected,
+                action: "retried".to_string(),
+            }),
+        }
+        translated.insert(idx, text);
+    }
+    issues.sort_by_key(|issue| issue.index);
+    Ok(issues)
+}
+
+pub(super) fn emit_language_issues(app: &AppHandle, phase_label: &str, issues: &[LanguageIssue]) {
+    if issues.is_empty() {
+        return;
+    }
+    let _ = app.emit(
+        "translate:language_issues",
+        TranslateLanguageCheck {
+            phase: phase_label.to_string(),
+            issues: issues.to_vec(),
+        },
+    );
+}
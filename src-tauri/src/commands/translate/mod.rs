@@ -0,0 +1,2512 @@
+use crate::ai_pool::{
+    self, AiPoolManager, ApiKeySpec, ChatError, PoolPermit, Priority, RateFeedback,
+};
+use crate::commands::transcribe::WordTiming;
+use crate::commands::{correction, prompt_template};
+use crate::db::connection::DbState;
+use crate::db::queries;
+use crate::http::ProxyConfig;
+use crate::langid;
+use crate::markup;
+use crate::llm_cache::{
+    CacheCounts, CacheRequest, CacheSession, LlmCacheState, PROTOCOL_CHAT, PROTOCOL_CHAT_JSON,
+};
+use crate::llm_journal::{Journal, JournalMode, LlmRequest};
+use crate::model_probe::ModelCapabilities;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, State};
+
+mod candidates;
+mod language;
+mod review;
+mod sentence;
+mod summary;
+mod terminology;
+
+use candidates::{
+    apply_translation_memory, request_candidates, CandidateLines, CANDIDATE_JSON_RULES,
+    DEFAULT_CANDIDATE_COUNT,
+};
+pub use candidates::{LineCandidates, LineChoice, RetranslateRequest};
+use language::{emit_language_issues, revert_wrong_language};
+use review::{review_batches, review_pass, DEFAULT_REVIEW_THRESHOLD, REVIEW_JSON_RULES};
+use sentence::{is_cjk, translate_phase};
+use summary::{
+    apply_content_summary, summarize_content, DEFAULT_SUMMARY_CHUNK, DEFAULT_SUMMARY_CORE,
+    SUMMARY_RULES,
+};
+use terminology::{
+    extract_terms, merge_project_glossary, DEFAULT_TERMINOLOGY_CHUNK, DEFAULT_TERMINOLOGY_CORE,
+    TERMINOLOGY_JSON_RULES,
+};
+
+// ── State & Types ────────────────────────────────────────────────────────────
+
+pub struct TranslateCancelState(pub Arc<AtomicBool>);
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleItem {
+    pub id: i32,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub text: String,
+    /// Word timestamps from transcription, used to group cues into sentences.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<WordTiming>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TranslateProgress {
+    phase: String,
+    batch: u32,
+    total_batches: u32,
+    skipped: u32,
+    /// Saved lines discarded because their source text or prompt changed.
+    invalidated: u32,
+    percent: f64,
+    message: String,
+    /// LLM response cache hits and misses of this run so far.
+    cache: CacheCounts,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TranslateBatchResult {
+    phase: String,
+    updates: Vec<BatchUpdate>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BatchUpdate {
+    index: usize,
+    text: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TranslateReviewResult {
+    phase: String,
+    reviews: Vec<ReviewUpdate>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReviewUpdate {
+    index: usize,
+    accuracy: f64,
+    fluency: f64,
+    terminology: f64,
+    score: f64,
+    reason: String,
+    flagged: bool,
+    /// Set when the line could not be reviewed.
+    error: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TranslateLanguageCheck {
+    phase: String,
+    issues: Vec<LanguageIssue>,
+}
+
+/// A line found in the wrong language after a phase, and what was done about it.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguageIssue {
+    index: usize,
+    phase: String,
+    /// ISO 639-3 code of the detected language, or a script name.
+    detected: String,
+    /// "reverted" (previous text kept), "retried" (re-requested successfully)
+    /// or "unresolved" (still wrong after the re-request).
+    action: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseLineStats {
+    phase: String,
+    translated: u32,
+    fallback: u32,
+    failed: u32,
+    /// Lines left in the wrong language after their one re-request.
+    wrong_language: u32,
+    /// Lines passed through untouched: untranslatable or already in the target language.
+    skipped: u32,
+    /// Lines served from saved progress in this run.
+    reused: u32,
+    /// Saved lines re-done because their source text or prompt had changed.
+    invalidated: u32,
+}
+
+/// Returned by the translation commands: the assembled subtitles plus per-phase
+/// counts of how each line's result was obtained.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslationOutput {
+    subtitles: Vec<SubtitleItem>,
+    line_stats: Vec<PhaseLineStats>,
+    language_issues: Vec<LanguageIssue>,
+    /// Corrections awaiting review. When non-zero the run stopped after correction
+    /// and `subtitles` holds the corrected source text.
+    pending_corrections: usize,
+}
+
+/// Returned by `cmd_extract_terminology`: the project's glossary, and how many
+/// transcript chunks no terms came back for because every request failed.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminologyResult {
+    terms: Vec<queries::GlossaryTerm>,
+    failed_chunks: u32,
+    /// Error of the last failed chunk.
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TranslateOpts {
+    target_language: String,
+    correction: bool,
+    optimization: bool,
+    prompt_type: String,
+    batch_size: usize,
+    world_building: String,
+    writing_style: String,
+    glossary: String,
+    forbidden: String,
+    examples: String,
+    custom_prompt: String,
+    prompt_correction: String,
+    prompt_standard: String,
+    prompt_reflective: String,
+    prompt_optimize: String,
+    review: bool,
+    /// Lines whose overall review score falls below this value are flagged.
+    review_threshold: f64,
+    /// Re-translate flagged lines once and review them again.
+    review_retranslate: bool,
+    /// Stop after correction until every changed line is accepted or rejected.
+    correction_review: bool,
+    prompt_review: String,
+    /// AI config id per phase ("correction", "translation", "optimization", "review");
+    /// phases not listed use the default config.
+    phase_configs: HashMap<String, String>,
+    /// Config ids tried in order when a phase's own config keeps failing.
+    fallback_configs: Vec<String>,
+    /// Prompt template id per slot ("correction", "standard", "reflective",
+    /// "optimize", "review"). A template replaces the whole prompt except the
+    /// output rules, and takes precedence over the `prompt_*` overrides.
+    prompt_templates: HashMap<String, String>,
+    /// LLM call journal: "off" (default), "record" or "replay".
+    journal_mode: String,
+    /// Sentence-aware translation: "" translates cue by cue; "proportional" or "llm"
+    /// translate whole sentences and spread them back over their cues by length or
+    /// with the model's help.
+    sentence_mode: String,
+    /// Content of the templates above, loaded from the DB before the run.
+    #[serde(skip)]
+    template_contents: HashMap<String, String>,
+}
+
+impl Default for TranslateOpts {
+    fn default() -> Self {
+        TranslateOpts {
+            target_language: String::new(),
+            correction: false,
+            optimization: false,
+            prompt_type: "standard".to_string(),
+            batch_size: 30,
+            world_building: String::new(),
+            writing_style: String::new(),
+            glossary: String::new(),
+            forbidden: String::new(),
+            examples: String::new(),
+            custom_prompt: String::new(),
+            prompt_correction: String::new(),
+            prompt_standard: String::new(),
+            prompt_reflective: String::new(),
+            prompt_optimize: String::new(),
+            review: false,
+            review_threshold: DEFAULT_REVIEW_THRESHOLD,
+            review_retranslate: false,
+            correction_review: false,
+            prompt_review: String::new(),
+            phase_configs: HashMap::new(),
+            fallback_configs: Vec::new(),
+            prompt_templates: HashMap::new(),
+            journal_mode: String::new(),
+            sentence_mode: String::new(),
+            template_contents: HashMap::new(),
+        }
+    }
+}
+
+/// What a command run after translation works on: the subtitles of `project_dir`,
+/// the translation options, and the config for phases without one of their own in
+/// `options.phaseConfigs` (the default config when unset).
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslateRequest {
+    subtitles: Vec<SubtitleItem>,
+    project_dir: String,
+    options: TranslateOpts,
+    #[serde(default)]
+    ai_config_id: Option<String>,
+}
+
+// AI config resolved from DB
+#[derive(Clone)]
+pub(crate) struct ResolvedConfig {
+    id: String,
+    base_url: String,
+    api_key: String,
+    model: String,
+    concurrent_limit: u32,
+    request_timeout: u64,
+    rate_limit: u32,
+    /// Token budgets of one request; 0 = unlimited.
+    max_input_tokens: usize,
+    max_output_tokens: usize,
+    /// Tokens per minute; 0 = unlimited.
+    tpm_limit: u32,
+    /// Every key with its limits; the limits above are their sum with several keys.
+    api_keys: Vec<ApiKeySpec>,
+    /// The config's proxy override, or the global proxy.
+    proxy: ProxyConfig,
+    /// The model was probed to support `response_format: json_object`; it's asked
+    /// for on requests whose reply is a JSON object (`ChatPrompt::json`).
+    json_mode: bool,
+}
+
+/// One link of a phase's fallback chain: a config with its pooled HTTP client,
+/// the journal its calls are logged to under `task` and `phase`, the run's response
+/// cache and the scheduling class its requests wait in.
+pub(crate) struct Route {
+    cfg: ResolvedConfig,
+    client: reqwest::Client,
+    feedback: RateFeedback,
+    task: &'static str,
+    phase: &'static str,
+    journal: Arc<Journal>,
+    cache: Arc<CacheSession>,
+    priority: Priority,
+}
+
+impl Route {
+    /// This route with cached answers ignored, for re-requesting lines whose
+    /// answer failed a check made after parsing.
+    fn uncached(&self) -> Route {
+        Route {
+            cfg: self.cfg.clone(),
+            client: self.client.clone(),
+            feedback: self.feedback.clone(),
+            task: self.task,
+            phase: self.phase,
+            journal: Arc::clone(&self.journal),
+            cache: Arc::new(self.cache.write_only()),
+            priority: self.priority,
+        }
+    }
+
+    /// Wait for a slot of the route's config, in the route's scheduling class.
+    async fn acquire(
+        &self,
+        pool: &AiPoolManager,
+        cancel: &Arc<AtomicBool>,
+    ) -> Result<PoolPermit, String> {
+        let cfg = &self.cfg;
+        pool.acquire(&cfg.id, cfg.concurrent_limit, cfg.rate_limit, self.priority, cancel).await
+    }
+
+    /// How a request along this route is logged to the journal.
+    fn journal_request<'a>(
+        &'a self,
+        path: &'a str,
+        prompt: ChatPrompt<'a>,
+    ) -> LlmRequest<'a> {
+        LlmRequest {
+            task: self.task,
+            phase: self.phase,
+            path,
+            config_id: &self.cfg.id,
+            model: &self.cfg.model,
+            system_prompt: prompt.system,
+            user_content: prompt.user,
+            temperature: prompt.temperature,
+        }
+    }
+
+    /// Drop the cached answer to `prompt`, so an unusable one isn't served again.
+    fn forget(&self, prompt: ChatPrompt<'_>) {
+        self.cache.forget(&chat_cache_request(&self.cfg, prompt));
+    }
+}
+
+/// Queue every route of a run the user is waiting on ahead of bulk work.
+fn prioritize(routes: &mut HashMap<&'static str, Vec<Route>>) {
+    for route in routes.values_mut().flatten() {
+        route.priority = Priority::Interactive;
+    }
+}
+
+// ── Prompts ──────────────────────────────────────────────────────────────────
+
+const JSON_RULES: &str = r#"
+## Rules
+- Input/output format: JSON object {"index": "text", ...}
+- Output count MUST exactly match input count
+- Never merge, split, or omit any entry
+- Output ONLY the JSON object, no extra text or explanation"#;
+
+const DEFAULT_CORRECTION_CORE: &str = "You are a subtitle correction assistant. Fix ASR transcription errors including:\n- Misrecognized words and homophones\n- Missing or incorrect punctuation\n- Obvious spelling mistakes\nKeep the ORIGINAL language — do NOT translate.";
+
+const DEFAULT_STANDARD_CORE: &str = "You are a professional subtitle translator.\n- Preserve the original meaning, tone, and style\n- Use natural expressions appropriate for the target language\n- Keep proper nouns unless they have well-known translations";
+
+const DEFAULT_REFLECTIVE_CORE: &str = "You are an expert subtitle translator. For each subtitle, internally perform these 4 steps (do NOT output intermediate steps):\n1. Literal translation\n2. Free/idiomatic translation\n3. Compare both, revise for accuracy and naturalness\n4. Produce the final polished translation\n\nOutput ONLY the final result.";
+
+const DEFAULT_OPTIMIZE_CORE: &str = "You are a subtitle polishing assistant. Improve the already-translated subtitles:\n- Enhance fluency and naturalness\n- Fix awkward phrasing\n- Ensure consistency in terminology and style\n- Do NOT change the meaning";
+
+const DEFAULT_REVIEW_CORE: &str = "You are a subtitle translation reviewer. For each entry, compare the translation against its source and score it from 1 to 10 on:\n- accuracy: meaning preserved, nothing added or omitted\n- fluency: natural wording in the target language\n- terminology: names and terms consistent, glossary respected\nGive a short one-sentence reason, especially for any score below 8.";
+
+#[derive(Clone, Copy)]
+enum Phase {
+    Correction,
+    Standard,
+    Reflective,
+    Optimize,
+    Review,
+    Terminology,
+    Summary,
+}
+
+impl Phase {
+    /// Slot name used for prompt templates.
+    fn key(self) -> &'static str {
+        match self {
+            Phase::Correction => "correction",
+            Phase::Standard => "standard",
+            Phase::Reflective => "reflective",
+            Phase::Optimize => "optimize",
+            Phase::Review => "review",
+            Phase::Terminology => "terminology",
+            Phase::Summary => "summary",
+        }
+    }
+}
+
+// ── Prompt Builder ───────────────────────────────────────────────────────────
+
+fn build_configurable_section(opts: &TranslateOpts) -> String {
+    let mut parts = Vec::new();
+    if !opts.world_building.is_empty() {
+        parts.push(format!("[Background / World Building]\n{}", opts.world_building));
+    }
+    if !opts.writing_style.is_empty() {
+        parts.push(format!("[Writing Style]\n{}", opts.writing_style));
+    }
+    if !opts.glossary.is_empty() {
+        parts.push(format!(
+            "[Glossary - Use these exact translations]\n{}",
+            opts.glossary
+        ));
+    }
+    if !opts.forbidden.is_empty() {
+        parts.push(format!(
+            "[Forbidden - Never use these words/translations]\n{}",
+            opts.forbidden
+        ));
+    }
+    if !opts.examples.is_empty() {
+        parts.push(format!("[Examples - Follow this translation style]\n{}", opts.examples));
+    }
+    if !opts.custom_prompt.is_empty() {
+        parts.push(format!("[Additional Instructions]\n{}", opts.custom_prompt));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("\n{}", parts.join("\n\n"))
+    }
+}
+
+/// Values for the placeholders a prompt template may use.
+fn template_vars(opts: &TranslateOpts) -> HashMap<String, String> {
+    [
+        ("target_language", opts.target_language.clone()),
+        ("world_building", opts.world_building.clone()),
+        ("writing_style", opts.writing_style.clone()),
+        ("glossary", opts.glossary.clone()),
+        ("forbidden", opts.forbidden.clone()),
+        ("examples", opts.examples.clone()),
+        ("custom_prompt", opts.custom_prompt.clone()),
+        ("context", build_configurable_section(opts).trim_start().to_string()),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v))
+    .collect()
+}
+
+/// Placeholder values for templates rendered outside a translation run: the
+/// translation settings saved on `project_dir`'s workbench task, with its approved
+/// glossary and content summary. Without a project every value is empty.
+pub(crate) fn project_template_vars(
+    db: &DbState,
+    project_dir: Option<&str>,
+) -> Result<HashMap<String, String>, String> {
+    let mut opts = TranslateOpts::default();
+    if let Some(project_dir) = project_dir {
+        let saved = {
+            let conn = db.0.lock().map_err(|e| e.to_string())?;
+            queries::get_translate_config_json(&conn, project_dir).map_err(|e| e.to_string())?
+        };
+        if let Some(saved) = saved.and_then(|json| serde_json::from_str(&json).ok()) {
+            opts = saved;
+        }
+        merge_project_glossary(db, project_dir, &mut opts)?;
+        apply_content_summary(db, project_dir, &mut opts)?;
+    }
+    Ok(template_vars(&opts))
+}
+
+/// Sentence modes `TranslateOpts::sentence_mode` accepts; "" is off.
+const SENTENCE_MODES: [&str; 3] = ["", "proportional", "llm"];
+
+/// Reject options the pipeline would otherwise silently misread.
+fn check_opts(opts: &TranslateOpts) -> Result<(), String> {
+    if !SENTENCE_MODES.contains(&opts.sentence_mode.as_str()) {
+        return Err(format!("未知的整句翻译模式: {}", opts.sentence_mode));
+    }
+    Ok(())
+}
+
+/// Load the templates selected in `opts.prompt_templates` (latest versions).
+fn load_prompt_templates(db: &DbState, opts: &mut TranslateOpts) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    for (slot, id) in &opts.prompt_templates {
+        if id.is_empty() {
+            continue;
+        }
+        let template = prompt_template::load_for(&conn, id, slot)?;
+        opts.template_contents.insert(slot.clone(), template.content);
+    }
+    Ok(())
+}
+
+fn build_phase_prompt(phase: Phase, opts: &TranslateOpts) -> String {
+    let rules = match phase {
+        Phase::Review => REVIEW_JSON_RULES,
+        Phase::Terminology => TERMINOLOGY_JSON_RULES,
+        Phase::Summary => SUMMARY_RULES,
+        _ => JSON_RULES,
+    };
+    build_prompt_with_rules(phase, opts, rules)
+}
+
+/// `build_phase_prompt` with other output rules, for tasks that reuse a phase's
+/// instructions and context but need a different answer format.
+fn build_prompt_with_rules(phase: Phase, opts: &TranslateOpts, rules: &str) -> String {
+    if let Some(template) = opts.template_contents.get(phase.key()) {
+        let (text, _) = prompt_template::render(template, &template_vars(opts));
+        return format!("{text}\n{rules}");
+    }
+
+    let section = build_configurable_section(opts);
+    let user_core = match phase {
+        Phase::Correction => {
+            if opts.prompt_correction.is_empty() { DEFAULT_CORRECTION_CORE } else { &opts.prompt_correction }
+        }
+        Phase::Standard => {
+            if opts.prompt_standard.is_empty() { DEFAULT_STANDARD_CORE } else { &opts.prompt_standard }
+        }
+        Phase::Reflective => {
+            if opts.prompt_reflective.is_empty() { DEFAULT_REFLECTIVE_CORE } else { &opts.prompt_reflective }
+        }
+        Phase::Optimize => {
+            if opts.prompt_optimize.is_empty() { DEFAULT_OPTIMIZE_CORE } else { &opts.prompt_optimize }
+        }
+        Phase::Review => {
+            if opts.prompt_review.is_empty() { DEFAULT_REVIEW_CORE } else { &opts.prompt_review }
+        }
+        Phase::Terminology => DEFAULT_TERMINOLOGY_CORE,
+        Phase::Summary => DEFAULT_SUMMARY_CORE,
+    };
+
+    let prefix = match phase {
+        Phase::Correction => String::new(),
+        Phase::Standard | Phase::Reflective => {
+            format!("Translate the following subtitles to {}.\n\n", opts.target_language)
+        }
+        Phase::Optimize | Phase::Review | Phase::Terminology | Phase::Summary => {
+            format!("Target language: {}\n\n", opts.target_language)
+        }
+    };
+
+    format!("{prefix}{user_core}\n{section}{rules}")
+}
+
+// ── API Call ─────────────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+    #[serde(default)]
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    total_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: String,
+}
+
+/// The prompts and sampling of one chat request, and whether its reply is a JSON
+/// object, which requests JSON mode where the model supports it.
+#[derive(Clone, Copy)]
+pub(crate) struct ChatPrompt<'a> {
+    system: &'a str,
+    user: &'a str,
+    temperature: f64,
+    json: bool,
+}
+
+impl<'a> ChatPrompt<'a> {
+    /// A request answered with a JSON object (line maps, candidates, terms, …).
+    pub(crate) fn json(system: &'a str, user: &'a str, temperature: f64) -> Self {
+        ChatPrompt { system, user, temperature, json: true }
+    }
+
+    /// A request answered with prose (content summaries).
+    fn text(system: &'a str, user: &'a str, temperature: f64) -> Self {
+        ChatPrompt { system, user, temperature, json: false }
+    }
+}
+
+/// Send one chat request along `route`, holding the config slot `permit`.
+async fn call_chat_api(
+    route: &Route,
+    permit: &PoolPermit,
+    prompt: ChatPrompt<'_>,
+) -> Result<String, ChatError> {
+    let (client, cfg, cache) = (&route.client, &route.cfg, &route.cache);
+    let ChatPrompt { system: system_prompt, user: user_content, temperature, .. } = prompt;
+    let cache_req = chat_cache_request(cfg, prompt);
+    if let Some(cached) = cache.lookup(&cache_req) {
+        return Ok(cached);
+    }
+    let url = format!("{}/chat/completions", cfg.base_url.trim_end_matches('/'));
+    let mut body = serde_json::json!({
+        "model": cfg.model,
+        "messages": [
+            { "role": "system", "content": system_prompt },
+            { "role": "user", "content": user_content },
+        ],
+        "temperature": temperature,
+    });
+    if cfg.max_output_tokens > 0 {
+        body["max_tokens"] = cfg.max_output_tokens.into();
+    }
+    if cfg.json_mode && prompt.json {
+        body["response_format"] = serde_json::json!({ "type": "json_object" });
+    }
+
+    let lease = route.feedback.lease(&cfg.api_key, permit).await?;
+    let estimate = estimate_request_tokens(system_prompt, user_content, cfg.max_output_tokens);
+    let reservation = lease.reserve_tokens(estimate, permit.abort_flag()).await?;
+    let started = std::time::Instant::now();
+    let resp = client
+        .post(&url)
+        .header("Authorization", format!("Bearer {}", lease.api_key()))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| {
+            let err = format!("HTTP请求失败: {e}");
+            lease.observe_failure(&err);
+            ChatError::from(err)
+        })?;
+    lease.observe(resp.status(), resp.headers(), started.elapsed());
+
+    if !resp.status().is_success() {
+        reservation.settle(0);
+        let status = resp.status();
+        let text = resp.text().await.unwrap_or_default();
+        lease.observe_rejection(status, &text);
+        return Err(ChatError::status(status, format!("API返回 {status}: {text}")));
+    }
+
+    let chat: ChatResponse = resp.json().await.map_err(|e| format!("解析响应失败: {e}"))?;
+    if let Some(usage) = &chat.usage {
+        reservation.settle(usage.total_tokens);
+    }
+    let content = chat.choices
+        .into_iter()
+        .next()
+        .map(|c| c.message.content)
+        .ok_or_else(|| "API返回空choices".to_string())?;
+    cache.store(&cache_req, &content);
+    Ok(content)
+}
+
+fn chat_cache_request<'a>(cfg: &'a ResolvedConfig, prompt: ChatPrompt<'a>) -> CacheRequest<'a> {
+    CacheRequest {
+        protocol: if cfg.json_mode && prompt.json { PROTOCOL_CHAT_JSON } else { PROTOCOL_CHAT },
+        base_url: &cfg.base_url,
+        model: &cfg.model,
+        system_prompt: prompt.system,
+        user_content: prompt.user,
+        temperature: prompt.temperature,
+    }
+}
+
+/// Send one request (or take it from the journal in replay mode), parse the reply,
+/// and journal the exchange with the parse outcome. `path` names the retry step.
+async fn call_parsed<T>(
+    route: &Route,
+    permit: &PoolPermit,
+    path: &str,
+    prompt: ChatPrompt<'_>,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<T, ChatError> {
+    let req = route.journal_request(path, prompt);
+    let started = std::time::Instant::now();
+    let response = match route.journal.replay(&req) {
+        Some(recorded) => recorded.map_err(ChatError::from),
+        None => call_chat_api(route, permit, prompt).await,
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let parsed = response.clone().and_then(|raw| parse(&raw).map_err(ChatError::from));
+    let parse_error = match (&response, &parsed) {
+        (Ok(_), Err(e)) => Some(e.message.as_str()),
+        _ => None,
+    };
+    if parse_error.is_some() {
+        // Don't serve an unusable answer again on retry
+        route.forget(prompt);
+    }
+    let logged = response.map_err(String::from);
+    route.journal.record(&req, &logged, parse_error, latency_ms);
+    parsed
+}
+
+// ── JSON Parse & Validate ────────────────────────────────────────────────────
+
+/// Repair common LLM JSON output issues before parsing.
+fn repair_json(s: &str) -> String {
+    let mut out = s.to_string();
+    // Replace Chinese/curly quotes with ASCII quotes
+    out = out
+        .replace('\u{201C}', "\"")
+        .replace('\u{201D}', "\"")
+        .replace('\u{2018}', "'")
+        .replace('\u{2019}', "'");
+    // Remove trailing commas before } or ]
+    let mut result = String::with_capacity(out.len());
+    let chars: Vec<char> = out.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == ',' {
+            // Peek ahead (skip whitespace) for } or ]
+            let mut j = i + 1;
+            while j < chars.len() && chars[j].is_whitespace() {
+                j += 1;
+            }
+            if j < chars.len() && (chars[j] == '}' || chars[j] == ']') {
+                i += 1;
+                continue; // skip trailing comma
+            }
+        }
+        result.push(chars[i]);
+        i += 1;
+    }
+    result
+}
+
+/// Parse a JSON reply as is, then repaired, then just the part from its first `{`
+/// to its last `}`, for replies wrapped in prose or code fences.
+fn parse_lenient<T: DeserializeOwned>(raw: &str) -> Result<T, String> {
+    if let Ok(value) = serde_json::from_str(raw) {
+        return Ok(value);
+    }
+    if let Ok(value) = serde_json::from_str(&repair_json(raw)) {
+        return Ok(value);
+    }
+    if let (Some(start), Some(end)) = (raw.find('{'), raw.rfind('}')) {
+        if let Ok(value) = serde_json::from_str(&repair_json(&raw[start..=end])) {
+            return Ok(value);
+        }
+    }
+    let head: String = raw.chars().take(200).collect();
+    Err(format!("无法解析JSON: {head}"))
+}
+
+/// Check map content quality beyond count matching.
+fn validate_content(map: &HashMap<String, String>) -> bool {
+    // Reject empty/whitespace translations
+    if map.values().any(|v| v.trim().is_empty()) {
+        return false;
+    }
+    // Reject LLM merge-annotation patterns
+    const MERGE_PATTERNS: &[&str] = &["已合并", "已并入", "同上", "（见第", "(见第", "合并至"];
+    if map
+        .values()
+        .any(|v| MERGE_PATTERNS.iter().any(|p| v.contains(p)))
+    {
+        return false;
+    }
+    true
+}
+
+fn try_parse_map(s: &str, expected_count: usize) -> Result<HashMap<String, String>, String> {
+    if let Ok(map) = serde_json::from_str::<HashMap<String, String>>(s) {
+        if map.len() != expected_count {
+            return Err(format!(
+                "条目数不匹配: 期望{expected_count}, 实际{}",
+                map.len()
+            ));
+        }
+        if !validate_content(&map) {
+            return Err("响应内容验证失败（空值或合并标注）".to_string());
+        }
+        return Ok(map);
+    }
+    // Try JSON repair
+    let repaired = repair_json(s);
+    if repaired != s {
+        if let Ok(map) = serde_json::from_str::<HashMap<String, String>>(&repaired) {
+            if map.len() != expected_count {
+                return Err(format!(
+                    "条目数不匹配: 期望{expected_count}, 实际{}",
+                    map.len()
+                ));
+            }
+            if !validate_content(&map) {
+                return Err("响应内容验证失败（空值或合并标注）".to_string());
+            }
+            return Ok(map);
+        }
+    }
+    Err(format!("JSON解析失败: {}", &s[..s.len().min(200)]))
+}
+
+fn parse_and_validate(
+    raw: &str,
+    expected_count: usize,
+) -> Result<HashMap<String, String>, String> {
+    // Try direct parse first
+    if let Ok(map) = try_parse_map(raw, expected_count) {
+        return Ok(map);
+    }
+    // Fallback: extract JSON object between first { and last }
+    if let (Some(start), Some(end)) = (raw.find('{'), raw.rfind('}')) {
+        let slice = &raw[start..=end];
+        return try_parse_map(slice, expected_count);
+    }
+    Err(format!("无法解析JSON: {}", &raw[..raw.len().min(200)]))
+}
+
+// ── Retry with batch splitting ───────────────────────────────────────────────
+
+fn build_user_content(items: &[(usize, &str)]) -> String {
+    let map: HashMap<String, &str> = items.iter().map(|(i, t)| (i.to_string(), *t)).collect();
+    serde_json::to_string(&map).unwrap_or_default()
+}
+
+/// How a line's result was obtained, persisted with its progress row.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LineStatus {
+    /// Returned by a (possibly split) batch request.
+    Translated,
+    /// Recovered only by the single-line fallback, without surrounding context.
+    Fallback,
+    /// Every attempt failed; the input text was kept unchanged.
+    Failed,
+    /// Passed through without a request: nothing to translate, or already in the
+    /// target language.
+    Skipped,
+    /// Still not in the target language after one uncached re-request; kept as
+    /// returned and not requested again on resume.
+    WrongLanguage,
+}
+
+impl LineStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineStatus::Translated => "translated",
+            LineStatus::Fallback => "fallback",
+            LineStatus::Failed => "failed",
+            LineStatus::Skipped => "skipped",
+            LineStatus::WrongLanguage => "wrong_language",
+        }
+    }
+}
+
+struct LineResult {
+    text: String,
+    status: LineStatus,
+    error: Option<String>,
+}
+
+impl LineResult {
+    fn translated(text: String) -> Self {
+        LineResult { text, status: LineStatus::Translated, error: None }
+    }
+}
+
+/// Translate a single line on its own, retrying once. An answer that lost markup
+/// placeholders is retried too, and used only if no retry does better.
+async fn call_single(
+    route: &Route,
+    permit: &PoolPermit,
+    system_prompt: &str,
+    idx: usize,
+    text: &str,
+    temperature: f64,
+) -> LineResult {
+    let content = build_user_content(&[(idx, text)]);
+    let mut last_error = String::new();
+    let mut tags_lost = None;
+    for retry in 0..2u32 {
+        if retry > 0 {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        let path = format!("single#{}", retry + 1);
+        let parse = |raw: &str| parse_and_validate(raw, 1);
+        let prompt = ChatPrompt::json(system_prompt, &content, temperature);
+        match call_parsed(route, permit, &path, prompt, parse).await {
+            Ok(map) => {
+                if let Some(result) = map.into_values().next() {
+                    if markup::intact(text, &result) {
+                        return LineResult { text: result, status: LineStatus::Fallback, error: None };
+                    }
+                    route.cache.forget(&chat_cache_request(&route.cfg, prompt));
+                    tags_lost = Some(result);
+                }
+            }
+            Err(e) if e.unavailable() => {
+                last_error = e.message;
+                break;
+            }
+            Err(e) => last_error = e.message,
+        }
+    }
+    if let Some(result) = tags_lost {
+        return LineResult {
+            text: result,
+            status: LineStatus::Fallback,
+            error: Some("格式标签丢失".to_string()),
+        };
+    }
+    // Keep original text — do not fail the whole batch
+    LineResult {
+        text: text.to_string(),
+        status: LineStatus::Failed,
+        error: Some(last_error),
+    }
+}
+
+/// Drop the cached answer to a batch if any of its lines lost markup placeholders;
+/// `call_with_retry` re-requests those lines, and a rerun must not get the same answer.
+fn forget_if_markup_lost(
+    route: &Route,
+    prompt: ChatPrompt<'_>,
+    items: &[(usize, &str)],
+    map: &HashMap<String, String>,
+) {
+    let lost = items.iter().any(|(idx, text)| {
+        map.get(&idx.to_string()).is_some_and(|result| !markup::intact(text, result))
+    });
+    if lost {
+        route.cache.forget(&chat_cache_request(&route.cfg, prompt));
+    }
+}
+
+/// Full-batch attempts, then halving splits. Returns `None` when the batch could not
+/// be completed this way; with `yield_on_rate_limit` it gives up on the first 429 so
+/// the caller can move on to another config.
+async fn call_batch(
+    route: &Route,
+    permit: &PoolPermit,
+    system_prompt: &str,
+    items: &[(usize, &str)],
+    temperature: f64,
+    yield_on_rate_limit: bool,
+) -> Option<HashMap<String, String>> {
+    // Phase 1: retry full batch up to 3 times
+    for attempt in 0..3u32 {
+        if attempt > 0 {
+            tokio::time::sleep(std::time::Duration::from_secs(1 << attempt)).await;
+        }
+        let content = build_user_content(items);
+        let path = format!("batch#{}", attempt + 1);
+        let parse = |raw: &str| parse_and_validate(raw, items.len());
+        let prompt = ChatPrompt::json(system_prompt, &content, temperature);
+        match call_parsed(route, permit, &path, prompt, parse).await {
+            Ok(map) => {
+                forget_if_markup_lost(route, prompt, items, &map);
+                return Some(map);
+            }
+            Err(e) if e.unavailable() => return None,
+            Err(e) if yield_on_rate_limit && e.rate_limited() => return None,
+            Err(_) => {}
+        }
+    }
+
+    // Phase 2: split in half, retry each half (max 3 splits)
+    if items.len() > 1 {
+        let mut depth = 0;
+        let mut chunks: Vec<&[(usize, &str)]> = vec![items];
+        while depth < 3 {
+            let mut next_chunks = Vec::new();
+            let mut all_ok = true;
+            for chunk in &chunks {
+                if chunk.len() > 1 {
+                    let mid = chunk.len() / 2;
+                    next_chunks.push(&chunk[..mid]);
+                    next_chunks.push(&chunk[mid..]);
+                    all_ok = false;
+                } else {
+                    next_chunks.push(chunk);
+                }
+            }
+            chunks = next_chunks;
+            if all_ok {
+                break;
+            }
+            depth += 1;
+
+            // Try each sub-chunk
+            let mut combined = HashMap::new();
+            let mut failed = false;
+            for chunk in &chunks {
+                let content = build_user_content(chunk);
+                let mut ok = false;
+                for retry in 0..3u32 {
+                    if retry > 0 {
+                        tokio::time::sleep(std::time::Duration::from_secs(1 << retry)).await;
+                    }
+                    let path = format!("split{depth}#{}", retry + 1);
+                    let parse = |raw: &str| parse_and_validate(raw, chunk.len());
+                    let prompt = ChatPrompt::json(system_prompt, &content, temperature);
+                    let reply = call_parsed(route, permit, &path, prompt, parse).await;
+                    match reply {
+                        Ok(map) => {
+                            forget_if_markup_lost(route, prompt, chunk, &map);
+                            combined.extend(map);
+                            ok = true;
+                            break;
+                        }
+                        Err(e) if e.unavailable() => return None,
+                        Err(e) if yield_on_rate_limit && e.rate_limited() => return None,
+                        Err(_) => {}
+                    }
+                }
+                if !ok {
+                    failed = true;
+                    break;
+                }
+            }
+            if !failed {
+                return Some(combined);
+            }
+        }
+    }
+    None
+}
+
+/// Run a batch along the fallback chain. Each config gets the full-batch and split
+/// attempts; a config that keeps failing or is rate-limited hands the batch to the
+/// next one. Only the last config drops to single-item fallback.
+async fn call_with_retry(
+    pool: &AiPoolManager,
+    routes: &[Route],
+    cancel: &Arc<AtomicBool>,
+    system_prompt: &str,
+    items: &[(usize, &str)],
+    temperature: f64,
+) -> Result<HashMap<String, LineResult>, String> {
+    let Some((last, _)) = routes.split_last() else {
+        return Err("未配置 AI 模型".to_string());
+    };
+
+    for (i, route) in routes.iter().enumerate() {
+        let is_last = i + 1 == routes.len();
+        let permit = route.acquire(pool, cancel).await?;
+        let reply = call_batch(route, &permit, system_prompt, items, temperature, !is_last).await;
+        let Some(map) = reply else {
+            continue;
+        };
+        let mut results: HashMap<String, LineResult> = map
+            .into_iter()
+            .map(|(k, v)| (k, LineResult::translated(v)))
+            .collect();
+        // Lines that lost markup placeholders are re-requested on their own
+        for (idx, text) in items {
+            let key = idx.to_string();
+            if results.get(&key).is_some_and(|r| !markup::intact(text, &r.text)) {
+                results.remove(&key);
+            }
+        }
+
+        // Retry any missing keys individually (silent fallback prevention)
+        let missing: Vec<(usize, &str)> = items
+            .iter()
+            .filter(|(i, _)| !results.contains_key(&i.to_string()))
+            .copied()
+            .collect();
+        for (idx, text) in &missing {
+            let result = call_single(route, &permit, system_prompt, *idx, text, temperature).await;
+            results.insert(idx.to_string(), result);
+        }
+        return Ok(results);
+    }
+
+    // Phase 3: single-item fallback on the last config — keep original on failure.
+    // A dead endpoint fails the run instead of leaving every line untranslated.
+    if let Some(e) = last.feedback.circuit_open() {
+        return Err(e);
+    }
+    let permit = last.acquire(pool, cancel).await?;
+    let mut fallback: HashMap<String, LineResult> = HashMap::new();
+    for (idx, text) in items {
+        let result = call_single(last, &permit, system_prompt, *idx, text, temperature).await;
+        if let Some(e) = result.error.as_ref().filter(|e| ai_pool::is_endpoint_unavailable(e)) {
+            return Err(e.clone());
+        }
+        fallback.insert(idx.to_string(), result);
+    }
+    Ok(fallback)
+}
+
+/// Send one request along the fallback chain, with up to three attempts per config
+/// and a growing pause between them; a rate-limited config hands over to the next
+/// one right away. The outer error stops the run (cancelled, or the endpoint is
+/// down), the inner one is the last failure once every config was tried. `path`
+/// names the step in the journal and is numbered per attempt.
+pub(crate) async fn call_along_routes<T>(
+    pool: &AiPoolManager,
+    routes: &[Route],
+    cancel: &Arc<AtomicBool>,
+    path: &str,
+    prompt: ChatPrompt<'_>,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<Result<T, String>, String> {
+    let mut last_error = "未配置 AI 模型".to_string();
+    'routes: for (i, route) in routes.iter().enumerate() {
+        let is_last = i + 1 == routes.len();
+        let permit = route.acquire(pool, cancel).await?;
+        for attempt in 0..3u32 {
+            if attempt > 0 {
+                tokio::time::sleep(std::time::Duration::from_secs(1 << attempt)).await;
+            }
+            if cancel.load(Ordering::Relaxed) {
+                return Err("已取消".to_string());
+            }
+            let attempt_path = format!("{path}#{}", attempt + 1);
+            match call_parsed(route, &permit, &attempt_path, prompt, &parse).await {
+                Ok(value) => return Ok(Ok(value)),
+                Err(e) if !is_last && e.hands_over() => {
+                    last_error = e.message;
+                    continue 'routes;
+                }
+                Err(e) if e.unavailable() => return Err(e.message),
+                Err(e) => last_error = e.message,
+            }
+        }
+    }
+    Ok(Err(last_error))
+}
+
+// ── Batch Processing ─────────────────────────────────────────────────────────
+
+/// What every step of a translation run works with: the app and its state, the
+/// cancel flag, and the project whose progress and reviews it saves.
+#[derive(Clone, Copy)]
+struct Run<'a> {
+    app: &'a AppHandle,
+    db: &'a DbState,
+    pool: &'a AiPoolManager,
+    cancel: &'a Arc<AtomicBool>,
+    project_dir: &'a str,
+}
+
+/// One pass over a set of lines: the progress `phase` its results are saved
+/// under, the label and slice of the progress bar it reports, and its requests.
+struct Pass<'a> {
+    phase: &'a str,
+    label: &'a str,
+    base_percent: f64,
+    weight: f64,
+    batch_size: usize,
+    temperature: f64,
+    /// Lines already in this language pass through without a request.
+    keep_lang: Option<whatlang::Lang>,
+}
+
+/// How many saved lines a phase reused and how many it discarded as stale.
+#[derive(Clone, Copy, Default)]
+struct ResumeCounts {
+    reused: u32,
+    invalidated: u32,
+}
+
+/// Rough token count: CJK characters are about a token each, other text about
+/// four characters per token.
+pub(crate) fn estimate_tokens(text: &str) -> usize {
+    let (cjk, other) = text.chars().fold((0usize, 0usize), |(cjk, other), c| {
+        if is_cjk(c) { (cjk + 1, other) } else { (cjk, other + 1) }
+    });
+    cjk + other.div_ceil(4)
+}
+
+/// JSON key, quotes and separators around each line of a request or response.
+const LINE_TOKEN_OVERHEAD: usize = 6;
+/// Translations can run longer than their source (English → Chinese roughly doubles
+/// the token count); budget output for the worst case.
+const OUTPUT_TOKEN_RATIO: usize = 2;
+
+/// Tokens a chat request is expected to consume, prompt and reply together, for
+/// the per-minute budget. `max_output` caps the reply estimate when set.
+fn estimate_request_tokens(
+    system_prompt: &str,
+    user_content: &str,
+    max_output: usize,
+) -> usize {
+    let input = estimate_tokens(system_prompt) + estimate_tokens(user_content);
+    let output = estimate_tokens(user_content) * OUTPUT_TOKEN_RATIO;
+    input + if max_output > 0 { output.min(max_output) } else { output }
+}
+
+/// Split `todo` into batches of at most `batch_size` lines that also stay within
+/// the smallest input and output token budgets along the route chain. Without
+/// budgets this is the plain fixed-size split. A line over budget on its own still
+/// gets a batch; the split-on-failure ladder is the safety net.
+fn plan_batches(
+    todo: &[(usize, String)],
+    batch_size: usize,
+    routes: &[Route],
+    system_prompt: &str,
+) -> Vec<Vec<(usize, String)>> {
+    let budget = |limit: fn(&ResolvedConfig) -> usize| {
+        routes.iter().map(|r| limit(&r.cfg)).filter(|l| *l > 0).min()
+    };
+    let input_budget = budget(|c| c.max_input_tokens)
+        .map(|b| b.saturating_sub(estimate_tokens(system_prompt)).max(1));
+    let output_budget = budget(|c| c.max_output_tokens);
+    split_by_budget(todo, batch_size, input_budget, output_budget)
+}
+
+/// The batching of `plan_batches` for per-batch token budgets left after the
+/// system prompt.
+fn split_by_budget(
+    todo: &[(usize, String)],
+    batch_size: usize,
+    input_budget: Option<usize>,
+    output_budget: Option<usize>,
+) -> Vec<Vec<(usize, String)>> {
+    let batch_size = batch_size.max(1);
+    if input_budget.is_none() && output_budget.is_none() {
+        return todo.chunks(batch_size).map(|c| c.to_vec()).collect();
+    }
+
+    let mut batches: Vec<Vec<(usize, String)>> = Vec::new();
+    let mut batch: Vec<(usize, String)> = Vec::new();
+    let (mut input, mut output) = (0, 0);
+    for (idx, text) in todo {
+        let tokens = estimate_tokens(text) + LINE_TOKEN_OVERHEAD;
+        let out_tokens = estimate_tokens(text) * OUTPUT_TOKEN_RATIO + LINE_TOKEN_OVERHEAD;
+        let over = input_budget.is_some_and(|b| input + tokens > b)
+            || output_budget.is_some_and(|b| output + out_tokens > b);
+        if !batch.is_empty() && (batch.len() >= batch_size || over) {
+            batches.push(std::mem::take(&mut batch));
+            input = 0;
+            output = 0;
+        }
+        input += tokens;
+        output += out_tokens;
+        batch.push((*idx, text.clone()));
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+fn text_hash(text: &str) -> String {
+    format!("{:x}", md5::compute(text.as_bytes()))
+}
+
+/// The system prompt already carries every prompt option (language, glossary, style…).
+fn prompt_hash(system_prompt: &str, temperature: f64) -> String {
+    text_hash(&format!("{system_prompt}\u{1f}{temperature}"))
+}
+
+async fn process_batches(
+    run: Run<'_>,
+    routes: &[Route],
+    system_prompt: &str,
+    texts: &HashMap<usize, String>,
+    pass: Pass<'_>,
+) -> Result<(HashMap<usize, String>, ResumeCounts), String> {
+    let Run { app, db, pool, cancel, project_dir } = run;
+    let Pass {
+        phase,
+        label: phase_label,
+        base_percent: phase_base_percent,
+        weight: phase_weight,
+        batch_size,
+        temperature,
+        keep_lang,
+    } = pass;
+    // Load existing progress for resume
+    let existing = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::get_translation_progress_rows(&conn, project_dir, phase)
+            .map_err(|e| e.to_string())?
+    };
+    // Hashed without the markup rule: whether it's added depends on other lines
+    let prompt_hash = prompt_hash(system_prompt, temperature);
+    // Inline tags travel as placeholders; the model is told to keep them
+    let with_markup_rule;
+    let system_prompt = if texts.values().any(|t| markup::has_tags(t)) {
+        with_markup_rule = format!("{system_prompt}{}", markup::PLACEHOLDER_RULE);
+        with_markup_rule.as_str()
+    } else {
+        system_prompt
+    };
+
+    // Lines with nothing to translate, or already in `keep_lang`, pass through untouched
+    let mut results: HashMap<usize, String> = HashMap::new();
+    {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        for (idx, text) in texts {
+            let pass = langid::untranslatable(text)
+                || keep_lang.is_some_and(|lang| langid::identify(text) == Some(lang));
+            if !pass {
+                continue;
+            }
+            results.insert(*idx, text.clone());
+            let source_hash = text_hash(text);
+            let saved = existing.get(&(*idx as i32)).is_some_and(|row| {
+                row.status == LineStatus::Skipped.as_str() && row.source_hash == source_hash
+            });
+            if !saved {
+                let row = queries::TranslationProgressRow {
+                    result_text: text.clone(),
+                    status: LineStatus::Skipped.as_str().to_string(),
+                    error: None,
+                    source_hash,
+                    prompt_hash: prompt_hash.clone(),
+                };
+                queries::save_translation_progress(&conn, project_dir, *idx as i32, phase, &row)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    let passed = results.len() as u32;
+
+    // Reuse saved lines only when both their input text and the prompt are unchanged;
+    // edited, split or re-transcribed subtitles shift indices and must not be trusted.
+    let mut todo: Vec<(usize, String)> = Vec::new();
+    let mut invalidated = 0u32;
+    for (idx, text) in texts {
+        if results.contains_key(idx) {
+            continue;
+        }
+        match existing.get(&(*idx as i32)) {
+            Some(row) if row.source_hash == text_hash(text) && row.prompt_hash == prompt_hash => {
+                results.insert(*idx, row.result_text.clone());
+            }
+            Some(_) => {
+                invalidated += 1;
+                todo.push((*idx, text.clone()));
+            }
+            None => todo.push((*idx, text.clone())),
+        }
+    }
+    todo.sort_by_key(|(idx, _)| *idx);
+    let reused = results.len() as u32 - passed;
+    let skipped = results.len() as u32;
+
+    if todo.is_empty() {
+        let _ = app.emit(
+            "translate:progress",
+            TranslateProgress {
+                phase: phase_label.to_string(),
+                batch: 0,
+                total_batches: 0,
+                skipped,
+                invalidated,
+                percent: phase_base_percent + phase_weight,
+                message: format!("{phase_label}: 全部已完成（断点续传）"),
+                cache: cache_counts(routes),
+            },
+        );
+        // Emit all existing results so the live subtitle list pre-fills on resume
+        let resume_updates: Vec<BatchUpdate> = results
+            .iter()
+            .map(|(idx, text)| BatchUpdate { index: *idx, text: text.clone() })
+            .collect();
+        let _ = app.emit(
+            "translate:batch_result",
+            TranslateBatchResult {
+                phase: phase_label.to_string(),
+                updates: resume_updates,
+            },
+        );
+        return Ok((results, ResumeCounts { reused, invalidated }));
+    }
+
+    let batches = plan_batches(&todo, batch_size, routes, system_prompt);
+    let total_batches = batches.len() as u32;
+
+    for (batch_idx, batch) in batches.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("已取消".to_string());
+        }
+
+        // Build items slice for API call, with inline tags swapped for placeholders
+        let protected: Vec<(usize, String, Vec<String>)> = batch
+            .iter()
+            .map(|(i, t)| {
+                let (text, tags) = markup::protect(t);
+                (*i, text, tags)
+            })
+            .collect();
+        let requests: Vec<(usize, &str)> =
+            protected.iter().map(|(i, t, _)| (*i, t.as_str())).collect();
+        let map = call_with_retry(pool, routes, cancel, system_prompt, &requests, temperature).await?;
+
+        // Save results to DB and collect
+        let items: Vec<(usize, &str)> = batch.iter().map(|(i, t)| (*i, t.as_str())).collect();
+        {
+            let conn = db.0.lock().map_err(|e| e.to_string())?;
+            for ((idx, text), (_, _, tags)) in items.iter().zip(&protected) {
+                let key = idx.to_string();
+                if let Some(line) = map.get(&key) {
+                    let restored = markup::restore(&line.text, tags, text);
+                    let row = queries::TranslationProgressRow {
+                        result_text: restored.clone(),
+                        status: line.status.as_str().to_string(),
+                        error: line.error.clone(),
+                        source_hash: text_hash(text),
+                        prompt_hash: prompt_hash.clone(),
+                    };
+                    queries::save_translation_progress(&conn, project_dir, *idx as i32, phase, &row)
+                        .map_err(|e| e.to_string())?;
+                    results.insert(*idx, restored);
+                } else {
+                    // Fallback: keep original text
+                    results.insert(*idx, text.to_string());
+                }
+            }
+        }
+
+        // Emit batch results for live subtitle preview
+        let batch_updates: Vec<BatchUpdate> = items
+            .iter()
+            .filter_map(|(idx, _)| {
+                results.get(idx).map(|text| BatchUpdate {
+                    index: *idx,
+                    text: text.clone(),
+                })
+            })
+            .collect();
+        let _ = app.emit(
+            "translate:batch_result",
+            TranslateBatchResult {
+                phase: phase_label.to_string(),
+                updates: batch_updates,
+            },
+        );
+
+        // Emit progress
+        let batch_num = batch_idx as u32 + 1;
+        let percent =
+            phase_base_percent + phase_weight * (batch_num as f64 / total_batches as f64);
+        let _ = app.emit(
+            "translate:progress",
+            TranslateProgress {
+                phase: phase_label.to_string(),
+                batch: batch_num,
+                total_batches,
+                skipped,
+                invalidated,
+                percent,
+                message: format!("{phase_label}: {batch_num}/{total_batches}"),
+                cache: cache_counts(routes),
+            },
+        );
+    }
+
+    Ok((results, ResumeCounts { reused, invalidated }))
+}
+
+// ── Pipeline ─────────────────────────────────────────────────────────────────
+
+/// Resolve `config_id`, or the default config when none is given.
+fn resolve_config(db: &DbState, config_id: Option<&str>) -> Result<ResolvedConfig, String> {
+    let (cfg, proxy) = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        let configs = queries::get_all_ai_configs(&conn).map_err(|e| e.to_string())?;
+        let cfg = match config_id.filter(|id| !id.is_empty()) {
+            Some(id) => configs
+                .into_iter()
+                .find(|c| c.id == id)
+                .ok_or_else(|| format!("AI 配置 {id} 不存在"))?,
+            None => configs
+                .into_iter()
+                .find(|c| c.is_default)
+                .ok_or_else(|| "未配置默认 AI 模型，请先在设置中添加".to_string())?,
+        };
+        cfg.check_keys()?;
+        let proxy = cfg.proxy.resolve(&conn)?;
+        (cfg, proxy)
+    };
+    let (concurrent_limit, rate_limit, tpm_limit) = ai_pool::config_limits(&cfg);
+    let api_keys = ai_pool::key_specs(&cfg);
+    // Budgets left unset fall back to what the model was probed to handle
+    let caps = ModelCapabilities::of(&cfg);
+    let max_input_tokens = match cfg.max_input_tokens {
+        n if n > 0 => n as usize,
+        _ => caps.input_budget(),
+    };
+    let max_output_tokens = match cfg.max_output_tokens {
+        n if n > 0 => n as usize,
+        _ => caps.max_output_tokens as usize,
+    };
+    Ok(ResolvedConfig {
+        id: cfg.id,
+        base_url: cfg.base_url,
+        api_key: cfg.api_key,
+        model: cfg.model,
+        concurrent_limit,
+        request_timeout: cfg.request_timeout as u64,
+        rate_limit,
+        max_input_tokens,
+        max_output_tokens,
+        tpm_limit,
+        api_keys,
+        proxy,
+        json_mode: caps.json_mode,
+    })
+}
+
+/// A response cache session for one run, per the cache settings.
+pub(crate) fn open_cache(db: &DbState, cache: &LlmCacheState) -> Result<Arc<CacheSession>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    Ok(Arc::new(CacheSession::open(&cache.0, &conn)?))
+}
+
+/// Cache counts of the run `routes` belong to.
+fn cache_counts(routes: &[Route]) -> CacheCounts {
+    routes.first().map(|r| r.cache.counts()).unwrap_or_default()
+}
+
+/// Open the project's LLM call journal in `journal_mode` ("off", "record" or
+/// "replay"). Every configured API key is redacted from it.
+pub(crate) fn open_journal(
+    db: &DbState,
+    project_dir: &str,
+    journal_mode: &str,
+) -> Result<Arc<Journal>, String> {
+    let mode = JournalMode::parse(journal_mode)?;
+    let secrets: Vec<String> = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::get_all_ai_configs(&conn)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .flat_map(|c| {
+                std::iter::once(c.api_key).chain(c.api_keys.into_iter().map(|k| k.api_key))
+            })
+            .collect()
+    };
+    Ok(Arc::new(Journal::open(project_dir, mode, secrets)?))
+}
+
+/// Fallback chains for `phases`: each phase's own config (or `default_id`, or the
+/// default config), followed by `opts.fallback_configs` without duplicates.
+async fn resolve_routes(
+    db: &DbState,
+    pool: &AiPoolManager,
+    opts: &TranslateOpts,
+    default_id: Option<&str>,
+    phases: &[&'static str],
+    journal: &Arc<Journal>,
+    cache: &Arc<CacheSession>,
+) -> Result<HashMap<&'static str, Vec<Route>>, String> {
+    let mut routes = HashMap::new();
+    for &phase in phases {
+        let primary = opts
+            .phase_configs
+            .get(phase)
+            .map(String::as_str)
+            .filter(|id| !id.is_empty())
+            .or(default_id);
+        let chain = resolve_chain(db, primary, &opts.fallback_configs)?;
+        let phase_routes = build_routes(pool, chain, "translate", phase, journal, cache).await?;
+        routes.insert(phase, phase_routes);
+    }
+    Ok(routes)
+}
+
+/// The configs of a fallback chain: `primary` (or the default config), followed
+/// by `fallbacks` without duplicates.
+pub(crate) fn resolve_chain(
+    db: &DbState,
+    primary: Option<&str>,
+    fallbacks: &[String],
+) -> Result<Vec<ResolvedConfig>, String> {
+    let mut chain = vec![resolve_config(db, primary)?];
+    for id in fallbacks {
+        if id.is_empty() || chain.iter().any(|c| &c.id == id) {
+            continue;
+        }
+        chain.push(resolve_config(db, Some(id))?);
+    }
+    Ok(chain)
+}
+
+/// Routes along `chain` for requests of `task`, journaled under `phase`, each with
+/// the config's pooled HTTP client and rate feedback.
+pub(crate) async fn build_routes(
+    pool: &AiPoolManager,
+    chain: Vec<ResolvedConfig>,
+    task: &'static str,
+    phase: &'static str,
+    journal: &Arc<Journal>,
+    cache: &Arc<CacheSession>,
+) -> Result<Vec<Route>, String> {
+    let mut routes = Vec::with_capacity(chain.len());
+    for cfg in chain {
+        let client = pool
+            .get_or_create_client(&cfg.id, cfg.request_timeout, &cfg.proxy)
+            .await?;
+        let feedback = pool
+            .feedback(
+                &cfg.id,
+                cfg.concurrent_limit,
+                cfg.rate_limit,
+                cfg.tpm_limit,
+                &cfg.api_keys,
+            )
+            .await;
+        routes.push(Route {
+            cfg,
+            client,
+            feedback,
+            task,
+            phase,
+            journal: Arc::clone(journal),
+            cache: Arc::clone(cache),
+            priority: Priority::Bulk,
+        });
+    }
+    Ok(routes)
+}
+
+fn translation_phase(opts: &TranslateOpts) -> Phase {
+    if opts.prompt_type == "reflective" {
+        Phase::Reflective
+    } else {
+        Phase::Standard
+    }
+}
+
+/// Progress phases written by `run_pipeline` for these options, in order.
+fn pipeline_phases(opts: &TranslateOpts) -> Vec<&'static str> {
+    let mut phases = Vec::new();
+    if opts.correction {
+        phases.push("correction");
+    }
+    phases.push("translation");
+    if opts.optimization {
+        phases.push("optimization");
+    }
+    if opts.review && opts.review_retranslate {
+        phases.push("retranslation");
+    }
+    phases
+}
+
+fn collect_line_stats(
+    db: &DbState,
+    project_dir: &str,
+    opts: &TranslateOpts,
+    resume: &HashMap<&str, ResumeCounts>,
+) -> Result<Vec<PhaseLineStats>, String> {
+    let counts = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::count_translation_statuses(&conn, project_dir).map_err(|e| e.to_string())?
+    };
+    let count = |phase: &str, status: LineStatus| -> u32 {
+        counts
+            .get(&(phase.to_string(), status.as_str().to_string()))
+            .copied()
+            .unwrap_or(0) as u32
+    };
+    Ok(pipeline_phases(opts)
+        .into_iter()
+        .map(|phase| {
+            let resumed = resume.get(phase).copied().unwrap_or_default();
+            PhaseLineStats {
+                phase: phase.to_string(),
+                translated: count(phase, LineStatus::Translated),
+                fallback: count(phase, LineStatus::Fallback),
+                failed: count(phase, LineStatus::Failed),
+                wrong_language: count(phase, LineStatus::WrongLanguage),
+                skipped: count(phase, LineStatus::Skipped),
+                reused: resumed.reused,
+                invalidated: resumed.invalidated,
+            }
+        })
+        .collect())
+}
+
+fn assemble_subtitles(
+    subtitles: &[SubtitleItem],
+    mut current: HashMap<usize, String>,
+) -> Vec<SubtitleItem> {
+    subtitles
+        .iter()
+        .enumerate()
+        .map(|(i, s)| SubtitleItem {
+            id: s.id,
+            start_time: s.start_time,
+            end_time: s.end_time,
+            text: current.remove(&i).unwrap_or_else(|| s.text.clone()),
+            words: Vec::new(),
+        })
+        .collect()
+}
+
+/// What a pipeline run produced; per-phase line stats are read back from the DB.
+struct PipelineResult {
+    subtitles: Vec<SubtitleItem>,
+    resume: HashMap<&'static str, ResumeCounts>,
+    language_issues: Vec<LanguageIssue>,
+    pending_corrections: usize,
+}
+
+async fn run_pipeline(
+    run: Run<'_>,
+    cache: &Arc<CacheSession>,
+    subtitles: &[SubtitleItem],
+    opts: &TranslateOpts,
+    default_config_id: Option<&str>,
+) -> Result<PipelineResult, String> {
+    let Run { app, db, pool, project_dir, .. } = run;
+    // Resolve every phase up front so a missing config fails before any API call;
+    // re-translation after review runs on the translation chain
+    let mut enabled = vec!["translation"];
+    if opts.correction {
+        enabled.push("correction");
+    }
+    if opts.optimization {
+        enabled.push("optimization");
+    }
+    if opts.review {
+        enabled.push("review");
+    }
+    let journal = open_journal(db, project_dir, &opts.journal_mode)?;
+    let routes =
+        resolve_routes(db, pool, opts, default_config_id, &enabled, &journal, cache).await?;
+    let mut resume: HashMap<&'static str, ResumeCounts> = HashMap::new();
+    let mut language_issues: Vec<LanguageIssue> = Vec::new();
+    let target = langid::parse_lang(&opts.target_language);
+
+    // Count enabled phases for percent distribution
+    let phase_count = opts.correction as u32
+        + 1 /* translation always */
+        + opts.optimization as u32
+        + opts.review as u32;
+    let phase_weight = 100.0 / phase_count as f64;
+    let mut phase_idx = 0u32;
+
+    // Current texts (evolves through phases)
+    let mut current: HashMap<usize, String> = subtitles
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (i, s.text.clone()))
+        .collect();
+
+    // Phase 1: Correction (conservative temperature)
+    if opts.correction {
+        let prompt = build_phase_prompt(Phase::Correction, opts);
+        let base = phase_idx as f64 * phase_weight;
+        let pass = Pass {
+            phase: "correction",
+            label: "校正",
+            base_percent: base,
+            weight: phase_weight,
+            batch_size: opts.batch_size,
+            temperature: 0.1,
+            keep_lang: None,
+        };
+        let (mut corrected, counts) =
+            process_batches(run, &routes["correction"], &prompt, &current, pass).await?;
+        // Correction must not change a line's language
+        let issues = revert_wrong_language(
+            &mut corrected,
+            &current,
+            |i| current.get(&i).and_then(|t| langid::identify(t)),
+            "correction",
+        );
+        emit_language_issues(app, "校正", &issues);
+        language_issues.extend(issues);
+        let pending = correction::record_corrections(db, project_dir, &current, &mut corrected)?;
+        current = corrected;
+        resume.insert("correction", counts);
+        phase_idx += 1;
+
+        // Hand the changes to the reviewer before anything is translated
+        if opts.correction_review && pending > 0 {
+            return Ok(PipelineResult {
+                subtitles: assemble_subtitles(subtitles, current),
+                resume,
+                language_issues,
+                pending_corrections: pending,
+            });
+        }
+    }
+
+    // Source text as seen by the translator (after correction), kept for review
+    let sources = current.clone();
+
+    // Phase 2: Translation (standard temperature)
+    {
+        let base = phase_idx as f64 * phase_weight;
+        let chain = &routes["translation"];
+        let (translated, counts, issues) =
+            translate_phase(run, chain, subtitles, &current, opts, base, phase_weight).await?;
+        emit_language_issues(app, "翻译", &issues);
+        language_issues.extend(issues);
+        current = translated;
+        resume.insert("translation", counts);
+        phase_idx += 1;
+    }
+
+    // Phase 3: Optimization (creative temperature)
+    if opts.optimization {
+        let prompt = build_phase_prompt(Phase::Optimize, opts);
+        let base = phase_idx as f64 * phase_weight;
+        let pass = Pass {
+            phase: "optimization",
+            label: "优化",
+            base_percent: base,
+            weight: phase_weight,
+            batch_size: opts.batch_size,
+            temperature: 0.5,
+            keep_lang: None,
+        };
+        let (mut optimized, counts) =
+            process_batches(run, &routes["optimization"], &prompt, &current, pass).await?;
+        resume.insert("optimization", counts);
+
+        // Lines the optimizer moved out of the target language keep their translation
+        let issues = revert_wrong_language(
+            &mut optimized,
+            &current,
+            |i| target.or_else(|| current.get(&i).and_then(|t| langid::identify(t))),
+            "optimization",
+        );
+        emit_language_issues(app, "优化", &issues);
+        language_issues.extend(issues);
+        current = optimized;
+        phase_idx += 1;
+    }
+
+    // Phase 4: Review (LLM-as-judge), optionally re-translating low-scoring lines
+    if opts.review {
+        let prompt = build_phase_prompt(Phase::Review, opts);
+        let base = phase_idx as f64 * phase_weight;
+        let review_weight = if opts.review_retranslate { phase_weight * 0.6 } else { phase_weight };
+        let pass = review_pass(opts, base, review_weight);
+        let threshold = opts.review_threshold;
+        let mut reviews =
+            review_batches(run, &routes["review"], &prompt, &sources, &current, threshold, pass)
+                .await?;
+
+        if opts.review_retranslate {
+            // Lines re-translated in an earlier (interrupted) run keep their new text,
+            // as long as their source is unchanged
+            let retranslate_prompt = build_phase_prompt(translation_phase(opts), opts);
+            let retranslate_hash = prompt_hash(&retranslate_prompt, 0.5);
+            let already: HashMap<i32, String> = {
+                let conn = db.0.lock().map_err(|e| e.to_string())?;
+                queries::get_translation_progress_rows(&conn, project_dir, "retranslation")
+                    .map_err(|e| e.to_string())?
+            }
+            .into_iter()
+            .filter(|(idx, row)| {
+                row.prompt_hash == retranslate_hash
+                    && sources
+                        .get(&(*idx as usize))
+                        .is_some_and(|src| row.source_hash == text_hash(src))
+            })
+            .map(|(idx, row)| (idx, row.result_text))
+            .collect();
+            for (idx, text) in &already {
+                current.insert(*idx as usize, text.clone());
+            }
+
+            let flagged: HashMap<usize, String> = reviews
+                .values()
+                .filter(|r| r.score < opts.review_threshold)
+                .filter(|r| !already.contains_key(&r.subtitle_index))
+                .filter_map(|r| {
+                    let idx = r.subtitle_index as usize;
+                    sources.get(&idx).map(|src| (idx, src.clone()))
+                })
+                .collect();
+
+            if !flagged.is_empty() {
+                let base = base + review_weight;
+                let pass = Pass {
+                    phase: "retranslation",
+                    label: "重译",
+                    base_percent: base,
+                    weight: phase_weight * 0.2,
+                    batch_size: opts.batch_size,
+                    temperature: 0.5,
+                    keep_lang: target,
+                };
+                let chain = &routes["translation"];
+                let (mut retranslated, counts) =
+                    process_batches(run, chain, &retranslate_prompt, &flagged, pass).await?;
+                resume.insert("retranslation", counts);
+                if let Some(target) = target {
+                    let issues = revert_wrong_language(
+                        &mut retranslated,
+                        &current,
+                        |_| Some(target),
+                        "retranslation",
+                    );
+                    emit_language_issues(app, "重译", &issues);
+                    language_issues.extend(issues);
+                }
+                // Failed lines come back as their source and reverted ones as the old
+                // translation; only the others replace the line and its review
+                let failed: Vec<usize> = {
+                    let conn = db.0.lock().map_err(|e| e.to_string())?;
+                    queries::get_translation_progress_rows(&conn, project_dir, "retranslation")
+                        .map_err(|e| e.to_string())?
+                }
+                .into_iter()
+                .filter(|(_, row)| row.status == LineStatus::Failed.as_str())
+                .map(|(idx, _)| idx as usize)
+                .collect();
+                let mut rereview: HashMap<usize, String> = HashMap::new();
+                for idx in flagged.keys().filter(|idx| !failed.contains(idx)) {
+                    match retranslated.get(idx) {
+                        Some(text) if current.get(idx) != Some(text) => {
+                            current.insert(*idx, text.clone());
+                            rereview.insert(*idx, text.clone());
+                        }
+                        _ => {}
+                    }
+                }
+                {
+                    let indices: Vec<i32> = rereview.keys().map(|i| *i as i32).collect();
+                    let conn = db.0.lock().map_err(|e| e.to_string())?;
+                    queries::delete_translation_reviews(&conn, project_dir, &indices)
+                        .map_err(|e| e.to_string())?;
+                }
+                let prompt = build_phase_prompt(Phase::Review, opts);
+                let pass = review_pass(opts, base + phase_weight * 0.2, phase_weight * 0.2);
+                let chain = &routes["review"];
+                let updated =
+                    review_batches(run, chain, &prompt, &sources, &rereview, threshold, pass)
+                        .await?;
+                reviews.extend(updated);
+            }
+        }
+
+        let flagged_count = reviews
+            .values()
+            .filter(|r| r.score < opts.review_threshold)
+            .count();
+        let _ = app.emit(
+            "translate:progress",
+            TranslateProgress {
+                phase: "审校".to_string(),
+                batch: 0,
+                total_batches: 0,
+                skipped: 0,
+                invalidated: 0,
+                percent: base + phase_weight,
+                message: format!("审校完成: {flagged_count} 行低于阈值"),
+                cache: cache_counts(&routes["review"]),
+            },
+        );
+    }
+
+    // Lines the user picked a candidate for keep that choice
+    apply_translation_memory(db, project_dir, opts, subtitles, &sources, &mut current)?;
+
+    Ok(PipelineResult {
+        subtitles: assemble_subtitles(subtitles, current),
+        resume,
+        language_issues,
+        pending_corrections: 0,
+    })
+}
+
+/// Re-run only the lines whose last result was a single-line fallback or a failure.
+/// A line redone in one phase is also redone in every later phase: its input changed,
+/// so the saved row no longer matches its source hash. Other lines are served from
+/// saved progress. Like the full pipeline, it stops after correction while changes
+/// await review.
+async fn retry_failed_lines(
+    run: Run<'_>,
+    cache: &Arc<CacheSession>,
+    subtitles: &[SubtitleItem],
+    opts: &TranslateOpts,
+    default_config_id: Option<&str>,
+) -> Result<PipelineResult, String> {
+    let Run { app, db, pool, project_dir, .. } = run;
+    let issues = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::get_translation_line_issues(&conn, project_dir).map_err(|e| e.to_string())?
+    };
+    let mut resume: HashMap<&'static str, ResumeCounts> = HashMap::new();
+    let mut language_issues: Vec<LanguageIssue> = Vec::new();
+    let target = langid::parse_lang(&opts.target_language);
+
+    let phases: Vec<(&'static str, &str, Phase, f64)> = [
+        ("correction", "校正", Phase::Correction, 0.1, opts.correction),
+        ("translation", "翻译", translation_phase(opts), 0.3, true),
+        ("optimization", "优化", Phase::Optimize, 0.5, opts.optimization),
+    ]
+    .into_iter()
+    .filter(|p| p.4)
+    .map(|(name, label, phase, temperature, _)| (name, label, phase, temperature))
+    .collect();
+    let phase_weight = 100.0 / phases.len() as f64;
+    let names: Vec<&'static str> = phases.iter().map(|p| p.0).collect();
+    let journal = open_journal(db, project_dir, &opts.journal_mode)?;
+    let mut routes =
+        resolve_routes(db, pool, opts, default_config_id, &names, &journal, cache).await?;
+    // The user asked for these lines and is waiting on them
+    prioritize(&mut routes);
+
+    let mut current: HashMap<usize, String> = subtitles
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (i, s.text.clone()))
+        .collect();
+    let mut sources: HashMap<usize, String> = current.clone();
+
+    for (phase_idx, (name, label, phase, temperature)) in phases.iter().enumerate() {
+        {
+            let indices: Vec<i32> = issues
+                .iter()
+                .filter(|i| i.phase == *name)
+                .map(|i| i.subtitle_index)
+                .collect();
+            let conn = db.0.lock().map_err(|e| e.to_string())?;
+            queries::delete_translation_progress_lines(&conn, project_dir, name, &indices)
+                .map_err(|e| e.to_string())?;
+        }
+        let base = phase_idx as f64 * phase_weight;
+        if *name == "translation" {
+            sources = current.clone();
+            let (output, counts, issues) =
+                translate_phase(run, &routes[name], subtitles, &current, opts, base, phase_weight)
+                    .await?;
+            resume.insert(name, counts);
+            emit_language_issues(app, label, &issues);
+            language_issues.extend(issues);
+            current = output;
+            continue;
+        }
+        let prompt = build_phase_prompt(*phase, opts);
+        let pass = Pass {
+            phase: name,
+            label,
+            base_percent: base,
+            weight: phase_weight,
+            batch_size: opts.batch_size,
+            temperature: *temperature,
+            keep_lang: None,
+        };
+        let (mut output, counts) =
+            process_batches(run, &routes[name], &prompt, &current, pass).await?;
+        resume.insert(name, counts);
+
+        // Same per-line language checks as the full pipeline
+        let issues = match *name {
+            "correction" => revert_wrong_language(
+                &mut output,
+                &current,
+                |i| current.get(&i).and_then(|t| langid::identify(t)),
+                name,
+            ),
+            _ => revert_wrong_language(
+                &mut output,
+                &current,
+                |i| target.or_else(|| current.get(&i).and_then(|t| langid::identify(t))),
+                name,
+            ),
+        };
+        emit_language_issues(app, label, &issues);
+        language_issues.extend(issues);
+        if *name == "correction" {
+            let pending = correction::record_corrections(db, project_dir, &current, &mut output)?;
+            current = output;
+            // New corrections go to the reviewer before anything is translated
+            if opts.correction_review && pending > 0 {
+                return Ok(PipelineResult {
+                    subtitles: assemble_subtitles(subtitles, current),
+                    resume,
+                    language_issues,
+                    pending_corrections: pending,
+                });
+            }
+            continue;
+        }
+        current = output;
+    }
+
+    // Keep review re-translations whose source is unchanged
+    if opts.review && opts.review_retranslate {
+        let retranslated = {
+            let conn = db.0.lock().map_err(|e| e.to_string())?;
+            queries::get_translation_progress_rows(&conn, project_dir, "retranslation")
+                .map_err(|e| e.to_string())?
+        };
+        for (idx, row) in retranslated {
+            let idx = idx as usize;
+            if sources.get(&idx).is_some_and(|src| row.source_hash == text_hash(src)) {
+                current.insert(idx, row.result_text);
+            }
+        }
+    }
+
+    // Lines the user picked a candidate for keep that choice
+    apply_translation_memory(db, project_dir, opts, subtitles, &sources, &mut current)?;
+
+    Ok(PipelineResult {
+        subtitles: assemble_subtitles(subtitles, current),
+        resume,
+        language_issues,
+        pending_corrections: 0,
+    })
+}
+
+// ── Tauri Commands ───────────────────────────────────────────────────────────
+
+#[tauri::command]
+pub async fn cmd_start_translation(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    pool: State<'_, AiPoolManager>,
+    llm_cache: State<'_, LlmCacheState>,
+    cancel: State<'_, TranslateCancelState>,
+    subtitles: Vec<SubtitleItem>,
+    project_dir: String,
+    mut options: TranslateOpts,
+) -> Result<TranslationOutput, String> {
+    // Reset cancel flag
+    cancel.0.store(false, Ordering::Relaxed);
+
+    check_opts(&options)?;
+    load_prompt_templates(&db, &mut options)?;
+    merge_project_glossary(&db, &project_dir, &mut options)?;
+    apply_content_summary(&db, &project_dir, &mut options)?;
+
+    let cache = open_cache(&db, &llm_cache)?;
+    let run = Run {
+        app: &app,
+        db: &db,
+        pool: &pool,
+        cancel: &cancel.0,
+        project_dir: &project_dir,
+    };
+    let result = run_pipeline(run, &cache, &subtitles, &options, None).await?;
+    let line_stats = collect_line_stats(&db, &project_dir, &options, &result.resume)?;
+    Ok(TranslationOutput {
+        subtitles: result.subtitles,
+        line_stats,
+        language_issues: result.language_issues,
+        pending_corrections: result.pending_corrections,
+    })
+}
+
+/// Re-translate only the lines left as fallback or failed by earlier runs,
+/// optionally with a different AI config (`request.aiConfigId`, default config
+/// otherwise) for phases that have no config of their own.
+#[tauri::command]
+pub async fn cmd_retry_failed_lines(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    pool: State<'_, AiPoolManager>,
+    llm_cache: State<'_, LlmCacheState>,
+    cancel: State<'_, TranslateCancelState>,
+    request: TranslateRequest,
+) -> Result<TranslationOutput, String> {
+    let TranslateRequest { subtitles, project_dir, mut options, ai_config_id } = request;
+    cancel.0.store(false, Ordering::Relaxed);
+    check_opts(&options)?;
+    load_prompt_templates(&db, &mut options)?;
+    merge_project_glossary(&db, &project_dir, &mut options)?;
+    apply_content_summary(&db, &project_dir, &mut options)?;
+    let cache = open_cache(&db, &llm_cache)?;
+    let run = Run {
+        app: &app,
+        db: &db,
+        pool: &pool,
+        cancel: &cancel.0,
+        project_dir: &project_dir,
+    };
+    let result =
+        retry_failed_lines(run, &cache, &subtitles, &options, ai_config_id.as_deref()).await?;
+    let line_stats = collect_line_stats(&db, &project_dir, &options, &result.resume)?;
+    Ok(TranslationOutput {
+        subtitles: result.subtitles,
+        line_stats,
+        language_issues: result.language_issues,
+        pending_corrections: result.pending_corrections,
+    })
+}
+
+/// Lines whose saved result is a fallback or a failure, with the last error.
+#[tauri::command]
+pub async fn cmd_get_translation_line_issues(
+    db: State<'_, DbState>,
+    project_dir: String,
+) -> Result<Vec<queries::TranslationLineIssue>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::get_translation_line_issues(&conn, &project_dir).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn cmd_cancel_translation(
+    cancel: State<'_, TranslateCancelState>,
+    pool: State<'_, AiPoolManager>,
+) -> Result<(), String> {
+    cancel.0.store(true, Ordering::Relaxed);
+    pool.wake_cancelled().await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn cmd_clear_translation_progress(
+    db: State<'_, DbState>,
+    project_dir: String,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::clear_translation_progress(&conn, &project_dir).map_err(|e| e.to_string())
+}
+
+/// Reviewed lines for a project, lowest score first. With `threshold`, only lines
+/// scoring below it (the flagged ones) and lines whose review failed are returned.
+#[tauri::command]
+pub async fn cmd_get_translation_reviews(
+    db: State<'_, DbState>,
+    project_dir: String,
+    threshold: Option<f64>,
+) -> Result<Vec<queries::TranslationReview>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let mut reviews =
+        queries::get_translation_reviews(&conn, &project_dir).map_err(|e| e.to_string())?;
+    if let Some(t) = threshold {
+        reviews.retain(|r| r.error.is_some() || r.score < t);
+    }
+    reviews.sort_by(|a, b| a.score.total_cmp(&b.score));
+    Ok(reviews)
+}
+
+/// Pre-pass before translation: have the LLM propose a glossary (names, places,
+/// products, terms with suggested translations) from the whole transcript. The
+/// proposals are stored for the user to edit and approve; terms approved earlier
+/// are kept as they are. Returns the project's full glossary and the chunks of
+/// the transcript that no proposals came back for.
+#[tauri::command]
+pub async fn cmd_extract_terminology(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    pool: State<'_, AiPoolManager>,
+    llm_cache: State<'_, LlmCacheState>,
+    cancel: State<'_, TranslateCancelState>,
+    request: TranslateRequest,
+    chunk_size: Option<usize>,
+) -> Result<TerminologyResult, String> {
+    let TranslateRequest { subtitles, project_dir, mut options, ai_config_id } = request;
+    cancel.0.store(false, Ordering::Relaxed);
+    load_prompt_templates(&db, &mut options)?;
+    apply_content_summary(&db, &project_dir, &mut options)?;
+    let journal = open_journal(&db, &project_dir, &options.journal_mode)?;
+    let cache = open_cache(&db, &llm_cache)?;
+    let routes = resolve_routes(
+        &db, &pool, &options, ai_config_id.as_deref(), &["terminology"], &journal, &cache,
+    )
+    .await?;
+    let prompt = build_phase_prompt(Phase::Terminology, &options);
+    let lines: Vec<&str> = subtitles.iter().map(|s| s.text.as_str()).collect();
+    let (terms, mut failures) = extract_terms(
+        &app, &pool, &routes["terminology"], &cancel.0, &prompt, &lines,
+        chunk_size.unwrap_or(DEFAULT_TERMINOLOGY_CHUNK),
+    )
+    .await?;
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::save_proposed_glossary_terms(&conn, &project_dir, &terms).map_err(|e| e.to_string())?;
+    Ok(TerminologyResult {
+        terms: queries::get_glossary_terms(&conn, &project_dir).map_err(|e| e.to_string())?,
+        failed_chunks: failures.len() as u32,
+        error: failures.pop(),
+    })
+}
+
+/// The project's glossary: approved terms first, then proposals by frequency.
+#[tauri::command]
+pub async fn cmd_get_glossary_terms(
+    db: State<'_, DbState>,
+    project_dir: String,
+) -> Result<Vec<queries::GlossaryTerm>, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::get_glossary_terms(&conn, &project_dir).map_err(|e| e.to_string())
+}
+
+/// Save the user-edited glossary. Approved terms feed the translation glossary.
+#[tauri::command]
+pub async fn cmd_save_glossary_terms(
+    db: State<'_, DbState>,
+    project_dir: String,
+    terms: Vec<queries::GlossaryTerm>,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    queries::replace_glossary_terms(&conn, &project_dir, &terms).map_err(|e| e.to_string())
+}
+
+/// Summarize the subtitles (topic, setting, speakers, tone) with the LLM and store
+/// the result on the project's workbench translate step, where it serves as the
+/// default world-building context of every phase. Returns the summary; fails
+/// before asking the model when no workbench task uses `project_dir`.
+#[tauri::command]
+pub async fn cmd_summarize_content(
+    app: AppHandle,
+    db: State<'_, DbState>,
+    pool: State<'_, AiPoolManager>,
+    llm_cache: State<'_, LlmCacheState>,
+    cancel: State<'_, TranslateCancelState>,
+    request: TranslateRequest,
+) -> Result<String, String> {
+    let TranslateRequest { subtitles, project_dir, mut options, ai_config_id } = request;
+    cancel.0.store(false, Ordering::Relaxed);
+    {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        if !queries::has_workbench_task(&conn, &project_dir).map_err(|e| e.to_string())? {
+            return Err(no_task_error(&project_dir));
+        }
+    }
+    load_prompt_templates(&db, &mut options)?;
+    // The summary replaces the world-building context, so it must not feed on itself
+    options.world_building.clear();
+    let journal = open_journal(&db, &project_dir, &options.journal_mode)?;
+    let cache = open_cache(&db, &llm_cache)?;
+    let routes = resolve_routes(
+        &db, &pool, &options, ai_config_id.as_deref(), &["summary"], &journal, &cache,
+    )
+    .await?;
+    let prompt = build_phase_prompt(Phase::Summary, &options);
+    let lines: Vec<&str> = subtitles.iter().map(|s| s.text.as_str()).collect();
+    let summary = summarize_content(
+        &app, &pool, &routes["summary"], &cancel.0, &prompt, &lines, DEFAULT_SUMMARY_CHUNK,
+    )
+    .await?;
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    if !queries::set_content_summary(&conn, &project_dir, &summary).map_err(|e| e.to_string())? {
+        return Err(no_task_error(&project_dir));
+    }
+    Ok(summary)
+}
+
+fn no_task_error(project_dir: &str) -> String {
+    format!("找不到项目目录对应的工作台任务: {project_dir}")
+}
+
+/// The stored content summary of the project, empty when none was generated.
+#[tauri::command]
+pub async fn cmd_get_content_summary(
+    db: State<'_, DbState>,
+    project_dir: String,
+) -> Result<String, String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let summary = queries::get_content_summary(&conn, &project_dir).map_err(|e| e.to_string())?;
+    Ok(summary.unwrap_or_default())
+}
+
+/// Save a user-edited content summary.
+#[tauri::command]
+pub async fn cmd_save_content_summary(
+    db: State<'_, DbState>,
+    project_dir: String,
+    summary: String,
+) -> Result<(), String> {
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    let stored = queries::set_content_summary(&conn, &project_dir, summary.trim())
+        .map_err(|e| e.to_string())?;
+    if !stored {
+        return Err(no_task_error(&project_dir));
+    }
+    Ok(())
+}
+
+/// Re-translate the selected lines following a user instruction ("more casual",
+/// "keep the pun"…) and return up to `count` alternatives per line. Context,
+/// glossary, content summary and config chain are resolved as for a full run.
+/// `request.subtitles` are the source lines and `selection.translations` the
+/// current translation.
+#[tauri::command]
+pub async fn cmd_retranslate_lines(
+    db: State<'_, DbState>,
+    pool: State<'_, AiPoolManager>,
+    llm_cache: State<'_, LlmCacheState>,
+    cancel: State<'_, TranslateCancelState>,
+    request: TranslateRequest,
+    selection: RetranslateRequest,
+) -> Result<Vec<LineCandidates>, String> {
+    let TranslateRequest { subtitles, project_dir, mut options, ai_config_id } = request;
+    let RetranslateRequest { translations, indices, instruction, count } = selection;
+    cancel.0.store(false, Ordering::Relaxed);
+    if let Some(bad) = indices.iter().find(|i| **i >= subtitles.len()) {
+        return Err(format!("字幕索引超出范围: {bad}"));
+    }
+    load_prompt_templates(&db, &mut options)?;
+    merge_project_glossary(&db, &project_dir, &mut options)?;
+    apply_content_summary(&db, &project_dir, &mut options)?;
+    let count = count.unwrap_or(DEFAULT_CANDIDATE_COUNT).clamp(1, 10);
+
+    let journal = open_journal(&db, &project_dir, &options.journal_mode)?;
+    let cache = open_cache(&db, &llm_cache)?;
+    let mut routes = resolve_routes(
+        &db, &pool, &options, ai_config_id.as_deref(), &["translation"], &journal, &cache,
+    )
+    .await?;
+    prioritize(&mut routes);
+    let rules = CANDIDATE_JSON_RULES.replace("{count}", &count.to_string());
+    let mut prompt = build_prompt_with_rules(translation_phase(&options), &options, &rules);
+    let instruction = instruction.trim();
+    if !instruction.is_empty() {
+        prompt.push_str(&format!("\n\n## User instruction\n{instruction}"));
+    }
+
+    let lines = CandidateLines {
+        sources: &subtitles,
+        translations: &translations,
+        indices: &indices,
+        count,
+    };
+    let routes = &routes["translation"];
+    request_candidates(&pool, routes, &cancel.0, &prompt, lines, options.batch_size).await
+}
+
+/// Write the chosen candidates into the project's translated subtitle file and
+/// remember them for their lines, so later runs keep them; choices marked
+/// `same_source` go into translation memory for every line with that text.
+/// Returns the updated subtitles.
+#[tauri::command]
+pub async fn cmd_apply_line_choices(
+    db: State<'_, DbState>,
+    project_dir: String,
+    target_language: String,
+    choices: Vec<LineChoice>,
+) -> Result<Vec<SubtitleItem>, String> {
+    let path = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::get_translated_subtitles_path(&conn, &project_dir)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_else(|| format!("{project_dir}/translated_subtitles.json"))
+    };
+    let json = std::fs::read_to_string(&path).map_err(|e| format!("读取翻译字幕失败: {e}"))?;
+    let mut subtitles: Vec<SubtitleItem> =
+        serde_json::from_str(&json).map_err(|e| format!("解析翻译字幕失败: {e}"))?;
+    for choice in &choices {
+        let line = subtitles
+            .get_mut(choice.index)
+            .ok_or_else(|| format!("字幕索引超出范围: {}", choice.index))?;
+        line.text = choice.text.clone();
+    }
+    let json = serde_json::to_string(&subtitles).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json).map_err(|e| format!("写入翻译字幕失败: {e}"))?;
+
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    for choice in &choices {
+        let (source, text) = (&choice.source, &choice.text);
+        if choice.same_source {
+            queries::save_translation_memory(&conn, &project_dir, source, &target_language, text)
+        } else {
+            let index = choice.index as i32;
+            queries::save_line_choice(&conn, &project_dir, index, source, &target_language, text)
+        }
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(subtitles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lenient_repairs_and_unwraps_replies() {
+        let plain: HashMap<String, String> = parse_lenient(r#"{"1": "a"}"#).unwrap();
+        assert_eq!(plain["1"], "a");
+        let trailing: HashMap<String, String> =
+            parse_lenient("{\"1\": \u{201C}a\u{201D},}").unwrap();
+        assert_eq!(trailing["1"], "a");
+        let wrapped: HashMap<String, String> =
+            parse_lenient("Here you go:\n```json\n{\"1\": \"a\",}\n```").unwrap();
+        assert_eq!(wrapped["1"], "a");
+        assert!(parse_lenient::<HashMap<String, String>>(&"无".repeat(300)).is_err());
+    }
+
+    fn batch_sizes(batches: &[Vec<(usize, String)>]) -> Vec<usize> {
+        batches.iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn split_by_budget_falls_back_to_fixed_size() {
+        let todo: Vec<(usize, String)> = (0..5).map(|i| (i, "line".to_string())).collect();
+        assert_eq!(batch_sizes(&split_by_budget(&todo, 2, None, None)), [2, 2, 1]);
+        assert_eq!(batch_sizes(&split_by_budget(&todo, 0, None, None)), [1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn split_by_budget_stays_within_token_budgets() {
+        // "abcd" is one token: 1 + 6 overhead in, 1 * 2 + 6 out
+        let todo: Vec<(usize, String)> = (0..5).map(|i| (i, "abcd".to_string())).collect();
+        assert_eq!(batch_sizes(&split_by_budget(&todo, 10, Some(14), None)), [2, 2, 1]);
+        assert_eq!(batch_sizes(&split_by_budget(&todo, 10, None, Some(24))), [3, 2]);
+        assert_eq!(batch_sizes(&split_by_budget(&todo, 2, Some(100), Some(100))), [2, 2, 1]);
+        let indices: Vec<usize> =
+            split_by_budget(&todo, 10, Some(14), None).concat().iter().map(|l| l.0).collect();
+        assert_eq!(indices, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn split_by_budget_gives_an_oversized_line_its_own_batch() {
+        let todo = vec![(0, "abcd".repeat(50)), (1, "abcd".to_string()), (2, "abcd".to_string())];
+        assert_eq!(batch_sizes(&split_by_budget(&todo, 10, Some(20), None)), [1, 2]);
+    }
+}
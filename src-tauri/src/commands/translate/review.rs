@@ -0,0 +1,253 @@
+//! Review phase: the model scores translated lines against their source, and
+//! low-scoring lines are flagged for re-translation.
+
+use crate::db::queries;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use tauri::Emitter;
+
+use super::{
+    cache_counts, call_along_routes, parse_lenient, text_hash, ChatPrompt, Pass, ReviewUpdate,
+    Route, Run, TranslateOpts, TranslateProgress, TranslateReviewResult,
+};
+
+pub(super) const REVIEW_JSON_RULES: &str = r#"
+## Rules
+- Input format: JSON object {"index": {"source": "...", "translation": "..."}, ...}
+- Output format: JSON object {"index": {"accuracy": 1-10, "fluency": 1-10, "terminology": 1-10, "reason": "..."}, ...}
+- Output count MUST exactly match input count
+- Output ONLY the JSON object, no extra text or explanation"#;
+
+pub(super) const DEFAULT_REVIEW_THRESHOLD: f64 = 7.0;
+
+#[derive(Deserialize)]
+struct LineReview {
+    accuracy: f64,
+    fluency: f64,
+    terminology: f64,
+    #[serde(default)]
+    reason: String,
+}
+
+impl LineReview {
+    fn into_review(self, index: usize, content_hash: String) -> queries::TranslationReview {
+        let clamp = |v: f64| v.clamp(0.0, 10.0);
+        let (accuracy, fluency, terminology) =
+            (clamp(self.accuracy), clamp(self.fluency), clamp(self.terminology));
+        queries::TranslationReview {
+            subtitle_index: index as i32,
+            accuracy,
+            fluency,
+            terminology,
+            score: (accuracy + fluency + terminology) / 3.0,
+            reason: self.reason.trim().to_string(),
+            content_hash,
+            error: None,
+        }
+    }
+}
+
+/// The record of a line whose review batch failed on every config.
+fn failed_review(index: usize, content_hash: String, error: &str) -> queries::TranslationReview {
+    queries::TranslationReview {
+        subtitle_index: index as i32,
+        accuracy: 0.0,
+        fluency: 0.0,
+        terminology: 0.0,
+        score: 0.0,
+        reason: String::new(),
+        content_hash,
+        error: Some(error.to_string()),
+    }
+}
+
+fn parse_review_map(
+    raw: &str,
+    expected_count: usize,
+) -> Result<HashMap<String, LineReview>, String> {
+    let map: HashMap<String, LineReview> = parse_lenient(raw)?;
+    if map.len() != expected_count {
+        return Err(format!("条目数不匹配: 期望{expected_count}, 实际{}", map.len()));
+    }
+    Ok(map)
+}
+
+fn build_review_content(items: &[(usize, &str, &str)]) -> String {
+    let map: HashMap<String, serde_json::Value> = items
+        .iter()
+        .map(|(i, src, tr)| {
+            (i.to_string(), serde_json::json!({ "source": src, "translation": tr }))
+        })
+        .collect();
+    serde_json::to_string(&map).unwrap_or_default()
+}
+
+fn to_review_update(review: &queries::TranslationReview, threshold: f64) -> ReviewUpdate {
+    ReviewUpdate {
+        index: review.subtitle_index as usize,
+        accuracy: review.accuracy,
+        fluency: review.fluency,
+        terminology: review.terminology,
+        score: review.score,
+        reason: review.reason.clone(),
+        flagged: review.error.is_none() && review.score < threshold,
+        error: review.error.clone(),
+    }
+}
+
+/// The review pass over the `weight` percent of the progress bar from `base`.
+pub(super) fn review_pass(opts: &TranslateOpts, base: f64, weight: f64) -> Pass<'static> {
+    Pass {
+        phase: "review",
+        label: "审校",
+        base_percent: base,
+        weight,
+        batch_size: opts.batch_size,
+        temperature: 0.1,
+        keep_lang: None,
+    }
+}
+
+/// Score each translated line against its source. Reviews are persisted per line
+/// so an interrupted run resumes where it stopped; batches that keep failing are
+/// recorded as failed, not failing the whole translation, and reviewed again on
+/// the next run. Only scored lines are returned.
+pub(super) async fn review_batches(
+    run: Run<'_>,
+    routes: &[Route],
+    system_prompt: &str,
+    sources: &HashMap<usize, String>,
+    translations: &HashMap<usize, String>,
+    threshold: f64,
+    pass: Pass<'_>,
+) -> Result<HashMap<usize, queries::TranslationReview>, String> {
+    let Run { app, db, pool, cancel, project_dir } = run;
+    let Pass {
+        label: phase_label,
+        base_percent: phase_base_percent,
+        weight: phase_weight,
+        batch_size,
+        temperature,
+        ..
+    } = pass;
+    let existing = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::get_translation_reviews(&conn, project_dir).map_err(|e| e.to_string())?
+    };
+    let content_hash = |idx: &usize| -> String {
+        let src = sources.get(idx).map(String::as_str).unwrap_or("");
+        let tr = translations.get(idx).map(String::as_str).unwrap_or("");
+        text_hash(&format!("{src}\u{1f}{tr}\u{1f}{system_prompt}"))
+    };
+    // A score only applies to the exact source/translation pair it was given
+    let mut results: HashMap<usize, queries::TranslationReview> = existing
+        .into_iter()
+        .map(|r| (r.subtitle_index as usize, r))
+        .filter(|(idx, r)| {
+            r.error.is_none()
+                && translations.contains_key(idx)
+                && r.content_hash == content_hash(idx)
+        })
+        .collect();
+
+    let mut todo: Vec<usize> = translations
+        .keys()
+        .filter(|idx| !results.contains_key(idx))
+        .copied()
+        .collect();
+    todo.sort_unstable();
+    let skipped = results.len() as u32;
+
+    if !results.is_empty() {
+        let _ = app.emit(
+            "translate:review_result",
+            TranslateReviewResult {
+                phase: phase_label.to_string(),
+                reviews: results.values().map(|r| to_review_update(r, threshold)).collect(),
+            },
+        );
+    }
+
+    let batches: Vec<&[usize]> = todo.chunks(batch_size.max(1)).collect();
+    let total_batches = batches.len() as u32;
+
+    for (batch_idx, batch) in batches.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("已取消".to_string());
+        }
+
+        let items: Vec<(usize, &str, &str)> = batch
+            .iter()
+            .map(|idx| {
+                let src = sources.get(idx).map(String::as_str).unwrap_or("");
+                let tr = translations.get(idx).map(String::as_str).unwrap_or("");
+                (*idx, src, tr)
+            })
+            .collect();
+        let content = build_review_content(&items);
+
+        let prompt = ChatPrompt::json(system_prompt, &content, temperature);
+        let parse = |raw: &str| parse_review_map(raw, items.len());
+        let (mut batch_reviews, last_error) =
+            match call_along_routes(pool, routes, cancel, "review", prompt, parse).await? {
+                Ok(map) => {
+                    let reviews = map
+                        .into_iter()
+                        .filter_map(|(k, v)| {
+                            let idx = k.parse::<usize>().ok()?;
+                            batch.contains(&idx).then(|| v.into_review(idx, content_hash(&idx)))
+                        })
+                        .collect();
+                    (reviews, String::new())
+                }
+                Err(e) => (Vec::new(), e),
+            };
+        let reviewed: Vec<usize> =
+            batch_reviews.iter().map(|r| r.subtitle_index as usize).collect();
+        if reviewed.len() < batch.len() {
+            let error = if last_error.is_empty() { "审校结果缺少该行".to_string() } else { last_error };
+            for idx in batch.iter().filter(|idx| !reviewed.contains(idx)) {
+                batch_reviews.push(failed_review(*idx, content_hash(idx), &error));
+            }
+        }
+
+        {
+            let conn = db.0.lock().map_err(|e| e.to_string())?;
+            for review in &batch_reviews {
+                queries::save_translation_review(&conn, project_dir, review)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        let _ = app.emit(
+            "translate:review_result",
+            TranslateReviewResult {
+                phase: phase_label.to_string(),
+                reviews: batch_reviews.iter().map(|r| to_review_update(r, threshold)).collect(),
+            },
+        );
+        for review in batch_reviews.into_iter().filter(|r| r.error.is_none()) {
+            results.insert(review.subtitle_index as usize, review);
+        }
+
+        let batch_num = batch_idx as u32 + 1;
+        let percent =
+            phase_base_percent + phase_weight * (batch_num as f64 / total_batches as f64);
+        let _ = app.emit(
+            "translate:progress",
+            TranslateProgress {
+                phase: phase_label.to_string(),
+                batch: batch_num,
+                total_batches,
+                skipped,
+                invalidated: 0,
+                percent,
+                message: format!("{phase_label}: {batch_num}/{total_batches}"),
+                cache: cache_counts(routes),
+            },
+        );
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,410 @@
+//! Sentence mode: cues that form one sentence are translated together and the
+//! translation is spread back over the cues.
+
+use crate::langid;
+use crate::markup;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+use super::language::retry_wrong_language;
+
+use super::{
+    build_phase_prompt, call_along_routes, call_with_retry, parse_lenient, process_batches,
+    translation_phase, ChatPrompt, LanguageIssue, Pass, ResumeCounts, Route, Run, SubtitleItem,
+    TranslateOpts,
+};
+
+const REDISTRIBUTE_CORE: &str = "You split translated sentences back over the subtitle cues they were spoken in. For each item you get the source cues in order and the translation of the whole sentence. Divide the translation into exactly as many parts as there are cues, so that each part carries what its cue says and reads naturally on screen. Keep the translation's words and order; do not add, drop or rephrase anything.";
+
+const REDISTRIBUTE_JSON_RULES: &str = r#"
+## Rules
+- Input format: JSON object {"index": {"cues": ["source cue", ...], "translation": "..."}, ...}
+- Output format: JSON object {"index": ["part for cue 1", "part for cue 2", ...], ...}
+- Each array must have exactly as many non-empty parts as "cues"
+- Output ONLY the JSON object, no extra text or explanation"#;
+
+/// Cues further apart than this (seconds) never form one sentence.
+const SENTENCE_MAX_GAP: f64 = 1.0;
+/// Most cues joined into one sentence.
+const SENTENCE_MAX_CUES: usize = 4;
+
+fn ends_sentence(text: &str) -> bool {
+    text.trim_end()
+        .trim_end_matches(['"', '\'', ')', '）', '」', '』', '”', '’'])
+        .ends_with(['.', '!', '?', '。', '！', '？', '…', ';', '；', '♪'])
+}
+
+pub(super) fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}' | '\u{3400}'..='\u{9FFF}' | '\u{AC00}'..='\u{D7AF}' | '\u{FF00}'..='\u{FFEF}' | '\u{3000}'..='\u{303F}')
+}
+
+/// Join cue texts, with a space only where both sides are not CJK.
+fn join_cues<'a>(parts: impl Iterator<Item = &'a str>) -> String {
+    let mut out = String::new();
+    for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+        let glue = match (out.chars().last(), part.chars().next()) {
+            (Some(a), Some(b)) => !(is_cjk(a) && is_cjk(b)),
+            _ => false,
+        };
+        if glue {
+            out.push(' ');
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+/// When speech in a cue starts and ends: its first and last word when word timings
+/// are known, otherwise the cue's own times (which often include padding).
+fn speech_span(sub: &SubtitleItem) -> (f64, f64) {
+    match (sub.words.first(), sub.words.last()) {
+        (Some(first), Some(last)) => (first.start, last.end),
+        _ => (sub.start_time, sub.end_time),
+    }
+}
+
+/// Group consecutive cues into sentences: a cue continues the previous one when
+/// that one lacks closing punctuation and the pause between them is short. Each
+/// group lists cue indices; with sentence mode off every cue is its own group.
+fn sentence_groups(subtitles: &[SubtitleItem], texts: &HashMap<usize, String>, opts: &TranslateOpts) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, sub) in subtitles.iter().enumerate() {
+        let continues = !opts.sentence_mode.is_empty()
+            && groups.last().is_some_and(|g| {
+                let prev = g[g.len() - 1];
+                g.len() < SENTENCE_MAX_CUES
+                    && !texts.get(&prev).is_some_and(|t| ends_sentence(t))
+                    && speech_span(sub).0 - speech_span(&subtitles[prev]).1 <= SENTENCE_MAX_GAP
+            });
+        match groups.last_mut() {
+            Some(group) if continues => group.push(i),
+            _ => groups.push(vec![i]),
+        }
+    }
+    groups
+}
+
+/// Sentence texts keyed by the index of their first cue.
+fn sentence_texts(groups: &[Vec<usize>], texts: &HashMap<usize, String>) -> HashMap<usize, String> {
+    groups
+        .iter()
+        .map(|g| (g[0], join_cues(g.iter().filter_map(|i| texts.get(i).map(String::as_str)))))
+        .collect()
+}
+
+/// Char offsets where a translation may be cut: after whitespace or punctuation.
+/// Text without spaces (CJK) may also be cut between any two characters.
+fn break_points(chars: &[char]) -> (Vec<usize>, Vec<usize>) {
+    let mut strong = Vec::new();
+    let mut weak = Vec::new();
+    let spaced = chars.iter().any(|c| c.is_whitespace());
+    for i in 1..chars.len() {
+        let prev = chars[i - 1];
+        if (prev.is_ascii_punctuation() && prev != '\'' && !chars[i].is_ascii_punctuation())
+            || "，、。！？；：…—".contains(prev)
+        {
+            strong.push(i);
+        } else if (spaced && prev.is_whitespace()) || (!spaced && !chars[i].is_whitespace()) {
+            weak.push(i);
+        }
+    }
+    (strong, weak)
+}
+
+/// Split `translation` into `weights.len()` parts sized like the weights, cutting
+/// at the break point nearest each ideal position (punctuation preferred). `None`
+/// when the translation is too short to give every part some text.
+fn split_proportionally(translation: &str, weights: &[usize]) -> Option<Vec<String>> {
+    let chars: Vec<char> = translation.trim().chars().collect();
+    let n = weights.len();
+    if n <= 1 {
+        return Some(vec![chars.iter().collect()]);
+    }
+    if chars.len() < n {
+        return None;
+    }
+    let total_weight: usize = weights.iter().map(|w| (*w).max(1)).sum();
+    let (strong, weak) = break_points(&chars);
+    let window = (chars.len() / (4 * n)).max(2);
+    let nearest = |points: &[usize], ideal: usize, range: (usize, usize), max_dist: usize| {
+        points
+            .iter()
+            .copied()
+            .filter(|p| *p > range.0 && *p <= range.1 && p.abs_diff(ideal) <= max_dist)
+            .min_by_key(|p| p.abs_diff(ideal))
+    };
+
+    let mut cuts = Vec::with_capacity(n);
+    let mut acc = 0;
+    let mut last = 0;
+    for (k, w) in weights[..n - 1].iter().enumerate() {
+        acc += (*w).max(1);
+        let ideal = chars.len() * acc / total_weight;
+        // Leave at least a character for each part still to come
+        let range = (last, chars.len() - (n - 1 - k));
+        let cut = nearest(&strong, ideal, range, window)
+            .or_else(|| nearest(&weak, ideal, range, usize::MAX))
+            .unwrap_or(ideal.clamp(range.0 + 1, range.1));
+        cuts.push(cut);
+        last = cut;
+    }
+    cuts.push(chars.len());
+
+    let mut start = 0;
+    let parts: Vec<String> = cuts
+        .into_iter()
+        .map(|end| {
+            let part: String = chars[start..end].iter().collect();
+            start = end;
+            part.trim().to_string()
+        })
+        .collect();
+    parts.iter().all(|p| !p.is_empty()).then_some(parts)
+}
+
+/// How much of a sentence's translation each cue of `group` gets: its share of the
+/// speaking time when every cue has word timings, otherwise of the source text.
+fn cue_weights(
+    group: &[usize],
+    subtitles: &[SubtitleItem],
+    sources: &HashMap<usize, String>,
+) -> Vec<usize> {
+    let timed = group.iter().all(|i| subtitles.get(*i).is_some_and(|s| !s.words.is_empty()));
+    group
+        .iter()
+        .map(|i| {
+            if timed {
+                let words = &subtitles[*i].words;
+                let spoken: f64 = words.iter().map(|w| (w.end - w.start).max(0.0)).sum();
+                (spoken * 1000.0).round() as usize
+            } else {
+                sources.get(i).map_or(0, |s| s.trim().chars().count())
+            }
+        })
+        .collect()
+}
+
+/// Letters and digits only, to check a split kept every word of the translation.
+fn words_only(text: &str) -> String {
+    text.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+/// Ask the model to split a batch of sentences over their cues. Splits that do not
+/// have one non-empty part per cue, or that change the words, are left out.
+async fn redistribute_with_llm(
+    run: Run<'_>,
+    routes: &[Route],
+    system_prompt: &str,
+    items: &[(&Vec<usize>, &str)],
+    sources: &HashMap<usize, String>,
+) -> Result<HashMap<usize, Vec<String>>, String> {
+    let request: HashMap<String, serde_json::Value> = items
+        .iter()
+        .map(|(group, translation)| {
+            let cues: Vec<&str> =
+                group.iter().map(|i| sources.get(i).map(String::as_str).unwrap_or("")).collect();
+            (group[0].to_string(), serde_json::json!({ "cues": cues, "translation": translation }))
+        })
+        .collect();
+    let content = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+
+    let Run { pool, cancel, .. } = run;
+    let prompt = ChatPrompt::json(system_prompt, &content, 0.1);
+    let mut parsed: HashMap<String, Vec<String>> =
+        call_along_routes(pool, routes, cancel, "redistribute", prompt, parse_lenient)
+            .await?
+            .unwrap_or_default();
+
+    Ok(items
+        .iter()
+        .filter_map(|(group, translation)| {
+            let parts = parsed.remove(&group[0].to_string())?;
+            let parts: Vec<String> = parts.iter().map(|p| p.trim().to_string()).collect();
+            let complete = parts.len() == group.len()
+                && parts.iter().all(|p| !p.is_empty())
+                && words_only(&parts.concat()) == words_only(translation);
+            complete.then_some((group[0], parts))
+        })
+        .collect())
+}
+
+/// Cues grouped into sentences: `groups` of cue indices into `subtitles` and
+/// `sources` (the cue texts), and `texts` with each group's joined text keyed by
+/// its first cue.
+struct Sentences<'a> {
+    subtitles: &'a [SubtitleItem],
+    sources: &'a HashMap<usize, String>,
+    groups: Vec<Vec<usize>>,
+    texts: HashMap<usize, String>,
+}
+
+/// Spread sentence translations (keyed by first cue) back over the cues of each
+/// group. Sentences that failed to translate keep their original cue texts; those
+/// too short to spread over their cues are translated again cue by cue.
+async fn redistribute_sentences(
+    run: Run<'_>,
+    routes: &[Route],
+    opts: &TranslateOpts,
+    system_prompt: &str,
+    sentences: Sentences<'_>,
+    translated: HashMap<usize, String>,
+) -> Result<HashMap<usize, String>, String> {
+    let Run { pool, cancel, .. } = run;
+    let Sentences { subtitles, sources, groups, texts: sentences } = sentences;
+    let mut out = HashMap::new();
+    let mut multi: Vec<(&Vec<usize>, &str)> = Vec::new();
+    for group in &groups {
+        let head = group[0];
+        let Some(text) = translated.get(&head) else { continue };
+        if group.len() == 1 {
+            out.insert(head, text.clone());
+        } else if sentences.get(&head) == Some(text) {
+            for i in group {
+                if let Some(src) = sources.get(i) {
+                    out.insert(*i, src.clone());
+                }
+            }
+        } else {
+            multi.push((group, text.as_str()));
+        }
+    }
+
+    let mut llm_parts = HashMap::new();
+    if opts.sentence_mode == "llm" && !multi.is_empty() {
+        let prompt = format!(
+            "Target language: {}\n\n{REDISTRIBUTE_CORE}\n{REDISTRIBUTE_JSON_RULES}",
+            opts.target_language
+        );
+        for chunk in multi.chunks(opts.batch_size.max(1)) {
+            if cancel.load(Ordering::Relaxed) {
+                return Err("已取消".to_string());
+            }
+            llm_parts.extend(redistribute_with_llm(run, routes, &prompt, chunk, sources).await?);
+        }
+    }
+
+    let mut unsplit: Vec<usize> = Vec::new();
+    for (group, text) in multi {
+        let parts = llm_parts
+            .remove(&group[0])
+            .or_else(|| split_proportionally(text, &cue_weights(group, subtitles, sources)));
+        match parts {
+            Some(parts) => out.extend(group.iter().copied().zip(parts)),
+            None => unsplit.extend(group),
+        }
+    }
+
+    for chunk in unsplit.chunks(opts.batch_size.max(1)) {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("已取消".to_string());
+        }
+        let protected: Vec<(usize, String, Vec<String>)> = chunk
+            .iter()
+            .filter_map(|i| {
+                let (text, tags) = markup::protect(sources.get(i)?);
+                Some((*i, text, tags))
+            })
+            .collect();
+        let requests: Vec<(usize, &str)> =
+            protected.iter().map(|(i, t, _)| (*i, t.as_str())).collect();
+        let mut prompt = system_prompt.to_string();
+        if protected.iter().any(|(_, _, tags)| !tags.is_empty()) {
+            prompt.push_str(markup::PLACEHOLDER_RULE);
+        }
+        let map = call_with_retry(pool, routes, cancel, &prompt, &requests, 0.3).await?;
+        for (idx, _, tags) in &protected {
+            let source = &sources[idx];
+            let text = map.get(&idx.to_string()).map_or(source.as_str(), |line| &line.text);
+            out.insert(*idx, markup::restore(text, tags, source));
+        }
+    }
+    Ok(out)
+}
+
+/// The translation phase: cue by cue, or in sentence mode whole sentences that are
+/// then redistributed over their cues. Lines in the wrong language are retried.
+pub(super) async fn translate_phase(
+    run: Run<'_>,
+    routes: &[Route],
+    subtitles: &[SubtitleItem],
+    current: &HashMap<usize, String>,
+    opts: &TranslateOpts,
+    base: f64,
+    weight: f64,
+) -> Result<(HashMap<usize, String>, ResumeCounts, Vec<LanguageIssue>), String> {
+    let prompt = build_phase_prompt(translation_phase(opts), opts);
+    let groups = sentence_groups(subtitles, current, opts);
+    let sentences = (groups.len() < current.len()).then(|| Sentences {
+        subtitles,
+        sources: current,
+        texts: sentence_texts(&groups, current),
+        groups,
+    });
+    let input = sentences.as_ref().map_or(current, |s| &s.texts);
+
+    let pass = Pass {
+        phase: "translation",
+        label: "翻译",
+        base_percent: base,
+        weight,
+        batch_size: opts.batch_size,
+        temperature: 0.3,
+        keep_lang: langid::parse_lang(&opts.target_language),
+    };
+    let (mut translated, counts) = process_batches(run, routes, &prompt, input, pass).await?;
+    let issues =
+        retry_wrong_language(run, routes, &prompt, input, &mut translated, opts, base + weight)
+            .await?;
+
+    if let Some(sentences) = sentences {
+        translated =
+            redistribute_sentences(run, routes, opts, &prompt, sentences, translated).await?;
+    }
+    Ok((translated, counts, issues))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::transcribe::WordTiming;
+
+    fn cue(words: &[(f64, f64)]) -> SubtitleItem {
+        SubtitleItem {
+            id: 0,
+            start_time: 0.0,
+            end_time: 0.0,
+            text: String::new(),
+            words: words
+                .iter()
+                .map(|&(start, end)| WordTiming { start, end, text: "w".to_string() })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn split_proportionally_cuts_at_punctuation_near_the_weights() {
+        let parts = split_proportionally("我们今天去公园，然后回家吃饭。", &[4, 4]).unwrap();
+        assert_eq!(parts, ["我们今天去公园，", "然后回家吃饭。"]);
+        let parts = split_proportionally("I went home, and then I slept", &[3, 3]).unwrap();
+        assert_eq!(parts, ["I went home,", "and then I slept"]);
+    }
+
+    #[test]
+    fn split_proportionally_gives_every_cue_text() {
+        // Weights that put every cut at the start still leave a word per cue
+        let parts = split_proportionally("one two three", &[0, 0, 100]).unwrap();
+        assert_eq!(parts.len(), 3);
+        assert!(parts.iter().all(|p| !p.is_empty()));
+        assert_eq!(parts.join(" "), "one two three");
+        assert_eq!(split_proportionally("好", &[1, 1]), None);
+    }
+
+    #[test]
+    fn cue_weights_prefer_word_timings() {
+        let sources = HashMap::from([(0, "aaaa".to_string()), (1, "b".to_string())]);
+        let timed = [cue(&[(0.0, 0.5)]), cue(&[(1.0, 2.0), (2.0, 2.5)])];
+        assert_eq!(cue_weights(&[0, 1], &timed, &sources), [500, 1500]);
+        let untimed = [cue(&[(0.0, 0.5)]), cue(&[])];
+        assert_eq!(cue_weights(&[0, 1], &untimed, &sources), [4, 1]);
+    }
+}
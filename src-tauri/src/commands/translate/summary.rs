@@ -0,0 +1,96 @@
+//! Content summary: background notes on the whole transcript, built chunk by
+//! chunk and handed to later runs as world-building context.
+
+use crate::ai_pool::AiPoolManager;
+use crate::db::connection::DbState;
+use crate::db::queries;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+
+use super::{
+    build_user_content, cache_counts, call_along_routes, ChatPrompt, Route, TranslateOpts,
+    TranslateProgress,
+};
+
+pub(super) const DEFAULT_SUMMARY_CORE: &str = "You are preparing background notes for the translators of these subtitles. Summarize the content: the topic, the setting, the speakers and their relationships, and the tone and register of the dialogue. Name people and places as they appear in the subtitles. Keep it under 200 words.";
+
+pub(super) const SUMMARY_RULES: &str = r#"
+## Rules
+- Input format: optionally "Summary so far:" followed by an earlier summary, then "Subtitles:" and a JSON object {"index": "text", ...}
+- When an earlier summary is given, extend and correct it with the new subtitles instead of starting over
+- Write the summary in the target language as plain text with the headings Topic, Setting, Speakers, Tone
+- Output ONLY the summary, no extra text or explanation"#;
+
+/// Subtitle lines sent per summary request; longer transcripts are summarized
+/// incrementally.
+pub(super) const DEFAULT_SUMMARY_CHUNK: usize = 400;
+
+/// Summarize the transcript chunk by chunk, each request refining the summary of
+/// the chunks before it.
+pub(super) async fn summarize_content(
+    app: &AppHandle,
+    pool: &AiPoolManager,
+    routes: &[Route],
+    cancel: &Arc<AtomicBool>,
+    system_prompt: &str,
+    lines: &[&str],
+    chunk_size: usize,
+) -> Result<String, String> {
+    let phase_label = "内容摘要";
+    let indexed: Vec<(usize, &str)> = lines.iter().copied().enumerate().collect();
+    let chunks: Vec<&[(usize, &str)]> = indexed.chunks(chunk_size.max(1)).collect();
+    let total_batches = chunks.len() as u32;
+    let mut summary = String::new();
+
+    for (chunk_idx, chunk) in chunks.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("已取消".to_string());
+        }
+        let subtitles = build_user_content(chunk);
+        let content = if summary.is_empty() {
+            format!("Subtitles:\n{subtitles}")
+        } else {
+            format!("Summary so far:\n{summary}\n\nSubtitles:\n{subtitles}")
+        };
+        let parse = |raw: &str| -> Result<String, String> {
+            let text = raw.trim();
+            if text.is_empty() { Err("摘要为空".to_string()) } else { Ok(text.to_string()) }
+        };
+
+        let prompt = ChatPrompt::text(system_prompt, &content, 0.3);
+        // Unlike per-line work there is nothing to fall back to, so a failed chunk fails
+        summary = call_along_routes(pool, routes, cancel, "summary", prompt, parse)
+            .await?
+            .map_err(|e| format!("{phase_label}失败: {e}"))?;
+
+        let batch_num = chunk_idx as u32 + 1;
+        let _ = app.emit(
+            "translate:progress",
+            TranslateProgress {
+                phase: phase_label.to_string(),
+                batch: batch_num,
+                total_batches,
+                skipped: 0,
+                invalidated: 0,
+                percent: 100.0 * batch_num as f64 / total_batches as f64,
+                message: format!("{phase_label}: {batch_num}/{total_batches}"),
+                cache: cache_counts(routes),
+            },
+        );
+    }
+    Ok(summary)
+}
+
+/// Use the project's stored content summary as world-building context when the
+/// user has not written any.
+pub(super) fn apply_content_summary(db: &DbState, project_dir: &str, opts: &mut TranslateOpts) -> Result<(), String> {
+    if !opts.world_building.trim().is_empty() {
+        return Ok(());
+    }
+    let conn = db.0.lock().map_err(|e| e.to_string())?;
+    if let Some(summary) = queries::get_content_summary(&conn, project_dir).map_err(|e| e.to_string())? {
+        opts.world_building = summary;
+    }
+    Ok(())
+}
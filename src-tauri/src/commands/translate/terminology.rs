@@ -0,0 +1,216 @@
+//! Glossary extraction before translation, and merging the approved project
+//! glossary into the prompt options.
+
+use crate::ai_pool::AiPoolManager;
+use crate::db::connection::DbState;
+use crate::db::queries;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+
+use super::{
+    build_user_content, cache_counts, call_along_routes, parse_lenient, ChatPrompt, Route,
+    TranslateOpts, TranslateProgress,
+};
+
+pub(super) const DEFAULT_TERMINOLOGY_CORE: &str = "You are a terminology extractor for subtitle translation. List the proper nouns and domain terms in these subtitles: people, places, organizations, products, and technical or fictional terms. For each, suggest the translation that should be used consistently in the target language. Skip common words.";
+
+pub(super) const TERMINOLOGY_JSON_RULES: &str = r#"
+## Rules
+- Input format: JSON object {"index": "text", ...}
+- Output format: JSON object {"terms": [{"source": "...", "target": "...", "category": "person|place|organization|product|term", "note": "..."}]}
+- "source" must appear verbatim in the subtitles
+- Output ONLY the JSON object, no extra text or explanation"#;
+
+/// Subtitle lines sent per terminology extraction request.
+pub(super) const DEFAULT_TERMINOLOGY_CHUNK: usize = 150;
+
+#[derive(Deserialize)]
+struct TermList {
+    terms: Vec<ProposedTerm>,
+}
+
+#[derive(Deserialize)]
+struct ProposedTerm {
+    source: String,
+    #[serde(default)]
+    target: String,
+    #[serde(default)]
+    category: String,
+    #[serde(default)]
+    note: String,
+}
+
+fn parse_terms(raw: &str) -> Result<Vec<ProposedTerm>, String> {
+    parse_lenient::<TermList>(raw).map(|list| list.terms)
+}
+
+/// Terms are compared ignoring case, by this one rule wherever they are matched.
+fn fold_case(text: &str) -> String {
+    text.to_lowercase()
+}
+
+/// Number of lines containing `term`, ignoring case.
+fn count_occurrences(term: &str, lines: &[&str]) -> i32 {
+    let needle = fold_case(term);
+    lines.iter().filter(|l| fold_case(l).contains(&needle)).count() as i32
+}
+
+/// Add a chunk's proposals to `merged`. The first suggestion for a term wins; later
+/// chunks only fill in blanks.
+fn merge_terms(merged: &mut Vec<queries::GlossaryTerm>, proposed: Vec<ProposedTerm>) {
+    for term in proposed {
+        let source = term.source.trim().to_string();
+        if source.is_empty() {
+            continue;
+        }
+        let key = fold_case(&source);
+        match merged.iter_mut().find(|t| fold_case(&t.source) == key) {
+            Some(existing) => {
+                if existing.target.is_empty() {
+                    existing.target = term.target.trim().to_string();
+                }
+                if existing.category.is_empty() {
+                    existing.category = term.category;
+                }
+                if existing.note.is_empty() {
+                    existing.note = term.note;
+                }
+            }
+            None => merged.push(queries::GlossaryTerm {
+                source,
+                target: term.target.trim().to_string(),
+                category: term.category,
+                note: term.note,
+                occurrences: 0,
+                approved: false,
+            }),
+        }
+    }
+}
+
+/// Send the transcript chunk by chunk and merge the proposed terms. Terms that do
+/// not occur in the transcript are dropped; the rest are sorted by frequency.
+/// Chunks that keep failing are skipped and their errors returned alongside the
+/// terms; the extraction only fails when no chunk succeeded.
+pub(super) async fn extract_terms(
+    app: &AppHandle,
+    pool: &AiPoolManager,
+    routes: &[Route],
+    cancel: &Arc<AtomicBool>,
+    system_prompt: &str,
+    lines: &[&str],
+    chunk_size: usize,
+) -> Result<(Vec<queries::GlossaryTerm>, Vec<String>), String> {
+    let phase_label = "术语提取";
+    let indexed: Vec<(usize, &str)> = lines.iter().copied().enumerate().collect();
+    let chunks: Vec<&[(usize, &str)]> = indexed.chunks(chunk_size.max(1)).collect();
+    let total_batches = chunks.len() as u32;
+    let mut merged: Vec<queries::GlossaryTerm> = Vec::new();
+    let mut failures = Vec::new();
+
+    for (chunk_idx, chunk) in chunks.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("已取消".to_string());
+        }
+        let content = build_user_content(chunk);
+
+        let prompt = ChatPrompt::json(system_prompt, &content, 0.1);
+        let proposed =
+            match call_along_routes(pool, routes, cancel, "terms", prompt, parse_terms).await? {
+                Ok(terms) => terms,
+                Err(e) => {
+                    failures.push(e);
+                    Vec::new()
+                }
+            };
+        merge_terms(&mut merged, proposed);
+
+        let batch_num = chunk_idx as u32 + 1;
+        let _ = app.emit(
+            "translate:progress",
+            TranslateProgress {
+                phase: phase_label.to_string(),
+                batch: batch_num,
+                total_batches,
+                skipped: 0,
+                invalidated: 0,
+                percent: 100.0 * batch_num as f64 / total_batches as f64,
+                message: format!("{phase_label}: {batch_num}/{total_batches}"),
+                cache: cache_counts(routes),
+            },
+        );
+    }
+
+    if failures.len() == chunks.len() {
+        let last = failures.pop().unwrap_or_default();
+        return Err(format!("{phase_label}失败: {last}"));
+    }
+    for term in &mut merged {
+        term.occurrences = count_occurrences(&term.source, lines);
+    }
+    merged.retain(|t| t.occurrences > 0);
+    merged.sort_by(|a, b| b.occurrences.cmp(&a.occurrences).then_with(|| a.source.cmp(&b.source)));
+    Ok((merged, failures))
+}
+
+/// Append the project's approved glossary terms to `opts.glossary`, in the same
+/// "source → target" line format the settings page uses. Sources already listed
+/// by the user are skipped.
+pub(super) fn merge_project_glossary(db: &DbState, project_dir: &str, opts: &mut TranslateOpts) -> Result<(), String> {
+    let terms = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        queries::get_glossary_terms(&conn, project_dir).map_err(|e| e.to_string())?
+    };
+    let listed: Vec<String> = opts
+        .glossary
+        .lines()
+        .filter_map(|l| l.split('→').next())
+        .map(|s| fold_case(s.trim()))
+        .collect();
+    let additions: Vec<String> = terms
+        .into_iter()
+        .filter(|t| t.approved && !t.target.is_empty())
+        .filter(|t| !listed.contains(&fold_case(&t.source)))
+        .map(|t| format!("{} → {}", t.source, t.target))
+        .collect();
+    if additions.is_empty() {
+        return Ok(());
+    }
+    if !opts.glossary.trim().is_empty() {
+        opts.glossary.push('\n');
+    }
+    opts.glossary.push_str(&additions.join("\n"));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proposed(source: &str, target: &str) -> ProposedTerm {
+        ProposedTerm {
+            source: source.to_string(),
+            target: target.to_string(),
+            category: String::new(),
+            note: String::new(),
+        }
+    }
+
+    #[test]
+    fn merge_terms_matches_non_ascii_case() {
+        let mut merged = Vec::new();
+        merge_terms(&mut merged, vec![proposed("Émile", "")]);
+        merge_terms(&mut merged, vec![proposed("ÉMILE", "埃米尔"), proposed("Zoë", "佐伊")]);
+        let sources: Vec<&str> = merged.iter().map(|t| t.source.as_str()).collect();
+        assert_eq!(sources, ["Émile", "Zoë"]);
+        assert_eq!(merged[0].target, "埃米尔");
+    }
+
+    #[test]
+    fn count_occurrences_folds_like_merge() {
+        let lines = ["ÉMILE arrive", "bonjour émile", "rien"];
+        assert_eq!(count_occurrences("Émile", &lines), 2);
+    }
+}
@@ -1,7 +1,9 @@
 use crate::db::connection::DbState;
 use crate::db::queries::{self, TtsPlugin};
+use crate::http::ProxyConfig;
 use crate::tts::{NcnProvider, GradioProvider, HttpRestProvider, TtsSynthRequest, TtsVoice, TtsProviderImpl};
 use base64::Engine as _;
+use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use tauri::State;
 
@@ -18,12 +20,13 @@ pub struct TtsPluginDto {
     pub created_at: String,
 }
 
+/// The frontend sees the proxy override's password masked.
 fn to_dto(p: TtsPlugin) -> TtsPluginDto {
     TtsPluginDto {
         id: p.id,
         name: p.name,
         plugin_type: p.plugin_type,
-        config_json: p.config_json,
+        config_json: map_proxy(&p.config_json, |proxy| proxy.masked()),
         requires_ref: p.requires_ref,
         is_enabled: p.is_enabled,
         sort_order: p.sort_order,
@@ -44,14 +47,47 @@ fn from_dto(dto: &TtsPluginDto) -> TtsPlugin {
     }
 }
 
-/// Build a TtsProviderImpl from a plugin record.
-pub fn build_provider(plugin: &TtsPlugin) -> Result<TtsProviderImpl, String> {
+/// `config_json` with its proxy override, if it has one, passed through `f`.
+fn map_proxy(config_json: &str, f: impl FnOnce(ProxyConfig) -> ProxyConfig) -> String {
+    let Ok(mut config) = serde_json::from_str::<serde_json::Value>(config_json) else {
+        return config_json.to_string();
+    };
+    if !config["proxy"].is_object() {
+        return config_json.to_string();
+    }
+    let proxy = f(ProxyConfig::from_value(&config["proxy"]));
+    config["proxy"] = serde_json::to_value(proxy).unwrap_or_default();
+    config.to_string()
+}
+
+/// A plugin as submitted by the frontend, a masked proxy password swapped back for
+/// the one stored.
+fn unmasked(conn: &Connection, dto: &TtsPluginDto) -> Result<TtsPlugin, String> {
+    let stored = queries::get_tts_plugin(conn, &dto.id)
+        .map_err(|e| e.to_string())?
+        .and_then(|p| serde_json::from_str::<serde_json::Value>(&p.config_json).ok())
+        .map(|config| ProxyConfig::from_value(&config["proxy"]))
+        .unwrap_or_default();
+    let mut plugin = from_dto(dto);
+    plugin.config_json = map_proxy(&plugin.config_json, |proxy| proxy.unmasked(&stored));
+    Ok(plugin)
+}
+
+/// The proxy a plugin's requests go through: `config_json.proxy`, or the global one.
+pub fn plugin_proxy(conn: &Connection, plugin: &TtsPlugin) -> Result<ProxyConfig, String> {
+    let config: serde_json::Value = serde_json::from_str(&plugin.config_json)
+        .unwrap_or(serde_json::json!({}));
+    ProxyConfig::from_value(&config["proxy"]).resolve(conn)
+}
+
+/// Build a TtsProviderImpl from a plugin record, sending requests through `proxy`.
+pub fn build_provider(plugin: &TtsPlugin, proxy: ProxyConfig) -> Result<TtsProviderImpl, String> {
     match plugin.plugin_type.as_str() {
         "ncn" => {
             let config: serde_json::Value = serde_json::from_str(&plugin.config_json)
                 .unwrap_or(serde_json::json!({}));
             let voice_id = config["voiceId"].as_str().unwrap_or("").to_string();
-            Ok(TtsProviderImpl::Ncn(NcnProvider::new(voice_id)))
+            Ok(TtsProviderImpl::Ncn(NcnProvider::new(voice_id, proxy)))
         }
         "gradio" => {
             let config: serde_json::Value = serde_json::from_str(&plugin.config_json)
@@ -59,10 +95,11 @@ pub fn build_provider(plugin: &TtsPlugin) -> Result<TtsProviderImpl, String> {
             let endpoint = config["endpoint"].as_str()
                 .ok_or_else(|| "Gradio 插件缺少 endpoint 配置".to_string())?
                 .to_string();
-            Ok(TtsProviderImpl::Gradio(GradioProvider::new(endpoint)))
+            Ok(TtsProviderImpl::Gradio(GradioProvider::new(endpoint, proxy)))
         }
         "http_rest" => {
-            let provider = HttpRestProvider::from_json(&plugin.config_json, plugin.requires_ref)?;
+            let provider =
+                HttpRestProvider::from_json(&plugin.config_json, plugin.requires_ref, proxy)?;
             Ok(TtsProviderImpl::HttpRest(provider))
         }
         other => Err(format!("未知 plugin_type: {other}")),
@@ -82,7 +119,7 @@ pub fn cmd_create_tts_plugin(
     plugin: TtsPluginDto,
 ) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    let p = from_dto(&plugin);
+    let p = unmasked(&conn, &plugin)?;
     queries::create_tts_plugin(&conn, &p).map_err(|e| e.to_string())
 }
 
@@ -92,7 +129,7 @@ pub fn cmd_update_tts_plugin(
     plugin: TtsPluginDto,
 ) -> Result<(), String> {
     let conn = db.0.lock().map_err(|e| e.to_string())?;
-    let p = from_dto(&plugin);
+    let p = unmasked(&conn, &plugin)?;
     queries::update_tts_plugin(&conn, &p).map_err(|e| e.to_string())
 }
 
@@ -104,8 +141,12 @@ pub fn cmd_delete_tts_plugin(db: State<'_, DbState>, id: String) -> Result<(), S
 
 /// List voices from the built-in NCN provider (no plugin registration needed).
 #[tauri::command]
-pub async fn cmd_list_ncn_voices() -> Result<Vec<TtsVoice>, String> {
-    let provider = NcnProvider::new(String::new());
+pub async fn cmd_list_ncn_voices(db: State<'_, DbState>) -> Result<Vec<TtsVoice>, String> {
+    let proxy = {
+        let conn = db.0.lock().map_err(|e| e.to_string())?;
+        ProxyConfig::global(&conn)?
+    };
+    let provider = NcnProvider::new(String::new(), proxy);
     provider.list_voices().await
 }
 
@@ -114,13 +155,15 @@ pub async fn cmd_list_tts_voices(
     db: State<'_, DbState>,
     plugin_id: String,
 ) -> Result<Vec<TtsVoice>, String> {
-    let plugin = {
+    let (plugin, proxy) = {
         let conn = db.0.lock().map_err(|e| e.to_string())?;
-        queries::get_tts_plugin(&conn, &plugin_id)
+        let plugin = queries::get_tts_plugin(&conn, &plugin_id)
             .map_err(|e| e.to_string())?
-            .ok_or_else(|| format!("未找到插件 {plugin_id}"))?
+            .ok_or_else(|| format!("未找到插件 {plugin_id}"))?;
+        let proxy = plugin_proxy(&conn, &plugin)?;
+        (plugin, proxy)
     };
-    let provider = build_provider(&plugin)?;
+    let provider = build_provider(&plugin, proxy)?;
     provider.list_voices().await
 }
 
@@ -130,13 +173,15 @@ pub async fn cmd_test_tts_plugin(
     plugin_id: String,
     sample_text: String,
 ) -> Result<String, String> {
-    let plugin = {
+    let (plugin, proxy) = {
         let conn = db.0.lock().map_err(|e| e.to_string())?;
-        queries::get_tts_plugin(&conn, &plugin_id)
+        let plugin = queries::get_tts_plugin(&conn, &plugin_id)
             .map_err(|e| e.to_string())?
-            .ok_or_else(|| format!("未找到插件 {plugin_id}"))?
+            .ok_or_else(|| format!("未找到插件 {plugin_id}"))?;
+        let proxy = plugin_proxy(&conn, &plugin)?;
+        (plugin, proxy)
     };
-    let provider = build_provider(&plugin)?;
+    let provider = build_provider(&plugin, proxy)?;
 
     let tmp_path = std::env::temp_dir()
         .join(format!("tts_test_{}.mp3", uuid::Uuid::new_v4()));
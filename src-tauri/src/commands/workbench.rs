@@ -105,6 +105,7 @@ pub fn cmd_save_translate_step(
         translated_subtitles_path: Some(translated_subtitles_path),
         subtitle_count,
         completed_at: Some(now),
+        content_summary: String::new(),
     };
     let conn = state.0.lock().map_err(|e| e.to_string())?;
     upsert_step_translate(&conn, &step).map_err(|e| e.to_string())
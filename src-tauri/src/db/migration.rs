@@ -2,6 +2,43 @@ use rusqlite::{Connection, Result};
 
 const SCHEMA: &str = include_str!("../../migrations/schema.sql");
 
+/// Columns added to tables after their first release: `(table, column, definition)`.
+/// `CREATE TABLE IF NOT EXISTS` leaves existing tables untouched, so these are
+/// applied with `ALTER TABLE` when missing. New entries go at the end.
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("translation_progress", "status", "TEXT NOT NULL DEFAULT 'translated'"),
+    ("translation_progress", "error", "TEXT"),
+    ("translation_progress", "source_hash", "TEXT NOT NULL DEFAULT ''"),
+    ("translation_progress", "prompt_hash", "TEXT NOT NULL DEFAULT ''"),
+    ("workbench_step_translate", "content_summary", "TEXT NOT NULL DEFAULT ''"),
+    ("ai_configs", "max_input_tokens", "INTEGER NOT NULL DEFAULT 0"),
+    ("ai_configs", "max_output_tokens", "INTEGER NOT NULL DEFAULT 0"),
+    ("ai_configs", "tpm_limit", "INTEGER NOT NULL DEFAULT 0"),
+    ("ai_configs", "proxy", "TEXT NOT NULL DEFAULT ''"),
+    ("ai_configs", "capabilities", "TEXT NOT NULL DEFAULT ''"),
+];
+
 pub fn run(conn: &Connection) -> Result<()> {
-    conn.execute_batch(SCHEMA)
+    conn.execute_batch(SCHEMA)?;
+    for (table, column, definition) in ADDED_COLUMNS {
+        add_column_if_missing(conn, table, column, definition)?;
+    }
+    Ok(())
+}
+
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))?;
+    }
+    Ok(())
 }
@@ -1,3 +1,6 @@
+use crate::http::{self, ProxyConfig};
+use crate::model_probe::ModelCapabilities;
+use crate::secrets;
 use rusqlite::{Connection, Result};
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
@@ -15,10 +18,19 @@ pub fn get_all_config(conn: &Connection) -> Result<HashMap<String, String>> {
     Ok(map)
 }
 
+/// `app_config` keys holding secrets, stored sealed.
+const SECRET_CONFIG_KEYS: &[&str] = &[http::KEY_PASSWORD];
+
+pub fn is_secret_config(key: &str) -> bool {
+    SECRET_CONFIG_KEYS.contains(&key)
+}
+
+/// Set `key`; the values of secret keys are sealed first.
 pub fn set_config(conn: &Connection, key: &str, value: &str) -> Result<()> {
+    let value = if is_secret_config(key) { seal_secret(value)? } else { value.to_string() };
     conn.execute(
         "INSERT OR REPLACE INTO app_config (key, value, updated_at) VALUES (?1, ?2, datetime('now'))",
-        [key, value],
+        [key, value.as_str()],
     )?;
     Ok(())
 }
@@ -28,7 +40,7 @@ pub fn get_provider_secret(conn: &Connection, provider_id: &str) -> Result<Optio
         conn.prepare("SELECT secret_json FROM provider_secrets WHERE provider_id = ?1")?;
     let mut rows = stmt.query([provider_id])?;
     if let Some(row) = rows.next()? {
-        Ok(Some(row.get(0)?))
+        Ok(Some(open_secret(&row.get::<_, String>(0)?)?))
     } else {
         Ok(None)
     }
@@ -37,11 +49,96 @@ pub fn get_provider_secret(conn: &Connection, provider_id: &str) -> Result<Optio
 pub fn set_provider_secret(conn: &Connection, provider_id: &str, secret_json: &str) -> Result<()> {
     conn.execute(
         "INSERT OR REPLACE INTO provider_secrets (provider_id, secret_json, updated_at) VALUES (?1, ?2, datetime('now'))",
-        [provider_id, secret_json],
+        rusqlite::params![provider_id, seal_secret(secret_json)?],
     )?;
     Ok(())
 }
 
+/// Encrypt a secret column value. Errors use `ToSqlConversionFailure`, the rusqlite
+/// error that displays just its message.
+fn seal_secret(plain: &str) -> Result<String> {
+    secrets::seal(plain).map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))
+}
+
+/// Decrypt a secret column value; plaintext from before encryption passes through.
+fn open_secret(stored: &str) -> Result<String> {
+    secrets::open(stored)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.to_string().into()))
+}
+
+/// Like `open_secret`, but a key that can't be decrypted reads as `None` so the rest
+/// of its config still loads and the key can be entered again.
+fn open_api_key(stored: &str) -> Result<Option<String>> {
+    match secrets::open(stored) {
+        Ok(key) => Ok(Some(key)),
+        Err(secrets::OpenError::Unreadable) => Ok(None),
+        Err(e) => Err(rusqlite::Error::ToSqlConversionFailure(e.to_string().into())),
+    }
+}
+
+/// Encrypt the secrets still stored in plaintext. Runs whenever the store unlocks.
+pub fn seal_plaintext_secrets(conn: &Connection) -> Result<()> {
+    let secret_config = SECRET_CONFIG_KEYS
+        .iter()
+        .map(|k| format!("'{k}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let secret_config = format!("key IN ({secret_config})");
+    // (table, column, rows, sealed value); proxy overrides are JSON holding a password
+    let columns: [(&str, &str, &str, SealColumn); 6] = [
+        ("ai_configs", "api_key", "1", seal_plaintext),
+        ("ai_config_keys", "api_key", "1", seal_plaintext),
+        ("provider_secrets", "secret_json", "1", seal_plaintext),
+        ("app_config", "value", &secret_config, seal_plaintext),
+        ("ai_configs", "proxy", "1", |value| proxy_to_column(&proxy_from_column(value))),
+        ("tts_plugins", "config_json", "1", seal_plugin_proxy),
+    ];
+    for (table, column, rows, seal) in columns {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT rowid, {column} FROM {table} WHERE {column} <> '' AND {rows}"
+        ))?;
+        let values = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        for (rowid, value) in values {
+            let sealed = seal(&value)?;
+            if sealed != value {
+                conn.execute(
+                    &format!("UPDATE {table} SET {column} = ?2 WHERE rowid = ?1"),
+                    rusqlite::params![rowid, sealed],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How `seal_plaintext_secrets` seals one column value; sealed values pass unchanged.
+type SealColumn = fn(&str) -> Result<String>;
+
+fn seal_plaintext(value: &str) -> Result<String> {
+    if secrets::is_sealed(value) {
+        Ok(value.to_string())
+    } else {
+        seal_secret(value)
+    }
+}
+
+/// A TTS plugin's `config_json` with its proxy override's password sealed.
+pub fn seal_plugin_proxy(config_json: &str) -> Result<String> {
+    let Ok(mut config) = serde_json::from_str::<serde_json::Value>(config_json) else {
+        return Ok(config_json.to_string());
+    };
+    let Some(password) = config["proxy"]["password"].as_str() else {
+        return Ok(config_json.to_string());
+    };
+    if password.is_empty() || secrets::is_sealed(password) {
+        return Ok(config_json.to_string());
+    }
+    config["proxy"]["password"] = seal_secret(password)?.into();
+    Ok(config.to_string())
+}
+
 // ── AI Configs ──────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,36 +153,185 @@ pub struct AiConfig {
     pub concurrent_limit: i32,
     pub request_timeout: i32,
     pub rate_limit: i32,
+    /// Token budget of one translation request (prompt included); 0 = no limit.
+    #[serde(default)]
+    pub max_input_tokens: i32,
+    /// The model's output limit, also sent as `max_tokens`; 0 = no limit.
+    #[serde(default)]
+    pub max_output_tokens: i32,
+    /// Tokens per minute the provider allows; 0 = no limit.
+    #[serde(default)]
+    pub tpm_limit: i32,
+    /// Keys used alongside `api_key`, which keeps the config's own limits.
+    #[serde(default)]
+    pub api_keys: Vec<AiConfigKey>,
+    /// Proxy override; `mode` "inherit" uses the global proxy.
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// What the last probe found out about the endpoint's model.
+    #[serde(default)]
+    pub capabilities: Option<ModelCapabilities>,
+    /// Some stored key couldn't be decrypted and reads as empty; it has to be entered
+    /// again before the config can be used.
+    #[serde(default)]
+    pub keys_unreadable: bool,
+}
+
+impl AiConfig {
+    /// An error when a stored key of the config was lost, rather than sending
+    /// requests with it missing.
+    pub fn check_keys(&self) -> std::result::Result<(), String> {
+        if self.keys_unreadable {
+            let lost = secrets::OpenError::Unreadable;
+            return Err(format!("AI 配置「{}」: {lost}", self.title));
+        }
+        Ok(())
+    }
+}
+
+/// An additional API key of an AI config. Limits of 0 fall back to the config's.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiConfigKey {
+    pub api_key: String,
+    /// Where the key is stored; a masked key submitted back is matched by it. `None`
+    /// for keys added in the form.
+    #[serde(default)]
+    pub position: Option<i32>,
+    #[serde(default)]
+    pub concurrent_limit: i32,
+    #[serde(default)]
+    pub rate_limit: i32,
+    #[serde(default)]
+    pub tpm_limit: i32,
 }
 
 pub fn get_all_ai_configs(conn: &Connection) -> Result<Vec<AiConfig>> {
     let mut stmt = conn.prepare(
         "SELECT id, title, base_url, api_key, model, sort_order, is_default,
-                concurrent_limit, request_timeout, rate_limit
+                concurrent_limit, request_timeout, rate_limit,
+                max_input_tokens, max_output_tokens, tpm_limit, proxy, capabilities
          FROM ai_configs
          ORDER BY is_default DESC, sort_order ASC",
     )?;
     let rows = stmt.query_map([], |row| {
+        let api_key = open_api_key(&row.get::<_, String>(3)?)?;
         Ok(AiConfig {
             id: row.get(0)?,
             title: row.get(1)?,
             base_url: row.get(2)?,
-            api_key: row.get(3)?,
+            keys_unreadable: api_key.is_none(),
+            api_key: api_key.unwrap_or_default(),
             model: row.get(4)?,
             sort_order: row.get(5)?,
             is_default: row.get::<_, i32>(6)? != 0,
             concurrent_limit: row.get(7)?,
             request_timeout: row.get(8)?,
             rate_limit: row.get(9)?,
+            max_input_tokens: row.get(10)?,
+            max_output_tokens: row.get(11)?,
+            tpm_limit: row.get(12)?,
+            api_keys: Vec::new(),
+            proxy: proxy_from_column(&row.get::<_, String>(13)?),
+            capabilities: serde_json::from_str(&row.get::<_, String>(14)?).ok(),
         })
     })?;
     let mut configs = Vec::new();
     for row in rows {
         configs.push(row?);
     }
+    for config in &mut configs {
+        let (keys, unreadable) = get_ai_config_keys(conn, &config.id)?;
+        config.api_keys = keys;
+        config.keys_unreadable |= unreadable;
+    }
     Ok(configs)
 }
 
+/// Overrides are stored as JSON, the password sealed; an empty column inherits the
+/// global proxy.
+fn proxy_from_column(value: &str) -> ProxyConfig {
+    serde_json::from_str(value).unwrap_or_default()
+}
+
+fn proxy_to_column(proxy: &ProxyConfig) -> Result<String> {
+    if proxy.inherits() {
+        return Ok(String::new());
+    }
+    let mut proxy = proxy.clone();
+    proxy
+        .seal_password()
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+    Ok(serde_json::to_string(&proxy).unwrap_or_default())
+}
+
+fn capabilities_to_column(capabilities: Option<&ModelCapabilities>) -> String {
+    capabilities
+        .and_then(|c| serde_json::to_string(c).ok())
+        .unwrap_or_default()
+}
+
+/// Store the probe result of config `id`.
+pub fn set_ai_config_capabilities(
+    conn: &Connection,
+    id: &str,
+    capabilities: &ModelCapabilities,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE ai_configs SET capabilities = ?2 WHERE id = ?1",
+        rusqlite::params![id, capabilities_to_column(Some(capabilities))],
+    )?;
+    Ok(())
+}
+
+/// The extra keys of `config_id`, and whether any of them couldn't be decrypted.
+fn get_ai_config_keys(conn: &Connection, config_id: &str) -> Result<(Vec<AiConfigKey>, bool)> {
+    let mut stmt = conn.prepare(
+        "SELECT api_key, concurrent_limit, rate_limit, tpm_limit, position
+         FROM ai_config_keys WHERE config_id = ?1 ORDER BY position",
+    )?;
+    let rows = stmt.query_map([config_id], |row| {
+        let api_key = open_api_key(&row.get::<_, String>(0)?)?;
+        Ok((
+            AiConfigKey {
+                api_key: api_key.clone().unwrap_or_default(),
+                concurrent_limit: row.get(1)?,
+                rate_limit: row.get(2)?,
+                tpm_limit: row.get(3)?,
+                position: Some(row.get(4)?),
+            },
+            api_key.is_none(),
+        ))
+    })?;
+    let mut keys = Vec::new();
+    let mut unreadable = false;
+    for row in rows {
+        let (key, lost) = row?;
+        keys.push(key);
+        unreadable |= lost;
+    }
+    Ok((keys, unreadable))
+}
+
+fn save_ai_config_keys(conn: &Connection, config_id: &str, keys: &[AiConfigKey]) -> Result<()> {
+    conn.execute("DELETE FROM ai_config_keys WHERE config_id = ?1", [config_id])?;
+    for (position, key) in keys.iter().filter(|k| !k.api_key.trim().is_empty()).enumerate() {
+        conn.execute(
+            "INSERT INTO ai_config_keys (config_id, position, api_key, concurrent_limit,
+                                         rate_limit, tpm_limit)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                config_id,
+                position as i32,
+                seal_secret(key.api_key.trim())?,
+                key.concurrent_limit,
+                key.rate_limit,
+                key.tpm_limit,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
 pub fn create_ai_config(conn: &Connection, config: &AiConfig) -> Result<()> {
     // If this is the first config, make it default
     let count: i64 =
@@ -94,22 +340,29 @@ pub fn create_ai_config(conn: &Connection, config: &AiConfig) -> Result<()> {
 
     conn.execute(
         "INSERT INTO ai_configs (id, title, base_url, api_key, model, sort_order, is_default,
-                                 concurrent_limit, request_timeout, rate_limit)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                                 concurrent_limit, request_timeout, rate_limit,
+                                 max_input_tokens, max_output_tokens, tpm_limit, proxy,
+                                 capabilities)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
         rusqlite::params![
             config.id,
             config.title,
             config.base_url,
-            config.api_key,
+            seal_secret(&config.api_key)?,
             config.model,
             config.sort_order,
             is_default,
             config.concurrent_limit,
             config.request_timeout,
             config.rate_limit,
+            config.max_input_tokens,
+            config.max_output_tokens,
+            config.tpm_limit,
+            proxy_to_column(&config.proxy)?,
+            capabilities_to_column(config.capabilities.as_ref()),
         ],
     )?;
-    Ok(())
+    save_ai_config_keys(conn, &config.id, &config.api_keys)
 }
 
 pub fn update_ai_config(conn: &Connection, config: &AiConfig) -> Result<()> {
@@ -117,26 +370,34 @@ pub fn update_ai_config(conn: &Connection, config: &AiConfig) -> Result<()> {
         "UPDATE ai_configs
          SET title = ?2, base_url = ?3, api_key = ?4, model = ?5,
              sort_order = ?6, is_default = ?7,
-             concurrent_limit = ?8, request_timeout = ?9, rate_limit = ?10
+             concurrent_limit = ?8, request_timeout = ?9, rate_limit = ?10,
+             max_input_tokens = ?11, max_output_tokens = ?12, tpm_limit = ?13,
+             proxy = ?14, capabilities = ?15
          WHERE id = ?1",
         rusqlite::params![
             config.id,
             config.title,
             config.base_url,
-            config.api_key,
+            seal_secret(&config.api_key)?,
             config.model,
             config.sort_order,
             config.is_default as i32,
             config.concurrent_limit,
             config.request_timeout,
             config.rate_limit,
+            config.max_input_tokens,
+            config.max_output_tokens,
+            config.tpm_limit,
+            proxy_to_column(&config.proxy)?,
+            capabilities_to_column(config.capabilities.as_ref()),
         ],
     )?;
-    Ok(())
+    save_ai_config_keys(conn, &config.id, &config.api_keys)
 }
 
 pub fn delete_ai_config(conn: &Connection, id: &str) -> Result<()> {
     conn.execute("DELETE FROM ai_configs WHERE id = ?1", [id])?;
+    conn.execute("DELETE FROM ai_config_keys WHERE config_id = ?1", [id])?;
     Ok(())
 }
 
@@ -148,16 +409,36 @@ pub fn set_default_ai_config(conn: &Connection, id: &str) -> Result<()> {
 
 // ── Translation Progress ────────────────────────────────────────────────────
 
-pub fn get_translation_progress(
+/// A saved line result together with the hashes of the input it was produced from.
+#[derive(Debug, Clone)]
+pub struct TranslationProgressRow {
+    pub result_text: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub source_hash: String,
+    pub prompt_hash: String,
+}
+
+pub fn get_translation_progress_rows(
     conn: &Connection,
     project_dir: &str,
     phase: &str,
-) -> Result<HashMap<i32, String>> {
+) -> Result<HashMap<i32, TranslationProgressRow>> {
     let mut stmt = conn.prepare(
-        "SELECT subtitle_index, result_text FROM translation_progress WHERE project_dir = ?1 AND phase = ?2",
+        "SELECT subtitle_index, result_text, status, error, source_hash, prompt_hash
+         FROM translation_progress WHERE project_dir = ?1 AND phase = ?2",
     )?;
     let rows = stmt.query_map(rusqlite::params![project_dir, phase], |row| {
-        Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?))
+        Ok((
+            row.get::<_, i32>(0)?,
+            TranslationProgressRow {
+                result_text: row.get(1)?,
+                status: row.get(2)?,
+                error: row.get(3)?,
+                source_hash: row.get(4)?,
+                prompt_hash: row.get(5)?,
+            },
+        ))
     })?;
     let mut map = HashMap::new();
     for row in rows {
@@ -172,20 +453,527 @@ pub fn save_translation_progress(
     project_dir: &str,
     subtitle_index: i32,
     phase: &str,
-    result_text: &str,
+    row: &TranslationProgressRow,
 ) -> Result<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO translation_progress (project_dir, subtitle_index, phase, result_text) VALUES (?1, ?2, ?3, ?4)",
-        rusqlite::params![project_dir, subtitle_index, phase, result_text],
+        "INSERT OR REPLACE INTO translation_progress
+         (project_dir, subtitle_index, phase, result_text, status, error, source_hash, prompt_hash)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        rusqlite::params![
+            project_dir, subtitle_index, phase, row.result_text, row.status, row.error,
+            row.source_hash, row.prompt_hash,
+        ],
     )?;
     Ok(())
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslationLineIssue {
+    pub subtitle_index: i32,
+    pub phase: String,
+    pub status: String,
+    pub error: Option<String>,
+}
+
+/// Lines whose last run did not come back as a regular batch translation. Lines
+/// passed through on purpose are not issues.
+pub fn get_translation_line_issues(
+    conn: &Connection,
+    project_dir: &str,
+) -> Result<Vec<TranslationLineIssue>> {
+    let mut stmt = conn.prepare(
+        "SELECT subtitle_index, phase, status, error FROM translation_progress
+         WHERE project_dir = ?1 AND status NOT IN ('translated', 'skipped')
+         ORDER BY phase, subtitle_index",
+    )?;
+    let rows = stmt.query_map([project_dir], |row| {
+        Ok(TranslationLineIssue {
+            subtitle_index: row.get(0)?,
+            phase: row.get(1)?,
+            status: row.get(2)?,
+            error: row.get(3)?,
+        })
+    })?;
+    let mut result = Vec::new();
+    for r in rows { result.push(r?); }
+    Ok(result)
+}
+
+/// Per-phase line counts keyed by `(phase, status)`.
+pub fn count_translation_statuses(
+    conn: &Connection,
+    project_dir: &str,
+) -> Result<HashMap<(String, String), i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT phase, status, COUNT(*) FROM translation_progress
+         WHERE project_dir = ?1 GROUP BY phase, status",
+    )?;
+    let rows = stmt.query_map([project_dir], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?))
+    })?;
+    let mut map = HashMap::new();
+    for row in rows {
+        let (phase, status, count) = row?;
+        map.insert((phase, status), count);
+    }
+    Ok(map)
+}
+
+pub fn delete_translation_progress_lines(
+    conn: &Connection,
+    project_dir: &str,
+    phase: &str,
+    indices: &[i32],
+) -> Result<()> {
+    for idx in indices {
+        conn.execute(
+            "DELETE FROM translation_progress WHERE project_dir = ?1 AND phase = ?2 AND subtitle_index = ?3",
+            rusqlite::params![project_dir, phase, idx],
+        )?;
+    }
+    Ok(())
+}
+
 pub fn clear_translation_progress(conn: &Connection, project_dir: &str) -> Result<()> {
     conn.execute(
         "DELETE FROM translation_progress WHERE project_dir = ?1",
         [project_dir],
     )?;
+    conn.execute(
+        "DELETE FROM translation_reviews WHERE project_dir = ?1",
+        [project_dir],
+    )?;
+    conn.execute(
+        "DELETE FROM correction_reviews WHERE project_dir = ?1",
+        [project_dir],
+    )?;
+    Ok(())
+}
+
+// ── Correction Reviews ──────────────────────────────────────────────────────
+
+/// A line changed by the correction phase, with the reviewer's decision:
+/// "pending", "accepted" or "rejected".
+#[derive(Debug, Clone)]
+pub struct CorrectionReview {
+    pub subtitle_index: i32,
+    pub original: String,
+    pub corrected: String,
+    pub decision: String,
+}
+
+pub fn get_correction_reviews(conn: &Connection, project_dir: &str) -> Result<Vec<CorrectionReview>> {
+    let mut stmt = conn.prepare(
+        "SELECT subtitle_index, original, corrected, decision FROM correction_reviews
+         WHERE project_dir = ?1 ORDER BY subtitle_index",
+    )?;
+    let rows = stmt.query_map([project_dir], |row| {
+        Ok(CorrectionReview {
+            subtitle_index: row.get(0)?,
+            original: row.get(1)?,
+            corrected: row.get(2)?,
+            decision: row.get(3)?,
+        })
+    })?;
+    let mut result = Vec::new();
+    for r in rows { result.push(r?); }
+    Ok(result)
+}
+
+pub fn save_correction_review(
+    conn: &Connection,
+    project_dir: &str,
+    review: &CorrectionReview,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO correction_reviews
+         (project_dir, subtitle_index, original, corrected, decision)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            project_dir, review.subtitle_index, review.original, review.corrected,
+            review.decision,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn delete_correction_review(conn: &Connection, project_dir: &str, subtitle_index: i32) -> Result<()> {
+    conn.execute(
+        "DELETE FROM correction_reviews WHERE project_dir = ?1 AND subtitle_index = ?2",
+        rusqlite::params![project_dir, subtitle_index],
+    )?;
+    Ok(())
+}
+
+/// Set the decision of one line, or of every line when `subtitle_index` is `None`.
+pub fn set_correction_decision(
+    conn: &Connection,
+    project_dir: &str,
+    subtitle_index: Option<i32>,
+    decision: &str,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE correction_reviews SET decision = ?3
+         WHERE project_dir = ?1 AND (?2 IS NULL OR subtitle_index = ?2)",
+        rusqlite::params![project_dir, subtitle_index, decision],
+    )?;
+    Ok(())
+}
+
+// ── Translation Memory ──────────────────────────────────────────────────────
+
+/// Translations the user picked by hand, keyed by source text.
+pub fn get_translation_memory(
+    conn: &Connection,
+    project_dir: &str,
+    target_language: &str,
+) -> Result<HashMap<String, String>> {
+    let mut stmt = conn.prepare(
+        "SELECT source_text, target_text FROM translation_memory
+         WHERE project_dir = ?1 AND target_language = ?2",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![project_dir, target_language], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut map = HashMap::new();
+    for row in rows {
+        let (source, target) = row?;
+        map.insert(source, target);
+    }
+    Ok(map)
+}
+
+pub fn save_translation_memory(
+    conn: &Connection,
+    project_dir: &str,
+    source_text: &str,
+    target_language: &str,
+    target_text: &str,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO translation_memory
+         (project_dir, source_text, target_language, target_text, updated_at)
+         VALUES (?1, ?2, ?3, ?4, datetime('now'))",
+        rusqlite::params![project_dir, source_text, target_language, target_text],
+    )?;
+    Ok(())
+}
+
+/// Translations the user picked for single lines: `(source text, chosen text)` by
+/// subtitle index.
+pub fn get_line_choices(
+    conn: &Connection,
+    project_dir: &str,
+    target_language: &str,
+) -> Result<HashMap<i32, (String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT subtitle_index, source_text, target_text FROM translation_line_choices
+         WHERE project_dir = ?1 AND target_language = ?2",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![project_dir, target_language], |row| {
+        Ok((row.get::<_, i32>(0)?, (row.get::<_, String>(1)?, row.get::<_, String>(2)?)))
+    })?;
+    let mut map = HashMap::new();
+    for row in rows {
+        let (index, choice) = row?;
+        map.insert(index, choice);
+    }
+    Ok(map)
+}
+
+pub fn save_line_choice(
+    conn: &Connection,
+    project_dir: &str,
+    subtitle_index: i32,
+    source_text: &str,
+    target_language: &str,
+    target_text: &str,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO translation_line_choices
+         (project_dir, subtitle_index, target_language, source_text, target_text, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'))",
+        rusqlite::params![project_dir, subtitle_index, target_language, source_text, target_text],
+    )?;
+    Ok(())
+}
+
+/// Path of the translated subtitle file saved for the task working in `project_dir`.
+pub fn get_translated_subtitles_path(conn: &Connection, project_dir: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT s.translated_subtitles_path FROM workbench_step_translate s
+         JOIN workbench_tasks t ON t.id = s.task_id
+         WHERE t.project_dir = ?1",
+    )?;
+    let mut rows = stmt.query([project_dir])?;
+    match rows.next()? {
+        Some(row) => row.get(0),
+        None => Ok(None),
+    }
+}
+
+// ── Translation Reviews ─────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslationReview {
+    pub subtitle_index: i32,
+    pub accuracy: f64,
+    pub fluency: f64,
+    pub terminology: f64,
+    pub score: f64,
+    pub reason: String,
+    /// Hash of the source, translation and review prompt this score applies to.
+    #[serde(skip)]
+    pub content_hash: String,
+    /// Why the line could not be reviewed; the scores are 0 then.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+pub fn get_translation_reviews(
+    conn: &Connection,
+    project_dir: &str,
+) -> Result<Vec<TranslationReview>> {
+    let mut stmt = conn.prepare(
+        "SELECT subtitle_index, accuracy, fluency, terminology, score, reason, content_hash, error
+         FROM translation_reviews WHERE project_dir = ?1 ORDER BY subtitle_index",
+    )?;
+    let rows = stmt.query_map([project_dir], |row| {
+        Ok(TranslationReview {
+            subtitle_index: row.get(0)?,
+            accuracy: row.get(1)?,
+            fluency: row.get(2)?,
+            terminology: row.get(3)?,
+            score: row.get(4)?,
+            reason: row.get(5)?,
+            content_hash: row.get(6)?,
+            error: row.get(7)?,
+        })
+    })?;
+    let mut result = Vec::new();
+    for r in rows { result.push(r?); }
+    Ok(result)
+}
+
+pub fn save_translation_review(
+    conn: &Connection,
+    project_dir: &str,
+    review: &TranslationReview,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO translation_reviews
+         (project_dir, subtitle_index, accuracy, fluency, terminology, score, reason,
+          content_hash, error)
+         VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9)",
+        rusqlite::params![
+            project_dir, review.subtitle_index, review.accuracy, review.fluency,
+            review.terminology, review.score, review.reason, review.content_hash, review.error,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn delete_translation_reviews(
+    conn: &Connection,
+    project_dir: &str,
+    indices: &[i32],
+) -> Result<()> {
+    for idx in indices {
+        conn.execute(
+            "DELETE FROM translation_reviews WHERE project_dir = ?1 AND subtitle_index = ?2",
+            rusqlite::params![project_dir, idx],
+        )?;
+    }
+    Ok(())
+}
+
+// ── Glossary Terms ──────────────────────────────────────────────────────────
+
+/// A project glossary entry. Extraction proposes terms; only approved ones are
+/// added to the translation glossary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlossaryTerm {
+    pub source: String,
+    pub target: String,
+    /// "person", "place", "organization", "product" or "term".
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub note: String,
+    /// Number of subtitle lines containing the source term.
+    #[serde(default)]
+    pub occurrences: i32,
+    #[serde(default)]
+    pub approved: bool,
+}
+
+pub fn get_glossary_terms(conn: &Connection, project_dir: &str) -> Result<Vec<GlossaryTerm>> {
+    let mut stmt = conn.prepare(
+        "SELECT source, target, category, note, occurrences, approved
+         FROM glossary_terms WHERE project_dir = ?1
+         ORDER BY approved DESC, occurrences DESC, source",
+    )?;
+    let rows = stmt.query_map([project_dir], |row| {
+        Ok(GlossaryTerm {
+            source: row.get(0)?,
+            target: row.get(1)?,
+            category: row.get(2)?,
+            note: row.get(3)?,
+            occurrences: row.get(4)?,
+            approved: row.get::<_, i32>(5)? != 0,
+        })
+    })?;
+    let mut result = Vec::new();
+    for r in rows { result.push(r?); }
+    Ok(result)
+}
+
+/// Store extracted proposals. Terms the user already approved are left untouched.
+pub fn save_proposed_glossary_terms(
+    conn: &Connection,
+    project_dir: &str,
+    terms: &[GlossaryTerm],
+) -> Result<()> {
+    for term in terms {
+        conn.execute(
+            "INSERT INTO glossary_terms
+             (project_dir, source, target, category, note, occurrences, approved)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, 0)
+             ON CONFLICT(project_dir, source) DO UPDATE SET
+                 target = excluded.target, category = excluded.category,
+                 note = excluded.note, occurrences = excluded.occurrences
+             WHERE approved = 0",
+            rusqlite::params![
+                project_dir, term.source, term.target, term.category, term.note,
+                term.occurrences,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Replace the project's glossary with the user-edited list.
+pub fn replace_glossary_terms(
+    conn: &Connection,
+    project_dir: &str,
+    terms: &[GlossaryTerm],
+) -> Result<()> {
+    conn.execute("DELETE FROM glossary_terms WHERE project_dir = ?1", [project_dir])?;
+    for term in terms {
+        conn.execute(
+            "INSERT OR REPLACE INTO glossary_terms
+             (project_dir, source, target, category, note, occurrences, approved)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                project_dir, term.source, term.target, term.category, term.note,
+                term.occurrences, term.approved as i32,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+// ── Prompt Templates ────────────────────────────────────────────────────────
+
+/// One version of a named prompt template. Editing a template adds a version;
+/// `id` stays the same across versions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptTemplate {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub version: i32,
+    pub name: String,
+    /// Prompt slot the template fills: "correction", "standard", "reflective",
+    /// "optimize", "review", "terminology", "summary" or "preprocess".
+    pub kind: String,
+    #[serde(default)]
+    pub description: String,
+    pub content: String,
+    #[serde(default)]
+    pub created_at: String,
+}
+
+fn prompt_template_from_row(row: &rusqlite::Row) -> Result<PromptTemplate> {
+    Ok(PromptTemplate {
+        id: row.get(0)?,
+        version: row.get(1)?,
+        name: row.get(2)?,
+        kind: row.get(3)?,
+        description: row.get(4)?,
+        content: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+/// Latest version of every template.
+pub fn list_prompt_templates(conn: &Connection) -> Result<Vec<PromptTemplate>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, version, name, kind, description, content, created_at
+         FROM prompt_templates t
+         WHERE version = (SELECT MAX(version) FROM prompt_templates WHERE id = t.id)
+         ORDER BY kind, name",
+    )?;
+    let rows = stmt.query_map([], prompt_template_from_row)?;
+    let mut result = Vec::new();
+    for r in rows { result.push(r?); }
+    Ok(result)
+}
+
+pub fn get_prompt_template_versions(conn: &Connection, id: &str) -> Result<Vec<PromptTemplate>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, version, name, kind, description, content, created_at
+         FROM prompt_templates WHERE id = ?1 ORDER BY version DESC",
+    )?;
+    let rows = stmt.query_map([id], prompt_template_from_row)?;
+    let mut result = Vec::new();
+    for r in rows { result.push(r?); }
+    Ok(result)
+}
+
+/// A specific version, or the latest one when `version` is `None`.
+pub fn get_prompt_template(
+    conn: &Connection,
+    id: &str,
+    version: Option<i32>,
+) -> Result<Option<PromptTemplate>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, version, name, kind, description, content, created_at
+         FROM prompt_templates
+         WHERE id = ?1 AND (?2 IS NULL OR version = ?2)
+         ORDER BY version DESC LIMIT 1",
+    )?;
+    let mut rows = stmt.query_map(rusqlite::params![id, version], prompt_template_from_row)?;
+    rows.next().transpose()
+}
+
+pub fn next_prompt_template_version(conn: &Connection, id: &str) -> Result<i32> {
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) + 1 FROM prompt_templates WHERE id = ?1",
+        [id],
+        |r| r.get(0),
+    )
+}
+
+pub fn insert_prompt_template(conn: &Connection, template: &PromptTemplate) -> Result<()> {
+    conn.execute(
+        "INSERT INTO prompt_templates (id, version, name, kind, description, content, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            template.id, template.version, template.name, template.kind,
+            template.description, template.content, template.created_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Delete a template with all its versions.
+pub fn delete_prompt_template(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute("DELETE FROM prompt_templates WHERE id = ?1", [id])?;
     Ok(())
 }
 
@@ -273,6 +1061,12 @@ pub fn get_dubbing_job_by_dir(conn: &Connection, project_dir: &str) -> Result<Op
     }
 }
 
+pub fn get_dubbing_job_project_dir(conn: &Connection, job_id: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare("SELECT project_dir FROM dubbing_jobs WHERE id = ?1")?;
+    let mut rows = stmt.query_map([job_id], |row| row.get(0))?;
+    rows.next().transpose()
+}
+
 pub fn upsert_dubbing_job(conn: &Connection, job: &DubbingJob) -> Result<()> {
     conn.execute(
         "INSERT OR REPLACE INTO dubbing_jobs
@@ -508,7 +1302,7 @@ pub fn create_tts_plugin(conn: &Connection, plugin: &TtsPlugin) -> Result<()> {
         "INSERT INTO tts_plugins (id, name, plugin_type, config_json, requires_ref, is_enabled, sort_order, created_at)
          VALUES (?1,?2,?3,?4,?5,?6,?7,?8)",
         rusqlite::params![
-            plugin.id, plugin.name, plugin.plugin_type, plugin.config_json,
+            plugin.id, plugin.name, plugin.plugin_type, seal_plugin_proxy(&plugin.config_json)?,
             plugin.requires_ref as i32, plugin.is_enabled as i32,
             plugin.sort_order, plugin.created_at,
         ],
@@ -521,7 +1315,7 @@ pub fn update_tts_plugin(conn: &Connection, plugin: &TtsPlugin) -> Result<()> {
         "UPDATE tts_plugins SET name=?2, plugin_type=?3, config_json=?4,
          requires_ref=?5, is_enabled=?6, sort_order=?7 WHERE id=?1",
         rusqlite::params![
-            plugin.id, plugin.name, plugin.plugin_type, plugin.config_json,
+            plugin.id, plugin.name, plugin.plugin_type, seal_plugin_proxy(&plugin.config_json)?,
             plugin.requires_ref as i32, plugin.is_enabled as i32, plugin.sort_order,
         ],
     )?;
@@ -574,6 +1368,9 @@ pub struct WorkbenchStepTranslate {
     pub translated_subtitles_path: Option<String>,
     pub subtitle_count: i32,
     pub completed_at: Option<String>,
+    /// LLM summary of the subtitles, used as default world-building context.
+    #[serde(default)]
+    pub content_summary: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -717,7 +1514,8 @@ pub fn get_workbench_task_full(conn: &Connection, task_id: &str) -> Result<Optio
 
     // Load translate step
     let mut tr_stmt = conn.prepare(
-        "SELECT task_id, config_json, translated_subtitles_path, subtitle_count, completed_at
+        "SELECT task_id, config_json, translated_subtitles_path, subtitle_count, completed_at,
+                content_summary
          FROM workbench_step_translate WHERE task_id=?1",
     )?;
     let mut tr_rows = tr_stmt.query([task_id])?;
@@ -728,6 +1526,7 @@ pub fn get_workbench_task_full(conn: &Connection, task_id: &str) -> Result<Optio
             translated_subtitles_path: row.get(2)?,
             subtitle_count: row.get(3)?,
             completed_at: row.get(4)?,
+            content_summary: row.get(5)?,
         });
     }
 
@@ -752,11 +1551,17 @@ pub fn upsert_step_transcribe(conn: &Connection, step: &WorkbenchStepTranscribe)
     Ok(())
 }
 
+/// Saving the step keeps the stored content summary, which is written separately.
 pub fn upsert_step_translate(conn: &Connection, step: &WorkbenchStepTranslate) -> Result<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO workbench_step_translate
+        "INSERT INTO workbench_step_translate
          (task_id, config_json, translated_subtitles_path, subtitle_count, completed_at)
-         VALUES (?1,?2,?3,?4,?5)",
+         VALUES (?1,?2,?3,?4,?5)
+         ON CONFLICT(task_id) DO UPDATE SET
+             config_json = excluded.config_json,
+             translated_subtitles_path = excluded.translated_subtitles_path,
+             subtitle_count = excluded.subtitle_count,
+             completed_at = excluded.completed_at",
         rusqlite::params![
             step.task_id, step.config_json, step.translated_subtitles_path,
             step.subtitle_count, step.completed_at,
@@ -764,3 +1569,52 @@ pub fn upsert_step_translate(conn: &Connection, step: &WorkbenchStepTranslate) -
     )?;
     Ok(())
 }
+
+/// Translation settings saved on the workbench task working in `project_dir`, if any.
+pub fn get_translate_config_json(conn: &Connection, project_dir: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT s.config_json FROM workbench_step_translate s
+         JOIN workbench_tasks t ON t.id = s.task_id
+         WHERE t.project_dir = ?1",
+    )?;
+    let mut rows = stmt.query([project_dir])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+/// Content summary of the workbench task working in `project_dir`, if any.
+pub fn get_content_summary(conn: &Connection, project_dir: &str) -> Result<Option<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT s.content_summary FROM workbench_step_translate s
+         JOIN workbench_tasks t ON t.id = s.task_id
+         WHERE t.project_dir = ?1",
+    )?;
+    let mut rows = stmt.query([project_dir])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+/// Whether a workbench task works in `project_dir`.
+pub fn has_workbench_task(conn: &Connection, project_dir: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM workbench_tasks WHERE project_dir = ?1)",
+        [project_dir],
+        |row| row.get(0),
+    )
+}
+
+/// Store the content summary on the translate step of the task working in
+/// `project_dir`. Returns false when no workbench task uses that directory.
+pub fn set_content_summary(conn: &Connection, project_dir: &str, summary: &str) -> Result<bool> {
+    let changed = conn.execute(
+        "INSERT INTO workbench_step_translate (task_id, content_summary)
+         SELECT id, ?2 FROM workbench_tasks WHERE project_dir = ?1
+         ON CONFLICT(task_id) DO UPDATE SET content_summary = excluded.content_summary",
+        rusqlite::params![project_dir, summary],
+    )?;
+    Ok(changed > 0)
+}
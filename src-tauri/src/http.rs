@@ -0,0 +1,139 @@
+//! Outgoing HTTP clients. Every request to an AI endpoint, TTS service or
+//! transcription API goes through a client built here, so the proxy settings
+//! (global in `app_config`, overridable per AI config and TTS plugin) apply to all.
+//! Proxy passwords are stored sealed like API keys and only opened by `resolve`.
+
+use crate::db::queries;
+use crate::secrets;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// `app_config` keys of the global proxy.
+const KEY_MODE: &str = "network.proxy_mode";
+const KEY_URL: &str = "network.proxy_url";
+const KEY_USERNAME: &str = "network.proxy_username";
+pub const KEY_PASSWORD: &str = "network.proxy_password";
+const KEY_NO_PROXY: &str = "network.no_proxy";
+
+/// Proxy settings. `mode` is one of:
+/// - `inherit` (or empty): use the global setting; the default of overrides
+/// - `system`: the OS proxy settings and `HTTP_PROXY`/`HTTPS_PROXY`/`ALL_PROXY`
+/// - `none`: connect directly
+/// - `custom`: `url` (`http://`, `https://`, `socks5://` or `socks5h://`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProxyConfig {
+    pub mode: String,
+    pub url: String,
+    pub username: String,
+    /// Sealed (`secrets::seal`) as stored, in the clear once resolved.
+    pub password: String,
+    /// Comma-separated hosts, `.domain` suffixes or CIDRs that bypass the proxy.
+    pub no_proxy: String,
+}
+
+impl ProxyConfig {
+    /// The global proxy stored in `app_config`, password opened.
+    pub fn global(conn: &Connection) -> Result<Self, String> {
+        let config = queries::get_all_config(conn).map_err(|e| e.to_string())?;
+        let get = |key: &str| config.get(key).cloned().unwrap_or_default();
+        ProxyConfig {
+            mode: get(KEY_MODE),
+            url: get(KEY_URL),
+            username: get(KEY_USERNAME),
+            password: get(KEY_PASSWORD),
+            no_proxy: get(KEY_NO_PROXY),
+        }
+        .opened()
+    }
+
+    /// An override stored as JSON (`ai_configs.proxy`, a plugin's `config_json.proxy`).
+    /// Missing or unreadable overrides inherit.
+    pub fn from_value(value: &serde_json::Value) -> Self {
+        serde_json::from_value(value.clone()).unwrap_or_default()
+    }
+
+    pub fn inherits(&self) -> bool {
+        matches!(self.mode.trim(), "" | "inherit")
+    }
+
+    /// This override, or the global proxy of `conn` when it inherits; either way with
+    /// the password opened for use.
+    pub fn resolve(&self, conn: &Connection) -> Result<Self, String> {
+        if self.inherits() {
+            Self::global(conn)
+        } else {
+            self.clone().opened()
+        }
+    }
+
+    fn opened(mut self) -> Result<Self, String> {
+        self.password =
+            secrets::open(&self.password).map_err(|e| format!("代理密码: {e}"))?;
+        Ok(self)
+    }
+
+    /// Seal a password entered in the clear for storage.
+    pub fn seal_password(&mut self) -> Result<(), String> {
+        if !secrets::is_sealed(&self.password) {
+            self.password = secrets::seal(&self.password)?;
+        }
+        Ok(())
+    }
+
+    /// As shown to the frontend: the password masked, or empty if it can't be opened.
+    pub fn masked(&self) -> Self {
+        let password = secrets::open(&self.password).unwrap_or_default();
+        ProxyConfig { password: secrets::mask(&password), ..self.clone() }
+    }
+
+    /// As submitted by the frontend: a password left masked keeps the stored one.
+    pub fn unmasked(mut self, stored: &ProxyConfig) -> Self {
+        if secrets::is_masked(&self.password) {
+            self.password = stored.password.clone();
+        }
+        self
+    }
+}
+
+/// A client builder that routes through `proxy`; callers add timeouts and headers.
+pub fn client_builder(proxy: &ProxyConfig) -> Result<reqwest::ClientBuilder, String> {
+    let builder = reqwest::Client::builder();
+    match proxy.mode.trim() {
+        "" | "inherit" | "system" => Ok(builder),
+        "none" => Ok(builder.no_proxy()),
+        "custom" => {
+            let url = proxy.url.trim();
+            if url.is_empty() {
+                return Err("代理地址不能为空".to_string());
+            }
+            let url = if url.contains("://") {
+                url.to_string()
+            } else {
+                format!("http://{url}")
+            };
+            let scheme = url.split("://").next().unwrap_or_default().to_ascii_lowercase();
+            if !matches!(scheme.as_str(), "http" | "https" | "socks5" | "socks5h") {
+                return Err(format!(
+                    "不支持的代理协议 {scheme}，请使用 http、https、socks5 或 socks5h"
+                ));
+            }
+            let mut p = reqwest::Proxy::all(&url).map_err(|e| format!("代理地址无效: {e}"))?;
+            if !proxy.username.is_empty() {
+                p = p.basic_auth(&proxy.username, &proxy.password);
+            }
+            p = p.no_proxy(reqwest::NoProxy::from_string(&proxy.no_proxy));
+            Ok(builder.proxy(p))
+        }
+        other => Err(format!("未知代理模式: {other}")),
+    }
+}
+
+/// A client with `timeout` that routes through `proxy`.
+pub fn client(proxy: &ProxyConfig, timeout: Duration) -> Result<reqwest::Client, String> {
+    client_builder(proxy)?
+        .timeout(timeout)
+        .build()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,80 @@
+//! Per-line language identification, backed by the trigram profiles `whatlang`
+//! embeds in the binary.
+
+use whatlang::{Lang, Script};
+
+/// Lines with fewer letters than this are too short to identify.
+const MIN_LETTERS: usize = 4;
+
+/// Map a language setting to a `Lang`: the frontend's ISO 639-1 codes ("zh", "en"…),
+/// an ISO 639-3 code, or an English language name.
+pub fn parse_lang(setting: &str) -> Option<Lang> {
+    let setting = setting.trim().to_lowercase();
+    match setting.as_str() {
+        "zh" | "zh-cn" | "zh-tw" | "chinese" => Some(Lang::Cmn),
+        "en" => Some(Lang::Eng),
+        "ja" => Some(Lang::Jpn),
+        "ko" => Some(Lang::Kor),
+        "es" => Some(Lang::Spa),
+        "fr" => Some(Lang::Fra),
+        "de" => Some(Lang::Deu),
+        "ru" => Some(Lang::Rus),
+        _ => Lang::from_code(setting.as_str()).or_else(|| {
+            Lang::all()
+                .iter()
+                .copied()
+                .find(|l| l.eng_name().eq_ignore_ascii_case(&setting))
+        }),
+    }
+}
+
+fn is_kana(c: char) -> bool {
+    ('\u{3040}'..='\u{30FF}').contains(&c)
+}
+
+fn letter_count(text: &str) -> usize {
+    text.chars().filter(|c| c.is_alphabetic()).count()
+}
+
+/// Identify the language of a single line. `None` when the line is too short or the
+/// detector is unsure between languages sharing a script.
+pub fn identify(text: &str) -> Option<Lang> {
+    if letter_count(text) < MIN_LETTERS {
+        return None;
+    }
+    // Kana only appear in Japanese; kanji-heavy lines would otherwise read as Chinese
+    if text.chars().any(is_kana) {
+        return Some(Lang::Jpn);
+    }
+    let info = whatlang::detect(text)?;
+    // Scripts used by a single language (Hangul, Han…) are decisive on their own
+    if info.script().langs().len() == 1 || info.is_reliable() {
+        Some(info.lang())
+    } else {
+        None
+    }
+}
+
+/// Lines without any letters (numbers, punctuation, music symbols) have nothing
+/// to translate.
+pub fn untranslatable(text: &str) -> bool {
+    letter_count(text) == 0
+}
+
+/// If `text` is confidently not in `expected`, what it looks like instead: an
+/// ISO 639-3 code, or a script name when only the script is certain.
+pub fn mismatch(text: &str, expected: Lang) -> Option<String> {
+    if letter_count(text) < MIN_LETTERS {
+        return None;
+    }
+    if let Some(detected) = identify(text) {
+        // All-kanji Japanese lines are indistinguishable from Chinese
+        let ambiguous = expected == Lang::Jpn && detected == Lang::Cmn;
+        return (detected != expected && !ambiguous).then(|| detected.code().to_string());
+    }
+    // Unsure of the language, but a script the expected language never uses is enough
+    let script = whatlang::detect_script(text)?;
+    let uses_script = script.langs().contains(&expected)
+        || (expected == Lang::Jpn && script == Script::Mandarin);
+    (!uses_script).then(|| script.name().to_lowercase())
+}
@@ -3,6 +3,13 @@ mod db;
 mod ai_pool;
 mod tts;
 mod media;
+mod langid;
+mod llm_journal;
+mod llm_cache;
+mod markup;
+mod http;
+mod model_probe;
+mod secrets;
 
 use db::connection::{DbState, open};
 use db::migration;
@@ -31,14 +38,25 @@ fn get_app_data_dir() -> Result<PathBuf, String> {
     Ok(exe_dir.join("dubverse_data"))
 }
 
+/// All settings, secret ones (the proxy password) masked.
 #[tauri::command]
 fn cmd_get_all_config(state: State<DbState>) -> Result<HashMap<String, String>, String> {
     let conn = state.0.lock().map_err(|e| e.to_string())?;
-    db::queries::get_all_config(&conn).map_err(|e| e.to_string())
+    let mut config = db::queries::get_all_config(&conn).map_err(|e| e.to_string())?;
+    for (key, value) in config.iter_mut() {
+        if db::queries::is_secret_config(key) {
+            *value = secrets::mask(&secrets::open(value).unwrap_or_default());
+        }
+    }
+    Ok(config)
 }
 
 #[tauri::command]
 fn cmd_set_config(state: State<DbState>, key: String, value: String) -> Result<(), String> {
+    // A secret setting written back still masked keeps its stored value
+    if db::queries::is_secret_config(&key) && secrets::is_masked(&value) {
+        return Ok(());
+    }
     let conn = state.0.lock().map_err(|e| e.to_string())?;
     db::queries::set_config(&conn, &key, &value).map_err(|e| e.to_string())
 }
@@ -49,7 +67,8 @@ fn cmd_get_provider_secret(
     provider_id: String,
 ) -> Result<Option<String>, String> {
     let conn = state.0.lock().map_err(|e| e.to_string())?;
-    db::queries::get_provider_secret(&conn, &provider_id).map_err(|e| e.to_string())
+    let secret = db::queries::get_provider_secret(&conn, &provider_id).map_err(|e| e.to_string())?;
+    Ok(secret.map(|json| secrets::mask_json(&json)))
 }
 
 #[tauri::command]
@@ -59,6 +78,15 @@ fn cmd_set_provider_secret(
     secret_json: String,
 ) -> Result<(), String> {
     let conn = state.0.lock().map_err(|e| e.to_string())?;
+    // Fields left masked by the frontend keep their stored values. Only then is the
+    // stored secret read, so one that can't be decrypted can still be replaced.
+    let secret_json = if secrets::is_masked(&secret_json) {
+        let stored =
+            db::queries::get_provider_secret(&conn, &provider_id).map_err(|e| e.to_string())?;
+        secrets::unmask_json(&secret_json, stored.as_deref())
+    } else {
+        secret_json
+    };
     db::queries::set_provider_secret(&conn, &provider_id, &secret_json).map_err(|e| e.to_string())
 }
 
@@ -71,6 +99,10 @@ pub fn run() {
             cmd_set_config,
             cmd_get_provider_secret,
             cmd_set_provider_secret,
+            commands::secret_store::cmd_get_secret_store_status,
+            commands::secret_store::cmd_unlock_secret_store,
+            commands::secret_store::cmd_set_secret_passphrase,
+            commands::secret_store::cmd_reset_secret_store,
             commands::transcribe::cmd_create_project_dir,
             commands::transcribe::cmd_create_cache_dir,
             commands::transcribe::cmd_extract_audio,
@@ -84,9 +116,41 @@ pub fn run() {
             commands::ai_config::cmd_delete_ai_config,
             commands::ai_config::cmd_set_default_ai_config,
             commands::ai_config::cmd_test_ai_connection,
+            commands::ai_config::cmd_reveal_ai_config,
+            commands::ai_config::cmd_list_ai_models,
+            commands::ai_config::cmd_probe_ai_model,
+            commands::ai_config::cmd_get_throttle_events,
+            commands::ai_config::cmd_get_ai_pool_stats,
+            commands::ai_config::cmd_get_llm_cache_info,
+            commands::ai_config::cmd_clear_llm_cache,
             commands::translate::cmd_start_translation,
             commands::translate::cmd_cancel_translation,
             commands::translate::cmd_clear_translation_progress,
+            commands::translate::cmd_get_translation_reviews,
+            commands::translate::cmd_retry_failed_lines,
+            commands::translate::cmd_get_translation_line_issues,
+            commands::translate::cmd_extract_terminology,
+            commands::translate::cmd_get_glossary_terms,
+            commands::translate::cmd_save_glossary_terms,
+            commands::translate::cmd_summarize_content,
+            commands::translate::cmd_get_content_summary,
+            commands::translate::cmd_save_content_summary,
+            commands::translate::cmd_retranslate_lines,
+            commands::translate::cmd_apply_line_choices,
+            commands::correction::cmd_get_correction_diffs,
+            commands::correction::cmd_set_correction_decisions,
+            commands::correction::cmd_set_all_correction_decisions,
+            // Prompt templates
+            commands::prompt_template::cmd_get_prompt_templates,
+            commands::prompt_template::cmd_get_prompt_template_versions,
+            commands::prompt_template::cmd_create_prompt_template,
+            commands::prompt_template::cmd_update_prompt_template,
+            commands::prompt_template::cmd_restore_prompt_template_version,
+            commands::prompt_template::cmd_delete_prompt_template,
+            commands::prompt_template::cmd_export_prompt_templates,
+            commands::prompt_template::cmd_import_prompt_templates,
+            commands::prompt_template::cmd_get_prompt_placeholders,
+            commands::prompt_template::cmd_render_prompt_template,
             // Dubbing pipeline
             commands::dubbing::cmd_init_dubbing_job,
             commands::dubbing::cmd_get_dubbing_job,
@@ -121,12 +185,23 @@ pub fn run() {
             let data_dir = get_app_data_dir().expect("get app data dir");
             std::fs::create_dir_all(&data_dir).expect("create data dir");
 
+            // Secrets are encrypted with a key kept in the user's config dir, apart from the DB
+            let key_dir = app.path().app_config_dir().expect("get app config dir");
+            // A damaged key file leaves the store locked; the unlock dialog offers a reset
+            let secret_store = secrets::SecretStoreState::init(&key_dir);
+
             // Initialize database
             let conn = open(data_dir.clone()).expect("open db");
             migration::run(&conn).expect("run migrations");
+            if secrets::is_unlocked() {
+                db::queries::seal_plaintext_secrets(&conn).expect("encrypt secrets");
+            }
+            app.manage(secret_store);
             app.manage(DbState(Mutex::new(conn)));
+            app.manage(llm_cache::LlmCacheState(Arc::new(llm_cache::LlmCache::new(&data_dir))));
             app.manage(DataDirState(data_dir));
             app.manage(ai_pool::AiPoolManager::new());
+            tauri::async_runtime::spawn(ai_pool::emit_stats(app.handle().clone()));
             app.manage(commands::translate::TranslateCancelState(Arc::new(AtomicBool::new(false))));
             app.manage(commands::dubbing::DubbingCancelState(Arc::new(AtomicBool::new(false))));
 
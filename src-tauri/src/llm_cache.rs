@@ -0,0 +1,288 @@
+//! On-disk cache of LLM responses, so re-running a translation after a crash or a
+//! prompt tweak doesn't pay again for requests that were already answered. Entries
+//! are keyed by endpoint, model, prompts and temperature and stored one file each
+//! under `llm_cache/` in the data dir; the least recently used are evicted once the
+//! cache outgrows its size limit. Off unless enabled in settings.
+
+use crate::db::queries;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+const CACHE_DIR: &str = "llm_cache";
+/// `app_config` keys of the cache settings.
+const KEY_ENABLED: &str = "llm_cache.enabled";
+const KEY_MAX_MB: &str = "llm_cache.max_mb";
+/// Size limit when `llm_cache.max_mb` is unset.
+const DEFAULT_MAX_MB: u64 = 200;
+/// Eviction trims the cache to this share of its limit, so it doesn't run on every insert.
+const EVICT_TARGET: f64 = 0.9;
+
+/// Requests to an OpenAI-compatible `/chat/completions` endpoint.
+pub const PROTOCOL_CHAT: &str = "openai-chat";
+/// The same with `response_format: json_object`.
+pub const PROTOCOL_CHAT_JSON: &str = "openai-chat-json";
+
+/// Shared state: the cache of the app's data dir.
+pub struct LlmCacheState(pub Arc<LlmCache>);
+
+/// What a response is cached under.
+pub struct CacheRequest<'a> {
+    pub protocol: &'a str,
+    /// Providers can serve different models under the same name.
+    pub base_url: &'a str,
+    pub model: &'a str,
+    pub system_prompt: &'a str,
+    pub user_content: &'a str,
+    pub temperature: f64,
+}
+
+impl CacheRequest<'_> {
+    fn key(&self) -> String {
+        let CacheRequest {
+            protocol, base_url, model, system_prompt, user_content, temperature,
+        } = self;
+        let base_url = base_url.trim().trim_end_matches('/');
+        format!(
+            "{:x}",
+            md5::compute(format!(
+                "{protocol}\u{1f}{base_url}\u{1f}{model}\u{1f}{system_prompt}\u{1f}\
+                 {user_content}\u{1f}{temperature}"
+            ))
+        )
+    }
+}
+
+struct Entry {
+    bytes: u64,
+    last_used: SystemTime,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    bytes: u64,
+    loaded: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmCacheInfo {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+pub struct LlmCache {
+    dir: PathBuf,
+    index: Mutex<Index>,
+}
+
+impl LlmCache {
+    pub fn new(data_dir: &Path) -> Self {
+        LlmCache {
+            dir: data_dir.join(CACHE_DIR),
+            index: Mutex::new(Index::default()),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.txt"))
+    }
+
+    /// The index, scanned from disk on first use. A file's mtime is its last use.
+    fn index(&self) -> MutexGuard<'_, Index> {
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        if !index.loaded {
+            index.loaded = true;
+            for entry in fs::read_dir(&self.dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("txt") {
+                    continue;
+                }
+                let Some(key) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+                let Ok(meta) = entry.metadata() else { continue };
+                let last_used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                index.bytes += meta.len();
+                index.entries.insert(key.to_string(), Entry { bytes: meta.len(), last_used });
+            }
+        }
+        index
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        let mut index = self.index();
+        if !index.entries.contains_key(key) {
+            return None;
+        }
+        let path = self.path(key);
+        let Ok(response) = fs::read_to_string(&path) else {
+            Self::drop_entry(&mut index, key);
+            return None;
+        };
+        let now = SystemTime::now();
+        if let Some(entry) = index.entries.get_mut(key) {
+            entry.last_used = now;
+        }
+        // Persist the use so eviction order survives restarts
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(now);
+        }
+        Some(response)
+    }
+
+    fn put(&self, key: &str, response: &str, max_bytes: u64) {
+        let mut index = self.index();
+        if fs::create_dir_all(&self.dir).is_err() || fs::write(self.path(key), response).is_err() {
+            return;
+        }
+        Self::drop_entry(&mut index, key);
+        let bytes = response.len() as u64;
+        index.bytes += bytes;
+        index.entries.insert(key.to_string(), Entry { bytes, last_used: SystemTime::now() });
+        if index.bytes > max_bytes {
+            self.evict(&mut index, (max_bytes as f64 * EVICT_TARGET) as u64);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let mut index = self.index();
+        if Self::drop_entry(&mut index, key) {
+            let _ = fs::remove_file(self.path(key));
+        }
+    }
+
+    fn drop_entry(index: &mut Index, key: &str) -> bool {
+        match index.entries.remove(key) {
+            Some(old) => {
+                index.bytes = index.bytes.saturating_sub(old.bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Delete least recently used entries until the cache fits in `target` bytes.
+    fn evict(&self, index: &mut Index, target: u64) {
+        let mut by_age: Vec<(SystemTime, String)> = index
+            .entries
+            .iter()
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        by_age.sort();
+        for (_, key) in by_age {
+            if index.bytes <= target {
+                break;
+            }
+            Self::drop_entry(index, &key);
+            let _ = fs::remove_file(self.path(&key));
+        }
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        let mut index = self.index();
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir).map_err(|e| format!("清空响应缓存失败: {e}"))?;
+        }
+        index.entries.clear();
+        index.bytes = 0;
+        Ok(())
+    }
+
+    pub fn info(&self) -> LlmCacheInfo {
+        let index = self.index();
+        LlmCacheInfo { entries: index.entries.len(), bytes: index.bytes }
+    }
+}
+
+/// Cache hits and misses of one run, reported with its progress.
+#[derive(Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheCounts {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+/// One run's use of the cache, with the settings it started with.
+pub struct CacheSession {
+    /// `None` when the cache is disabled.
+    cache: Option<Arc<LlmCache>>,
+    max_bytes: u64,
+    /// False for a session that only stores fresh answers and never serves any.
+    serve: bool,
+    hits: AtomicU32,
+    misses: AtomicU32,
+}
+
+impl CacheSession {
+    pub fn disabled() -> Self {
+        CacheSession {
+            cache: None,
+            max_bytes: 0,
+            serve: true,
+            hits: AtomicU32::new(0),
+            misses: AtomicU32::new(0),
+        }
+    }
+
+    /// A session on the same cache that asks the model every time and stores the
+    /// new answers over the old ones, for requests whose cached answer was rejected.
+    pub fn write_only(&self) -> Self {
+        CacheSession {
+            cache: self.cache.clone(),
+            max_bytes: self.max_bytes,
+            serve: false,
+            ..Self::disabled()
+        }
+    }
+
+    /// A session on `cache` if the settings in `conn` enable it.
+    pub fn open(cache: &Arc<LlmCache>, conn: &Connection) -> Result<Self, String> {
+        let config = queries::get_all_config(conn).map_err(|e| e.to_string())?;
+        if config.get(KEY_ENABLED).map(String::as_str) != Some("true") {
+            return Ok(Self::disabled());
+        }
+        let max_mb = config
+            .get(KEY_MAX_MB)
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .filter(|&mb| mb > 0)
+            .unwrap_or(DEFAULT_MAX_MB);
+        Ok(CacheSession {
+            cache: Some(Arc::clone(cache)),
+            max_bytes: max_mb * 1024 * 1024,
+            ..Self::disabled()
+        })
+    }
+
+    pub fn lookup(&self, req: &CacheRequest) -> Option<String> {
+        let cache = self.cache.as_ref().filter(|_| self.serve)?;
+        let hit = cache.get(&req.key());
+        let counter = if hit.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        hit
+    }
+
+    pub fn store(&self, req: &CacheRequest, response: &str) {
+        if let Some(cache) = &self.cache {
+            cache.put(&req.key(), response, self.max_bytes);
+        }
+    }
+
+    /// Drop a response the caller rejected, so retrying the request asks the model again.
+    pub fn forget(&self, req: &CacheRequest) {
+        if let Some(cache) = &self.cache {
+            cache.remove(&req.key());
+        }
+    }
+
+    pub fn counts(&self) -> CacheCounts {
+        CacheCounts {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Per-project journal of LLM calls, off unless a run asks for it. In record mode
+//! every request and response is appended to `llm_journal.jsonl` in the project
+//! dir; in replay mode responses are served from that file instead of the network,
+//! so prompt or parser changes can be reproduced offline.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const JOURNAL_FILE: &str = "llm_journal.jsonl";
+/// Replay runs are journaled separately so the recording stays untouched.
+const REPLAY_JOURNAL_FILE: &str = "llm_journal.replay.jsonl";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum JournalMode {
+    Off,
+    Record,
+    Replay,
+}
+
+impl JournalMode {
+    /// "off", "record" or "replay"; empty means off.
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "" | "off" => Ok(JournalMode::Off),
+            "record" => Ok(JournalMode::Record),
+            "replay" => Ok(JournalMode::Replay),
+            other => Err(format!("未知的调用日志模式: {other}")),
+        }
+    }
+}
+
+/// What was asked of the model, and from where in the retry ladder.
+pub struct LlmRequest<'a> {
+    pub task: &'a str,
+    pub phase: &'a str,
+    /// Position in the retry ladder, e.g. "batch#2", "split1#1", "single#1".
+    pub path: &'a str,
+    pub config_id: &'a str,
+    pub model: &'a str,
+    pub system_prompt: &'a str,
+    pub user_content: &'a str,
+    pub temperature: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JournalEntry {
+    ts: String,
+    task: String,
+    phase: String,
+    path: String,
+    config_id: String,
+    model: String,
+    temperature: f64,
+    system_prompt: String,
+    user_content: String,
+    #[serde(default)]
+    output: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+    /// "ok", the parse error, or "skipped" when the request itself failed.
+    parse: String,
+    latency_ms: u64,
+    #[serde(default)]
+    replayed: bool,
+}
+
+/// Recorded responses for one request, served in recording order. The last one
+/// repeats once the queue is exhausted.
+struct ReplayQueue {
+    responses: Vec<Result<String, String>>,
+    next: usize,
+}
+
+impl ReplayQueue {
+    fn pop(&mut self) -> Option<Result<String, String>> {
+        let response = self.responses.get(self.next).or(self.responses.last())?.clone();
+        self.next = (self.next + 1).min(self.responses.len());
+        Some(response)
+    }
+}
+
+pub struct Journal {
+    mode: JournalMode,
+    path: PathBuf,
+    file: Mutex<Option<File>>,
+    /// Keyed by system prompt + user content + temperature.
+    exact: Mutex<HashMap<String, ReplayQueue>>,
+    /// Keyed by phase + user content, for replays after a prompt change.
+    loose: Mutex<HashMap<String, ReplayQueue>>,
+    secrets: Vec<String>,
+}
+
+fn exact_key(system_prompt: &str, user_content: &str, temperature: f64) -> String {
+    format!("{:x}", md5::compute(format!("{system_prompt}\u{1f}{user_content}\u{1f}{temperature}")))
+}
+
+fn loose_key(task: &str, phase: &str, user_content: &str) -> String {
+    format!("{:x}", md5::compute(format!("{task}\u{1f}{phase}\u{1f}{user_content}")))
+}
+
+/// Mask `secrets`, and the value of any `Authorization` header an error echoes
+/// back (`Authorization: …` or `"authorization": "…"`). Subtitle text is left
+/// alone even where it looks like a key.
+fn redact(text: &str, secrets: &[String]) -> String {
+    let mut out = text.to_string();
+    for secret in secrets {
+        out = out.replace(secret.as_str(), "***");
+    }
+    let mut result = String::with_capacity(out.len());
+    let mut rest = out.as_str();
+    while let Some(start) = find_ascii_ci(rest, AUTH_HEADER) {
+        let name_end = start + AUTH_HEADER.len();
+        let after = rest[name_end..].trim_start_matches('"').trim_start();
+        let Some(value) = after.strip_prefix(':') else {
+            result.push_str(&rest[..name_end]);
+            rest = &rest[name_end..];
+            continue;
+        };
+        let value = value.trim_start().trim_start_matches('"');
+        let value_start = rest.len() - value.len();
+        let value_len = value.find(['\n', '\r', '"']).unwrap_or(value.len());
+        result.push_str(&rest[..value_start]);
+        result.push_str("***");
+        rest = &rest[value_start + value_len..];
+    }
+    result.push_str(rest);
+    result
+}
+
+const AUTH_HEADER: &str = "authorization";
+
+/// Byte offset of the first ASCII-case-insensitive match of `needle` (lowercase).
+fn find_ascii_ci(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+impl Journal {
+    /// A journal that neither records nor replays.
+    pub fn disabled() -> Self {
+        Journal {
+            mode: JournalMode::Off,
+            path: PathBuf::new(),
+            file: Mutex::new(None),
+            exact: Mutex::new(HashMap::new()),
+            loose: Mutex::new(HashMap::new()),
+            secrets: Vec::new(),
+        }
+    }
+
+    /// Open the journal of `project_dir`. `secrets` (API keys) are redacted from
+    /// everything written. Replay mode loads the existing recording.
+    pub fn open(project_dir: &str, mode: JournalMode, secrets: Vec<String>) -> Result<Self, String> {
+        let secrets: Vec<String> = secrets.into_iter().filter(|s| s.len() >= 8).collect();
+        let file_name = match mode {
+            JournalMode::Off => return Ok(Journal::disabled()),
+            JournalMode::Record => JOURNAL_FILE,
+            JournalMode::Replay => REPLAY_JOURNAL_FILE,
+        };
+        let mut exact = HashMap::new();
+        let mut loose = HashMap::new();
+        if mode == JournalMode::Replay {
+            let recording = Path::new(project_dir).join(JOURNAL_FILE);
+            let file = File::open(&recording)
+                .map_err(|e| format!("无法读取调用日志 {}: {e}", recording.display()))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| e.to_string())?;
+                let Ok(entry) = serde_json::from_str::<JournalEntry>(&line) else {
+                    continue;
+                };
+                let response = match (entry.output, entry.error) {
+                    (Some(output), _) => Ok(output),
+                    (None, Some(error)) => Err(error),
+                    (None, None) => continue,
+                };
+                for (map, key) in [
+                    (&mut exact, exact_key(&entry.system_prompt, &entry.user_content, entry.temperature)),
+                    (&mut loose, loose_key(&entry.task, &entry.phase, &entry.user_content)),
+                ] {
+                    map.entry(key)
+                        .or_insert_with(|| ReplayQueue { responses: Vec::new(), next: 0 })
+                        .responses
+                        .push(response.clone());
+                }
+            }
+        }
+        Ok(Journal {
+            mode,
+            path: Path::new(project_dir).join(file_name),
+            file: Mutex::new(None),
+            exact: Mutex::new(exact),
+            loose: Mutex::new(loose),
+            secrets,
+        })
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.mode == JournalMode::Replay
+    }
+
+    /// In replay mode, the recorded response for `req`: an exact match first, then
+    /// the same input in the same phase. `None` outside replay mode.
+    pub fn replay(&self, req: &LlmRequest) -> Option<Result<String, String>> {
+        if !self.is_replay() {
+            return None;
+        }
+        let system_prompt = redact(req.system_prompt, &self.secrets);
+        let user_content = redact(req.user_content, &self.secrets);
+        let exact = exact_key(&system_prompt, &user_content, req.temperature);
+        if let Some(response) = self.exact.lock().ok()?.get_mut(&exact).and_then(ReplayQueue::pop) {
+            return Some(response);
+        }
+        let loose = loose_key(req.task, req.phase, &user_content);
+        let response = self.loose.lock().ok()?.get_mut(&loose).and_then(ReplayQueue::pop);
+        Some(response.unwrap_or_else(|| Err("回放：调用日志中没有对应的请求".to_string())))
+    }
+
+    /// Append one exchange. `parse_error` is the parser's verdict on a successful
+    /// response. Journal write failures never fail the caller.
+    pub fn record(
+        &self,
+        req: &LlmRequest,
+        response: &Result<String, String>,
+        parse_error: Option<&str>,
+        latency_ms: u64,
+    ) {
+        if self.mode == JournalMode::Off {
+            return;
+        }
+        let parse = match (response, parse_error) {
+            (Err(_), _) => "skipped".to_string(),
+            (Ok(_), None) => "ok".to_string(),
+            (Ok(_), Some(e)) => e.to_string(),
+        };
+        let entry = JournalEntry {
+            ts: chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f").to_string(),
+            task: req.task.to_string(),
+            phase: req.phase.to_string(),
+            path: req.path.to_string(),
+            config_id: req.config_id.to_string(),
+            model: req.model.to_string(),
+            temperature: req.temperature,
+            system_prompt: redact(req.system_prompt, &self.secrets),
+            user_content: redact(req.user_content, &self.secrets),
+            output: response.as_ref().ok().map(|o| redact(o, &self.secrets)),
+            error: response.as_ref().err().map(|e| redact(e, &self.secrets)),
+            parse: redact(&parse, &self.secrets),
+            latency_ms,
+            replayed: self.is_replay(),
+        };
+        let Ok(line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        let Ok(mut guard) = self.file.lock() else {
+            return;
+        };
+        if guard.is_none() {
+            *guard = OpenOptions::new().create(true).append(true).open(&self.path).ok();
+        }
+        if let Some(file) = guard.as_mut() {
+            let _ = writeln!(file, "{line}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_masks_known_secrets_and_auth_headers() {
+        let secrets = vec!["sk-live-0123456789".to_string()];
+        assert_eq!(redact("key sk-live-0123456789 leaked", &secrets), "key *** leaked");
+        assert_eq!(
+            redact("echo: Authorization: Bearer abc.def\nnext", &[]),
+            "echo: Authorization: ***\nnext"
+        );
+        assert_eq!(
+            redact(r#"{"authorization": "Bearer abc", "x": 1}"#, &[]),
+            r#"{"authorization": "***", "x": 1}"#
+        );
+    }
+
+    #[test]
+    fn redact_leaves_subtitle_text_alone() {
+        let text = "The bearer of bad news, sk-12345678901234567890, needs authorization";
+        assert_eq!(redact(text, &[]), text);
+    }
+}
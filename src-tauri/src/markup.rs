@@ -0,0 +1,235 @@
+//! Inline markup in subtitle text (`<i>`, ASS override blocks like `{\an8}`, `\N`
+//! and line breaks) is swapped for numbered placeholders before a line goes to the
+//! LLM and put back afterwards, so the model cannot mangle or drop it.
+
+/// Added to the system prompt when a batch contains placeholders.
+pub const PLACEHOLDER_RULE: &str = "\n- Placeholders like {{1}} stand for formatting tags: keep every one exactly once, unchanged, at the matching position in the output";
+
+/// Longest HTML-style tag taken for markup, in bytes.
+const MAX_HTML_TAG: usize = 100;
+
+/// Bytes of a tag or attribute name at the start of `bytes`.
+fn name_len(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b':'))
+        .count()
+}
+
+/// Bytes of an attribute value at the start of `bytes`: quoted, or a bare word.
+fn attr_value_len(bytes: &[u8]) -> Option<usize> {
+    match bytes.first()? {
+        q @ (b'"' | b'\'') => bytes[1..].iter().position(|b| b == q).map(|end| end + 2),
+        _ => {
+            let len = bytes
+                .iter()
+                .take_while(|b| !b.is_ascii_whitespace())
+                .take_while(|b| !matches!(b, b'>' | b'<' | b'"' | b'\'' | b'='))
+                .count();
+            (len > 0).then_some(len)
+        }
+    }
+}
+
+/// Length of the HTML-style tag `rest` starts with: `<i>`, `</i>`, `<br/>` or
+/// `<font color="red">`. Attributes need a value, so prose in angle brackets
+/// ("a <b and c> d") is not taken for a tag.
+fn html_tag_len(rest: &str) -> Option<usize> {
+    let bytes = rest.as_bytes();
+    if bytes.first() != Some(&b'<') {
+        return None;
+    }
+    let closing = bytes.get(1) == Some(&b'/');
+    let mut i = if closing { 2 } else { 1 };
+    if !bytes.get(i)?.is_ascii_alphabetic() {
+        return None;
+    }
+    i += name_len(&bytes[i..]);
+    loop {
+        let space = bytes[i..].iter().take_while(|b| b.is_ascii_whitespace()).count();
+        i += space;
+        match bytes.get(i)? {
+            b'>' => break,
+            b'/' if !closing && bytes.get(i + 1) == Some(&b'>') => {
+                i += 1;
+                break;
+            }
+            _ if space > 0 && !closing => {
+                let name = name_len(&bytes[i..]);
+                if name == 0 || bytes.get(i + name) != Some(&b'=') {
+                    return None;
+                }
+                i += name + 1;
+                i += attr_value_len(&bytes[i..])?;
+            }
+            _ => return None,
+        }
+        if i > MAX_HTML_TAG {
+            return None;
+        }
+    }
+    (i < MAX_HTML_TAG).then_some(i + 1)
+}
+
+/// Length in bytes of the tag `rest` starts with, if any.
+fn tag_len(rest: &str) -> Option<usize> {
+    if rest.starts_with("{\\") {
+        return rest.find('}').map(|end| end + 1);
+    }
+    if rest.starts_with("\\N") || rest.starts_with("\\n") || rest.starts_with("\\h") {
+        return Some(2);
+    }
+    if rest.starts_with("\r\n") {
+        return Some(2);
+    }
+    if rest.starts_with('\n') {
+        return Some(1);
+    }
+    if rest.starts_with('<') {
+        return html_tag_len(rest);
+    }
+    None
+}
+
+fn placeholder(n: usize) -> String {
+    format!("{{{{{n}}}}}")
+}
+
+/// Replace inline tags with `{{1}}`, `{{2}}`… Returns the protected text and the
+/// tags in placeholder order.
+pub fn protect(text: &str) -> (String, Vec<String>) {
+    let mut out = String::with_capacity(text.len());
+    let mut tags = Vec::new();
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        if let Some(len) = tag_len(rest) {
+            tags.push(rest[..len].to_string());
+            out.push_str(&placeholder(tags.len()));
+            i += len;
+        } else {
+            let c = rest.chars().next().unwrap_or_default();
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+    (out, tags)
+}
+
+/// Placeholder numbers in `text`, in order of appearance.
+fn placeholders_in(text: &str) -> Vec<usize> {
+    let mut found = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 && after[digits..].starts_with("}}") {
+            if let Ok(n) = after[..digits].parse() {
+                found.push(n);
+            }
+            rest = &after[digits + 2..];
+        } else {
+            rest = after;
+        }
+    }
+    found
+}
+
+/// Whether `text` contains any inline tag.
+pub fn has_tags(text: &str) -> bool {
+    let mut i = 0;
+    while i < text.len() {
+        if tag_len(&text[i..]).is_some() {
+            return true;
+        }
+        i += text[i..].chars().next().map_or(1, char::len_utf8);
+    }
+    false
+}
+
+/// Whether `output` carries each placeholder of `input` exactly once.
+pub fn intact(input: &str, output: &str) -> bool {
+    let mut expected = placeholders_in(input);
+    let mut found = placeholders_in(output);
+    expected.sort_unstable();
+    found.sort_unstable();
+    expected == found
+}
+
+/// Put the tags back. Tags whose placeholder the model dropped are re-attached
+/// where they most likely belong: at the start if the original began with them,
+/// otherwise at the end; dropped line breaks are left out.
+pub fn restore(output: &str, tags: &[String], original: &str) -> String {
+    if tags.is_empty() {
+        return output.to_string();
+    }
+    let mut text = output.to_string();
+    let mut missing = Vec::new();
+    for (i, tag) in tags.iter().enumerate() {
+        let ph = placeholder(i + 1);
+        if text.contains(&ph) {
+            text = text.replacen(&ph, tag, 1);
+            // Stray duplicates carry no information
+            text = text.replace(&ph, "");
+        } else {
+            missing.push(tag);
+        }
+    }
+    let mut prefix = String::new();
+    let mut leading = original;
+    for tag in missing {
+        if matches!(tag.as_str(), "\\N" | "\\n" | "\\h" | "\n" | "\r\n") {
+            continue;
+        }
+        if let Some(after) = leading.strip_prefix(tag.as_str()) {
+            prefix.push_str(tag);
+            leading = after;
+        } else {
+            text.push_str(tag);
+        }
+    }
+    format!("{prefix}{text}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protect_swaps_tags_for_placeholders() {
+        let (text, tags) = protect("{\\an8}<i>Hello</i>\\Nworld");
+        assert_eq!(text, "{{1}}{{2}}Hello{{3}}{{4}}world");
+        assert_eq!(tags, ["{\\an8}", "<i>", "</i>", "\\N"]);
+        let (text, tags) = protect(r#"<font color="red">a</font> <br/>"#);
+        assert_eq!(text, "{{1}}a{{2}} {{3}}");
+        assert_eq!(tags.len(), 3);
+    }
+
+    #[test]
+    fn prose_in_angle_brackets_is_not_a_tag() {
+        for text in ["a <b and c> d", "x < y > z", "1 <2> 3", "<i class>", "</i x=1>"] {
+            assert!(!has_tags(text), "{text}");
+            assert_eq!(protect(text).0, text);
+        }
+    }
+
+    #[test]
+    fn intact_compares_placeholder_sets() {
+        assert!(intact("{{1}}a{{2}}", "{{2}}b{{1}}"));
+        assert!(!intact("{{1}}a{{2}}", "{{1}}b"));
+        assert!(!intact("{{1}}a", "{{1}}b{{1}}"));
+        assert!(intact("plain", "text"));
+    }
+
+    #[test]
+    fn restore_puts_tags_back_and_reattaches_dropped_ones() {
+        let tags = ["<i>".to_string(), "</i>".to_string()];
+        assert_eq!(restore("{{1}}你好{{2}}", &tags, "<i>Hi</i>"), "<i>你好</i>");
+        // Dropped: the opening tag goes back to the start, the closing one to the end
+        assert_eq!(restore("你好", &tags, "<i>Hi</i>"), "<i>你好</i>");
+        // Duplicates are dropped, lost line breaks stay out
+        let tags = ["\\N".to_string()];
+        assert_eq!(restore("a{{1}}b{{1}}", &tags, "a\\Nb"), "a\\Nb");
+        assert_eq!(restore("ab", &tags, "a\\Nb"), "ab");
+    }
+}
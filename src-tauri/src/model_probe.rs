@@ -0,0 +1,275 @@
+//! Model discovery and capability probing for OpenAI-compatible endpoints. The
+//! probed capabilities are stored on the AI config, and translation uses them to
+//! request JSON mode and to size batches to the context window.
+
+use crate::commands::translate::estimate_tokens;
+use crate::db::queries::AiConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// Context-length fields of `/models` entries across providers (OpenRouter, Groq,
+/// Mistral, vLLM, …).
+const CONTEXT_FIELDS: &[&str] =
+    &["context_length", "context_window", "max_context_length", "max_model_len"];
+const OUTPUT_FIELDS: &[&str] = &["max_completion_tokens", "max_output_tokens"];
+
+const SAMPLE_SYSTEM: &str = "Translate the values of the user's JSON object into Chinese. \
+Reply with a JSON object with the same keys and nothing else.";
+const SAMPLE_USER: &str = r#"{"1": "Good morning, everyone.",
+"2": "Today we are going to talk about rivers.", "3": "Let's get started."}"#;
+const SAMPLE_MAX_TOKENS: u32 = 200;
+/// How much of a streamed reply is read looking for the first event.
+const STREAM_PEEK_BYTES: usize = 64 * 1024;
+
+/// One model of an endpoint's listing.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,
+    /// Context window, when the listing reports one; 0 = unknown.
+    pub context_length: u32,
+    /// Output limit, when the listing reports one; 0 = unknown.
+    pub max_output_tokens: u32,
+}
+
+/// What a probe found out about a model.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ModelCapabilities {
+    /// The model probed; the capabilities don't apply once the config's model changes.
+    pub model: String,
+    /// Accepts `response_format: json_object` and answers with a bare JSON object.
+    pub json_mode: bool,
+    pub streaming: bool,
+    /// From the endpoint's model listing; 0 = unknown.
+    pub context_length: u32,
+    pub max_output_tokens: u32,
+    /// Duration of the sample request.
+    pub latency_ms: u64,
+    /// Time to the first streamed event; 0 without streaming.
+    pub first_token_ms: u64,
+    /// Output tokens per second on the sample.
+    pub tokens_per_second: f64,
+    pub probed_at: String,
+}
+
+impl ModelCapabilities {
+    /// The probed capabilities of `cfg`, if they were probed on its current model.
+    pub fn of(cfg: &AiConfig) -> Self {
+        cfg.capabilities.clone().filter(|c| c.model == cfg.model).unwrap_or_default()
+    }
+
+    /// An input token budget that leaves room for the reply in the context window:
+    /// translations run about twice as long as their source, so a third of it.
+    /// 0 when the context length is unknown.
+    pub fn input_budget(&self) -> usize {
+        self.context_length as usize / 3
+    }
+}
+
+/// The models `base_url` offers, sorted by id. Tries the OpenAI-style `/models`
+/// listing, then Ollama's `/api/tags` next to an `/v1` base.
+pub async fn list_models(
+    client: &reqwest::Client,
+    base_url: &str,
+    api_key: &str,
+) -> Result<Vec<ModelInfo>, String> {
+    let base = base_url.trim().trim_end_matches('/');
+    let mut models = match openai_models(client, base, api_key).await {
+        Ok(models) => models,
+        Err(e) => match base.strip_suffix("/v1") {
+            Some(root) => ollama_models(client, root).await.map_err(|_| e)?,
+            None => return Err(e),
+        },
+    };
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    models.dedup_by(|a, b| a.id == b.id);
+    Ok(models)
+}
+
+async fn get_json(client: &reqwest::Client, url: &str, api_key: &str) -> Result<Value, String> {
+    let mut req = client.get(url);
+    if !api_key.is_empty() {
+        req = req.bearer_auth(api_key);
+    }
+    let resp = req.send().await.map_err(|e| format!("请求失败: {e}"))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let text = resp.text().await.unwrap_or_else(|_| status.to_string());
+        return Err(format!("HTTP {}: {}", status.as_u16(), text));
+    }
+    resp.json().await.map_err(|e| format!("解析模型列表失败: {e}"))
+}
+
+async fn openai_models(
+    client: &reqwest::Client,
+    base: &str,
+    api_key: &str,
+) -> Result<Vec<ModelInfo>, String> {
+    let body = get_json(client, &format!("{base}/models"), api_key).await?;
+    let list = body["data"]
+        .as_array()
+        .or_else(|| body.as_array())
+        .ok_or_else(|| "无法识别的模型列表格式".to_string())?;
+    Ok(list
+        .iter()
+        .filter_map(|m| {
+            Some(ModelInfo {
+                id: m["id"].as_str()?.to_string(),
+                context_length: number_field(m, CONTEXT_FIELDS),
+                max_output_tokens: number_field(m, OUTPUT_FIELDS)
+                    .max(number_field(&m["top_provider"], OUTPUT_FIELDS)),
+            })
+        })
+        .collect())
+}
+
+async fn ollama_models(client: &reqwest::Client, root: &str) -> Result<Vec<ModelInfo>, String> {
+    let body = get_json(client, &format!("{root}/api/tags"), "").await?;
+    let list = body["models"]
+        .as_array()
+        .ok_or_else(|| "无法识别的模型列表格式".to_string())?;
+    Ok(list
+        .iter()
+        .filter_map(|m| {
+            Some(ModelInfo {
+                id: m["name"].as_str()?.to_string(),
+                context_length: 0,
+                max_output_tokens: 0,
+            })
+        })
+        .collect())
+}
+
+/// The first of `fields` holding a positive number.
+fn number_field(value: &Value, fields: &[&str]) -> u32 {
+    fields
+        .iter()
+        .filter_map(|f| value[*f].as_u64())
+        .find(|&n| n > 0)
+        .map_or(0, |n| n.min(u32::MAX as u64) as u32)
+}
+
+struct Sample {
+    content: String,
+    latency: Duration,
+    completion_tokens: Option<u64>,
+}
+
+/// Probe `model` with a short translation sample: once with JSON mode (again
+/// without if that's rejected), once streamed. Context hints come from the listing.
+pub async fn probe(
+    client: &reqwest::Client,
+    base_url: &str,
+    api_key: &str,
+    model: &str,
+) -> Result<ModelCapabilities, String> {
+    let url = format!("{}/chat/completions", base_url.trim().trim_end_matches('/'));
+    let body = |json_mode: bool, stream: bool| {
+        let mut body = json!({
+            "model": model,
+            "messages": [
+                { "role": "system", "content": SAMPLE_SYSTEM },
+                { "role": "user", "content": SAMPLE_USER },
+            ],
+            "temperature": 0.3,
+            "max_tokens": SAMPLE_MAX_TOKENS,
+        });
+        if json_mode {
+            body["response_format"] = json!({ "type": "json_object" });
+        }
+        if stream {
+            body["stream"] = true.into();
+        }
+        body
+    };
+
+    // Endpoints without JSON mode either reject `response_format` or ignore it
+    let json_sample = sample_request(client, &url, api_key, &body(true, false)).await;
+    let (json_mode, sample) = match json_sample {
+        Ok(sample) => (is_json_object(&sample.content), sample),
+        Err(_) => (false, sample_request(client, &url, api_key, &body(false, false)).await?),
+    };
+    let first_event = first_stream_event(client, &url, api_key, &body(false, true)).await;
+
+    let tokens = sample
+        .completion_tokens
+        .unwrap_or_else(|| estimate_tokens(&sample.content) as u64);
+    let secs = sample.latency.as_secs_f64();
+    let listed = list_models(client, base_url, api_key)
+        .await
+        .ok()
+        .and_then(|models| models.into_iter().find(|m| m.id == model));
+    Ok(ModelCapabilities {
+        model: model.to_string(),
+        json_mode,
+        streaming: first_event.is_some(),
+        context_length: listed.as_ref().map_or(0, |m| m.context_length),
+        max_output_tokens: listed.as_ref().map_or(0, |m| m.max_output_tokens),
+        latency_ms: sample.latency.as_millis() as u64,
+        first_token_ms: first_event.map_or(0, |d| d.as_millis() as u64),
+        tokens_per_second: if secs > 0.0 { tokens as f64 / secs } else { 0.0 },
+        probed_at: chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+    })
+}
+
+async fn sample_request(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    body: &Value,
+) -> Result<Sample, String> {
+    let started = Instant::now();
+    let resp = client
+        .post(url)
+        .bearer_auth(api_key)
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {e}"))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let text = resp.text().await.unwrap_or_else(|_| status.to_string());
+        return Err(format!("HTTP {}: {}", status.as_u16(), text));
+    }
+    let reply: Value = resp.json().await.map_err(|e| format!("解析响应失败: {e}"))?;
+    let latency = started.elapsed();
+    let content = reply["choices"][0]["message"]["content"]
+        .as_str()
+        .ok_or_else(|| "API返回空choices".to_string())?
+        .to_string();
+    let completion_tokens = reply["usage"]["completion_tokens"].as_u64();
+    Ok(Sample { content, latency, completion_tokens })
+}
+
+fn is_json_object(content: &str) -> bool {
+    serde_json::from_str::<Value>(content.trim()).is_ok_and(|v| v.is_object())
+}
+
+/// Time to the first server-sent event of a streamed request; `None` if the
+/// endpoint fails or answers with a plain JSON body instead.
+async fn first_stream_event(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: &str,
+    body: &Value,
+) -> Option<Duration> {
+    let started = Instant::now();
+    let mut resp = client.post(url).bearer_auth(api_key).json(body).send().await.ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    let mut seen = String::new();
+    while let Some(chunk) = resp.chunk().await.ok()? {
+        seen.push_str(&String::from_utf8_lossy(&chunk));
+        // Lines starting with ':' are keep-alive comments some providers send first
+        if seen.lines().any(|l| l.starts_with("data:")) {
+            return Some(started.elapsed());
+        }
+        if seen.trim_start().starts_with('{') || seen.len() > STREAM_PEEK_BYTES {
+            return None;
+        }
+    }
+    None
+}
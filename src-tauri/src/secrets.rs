@@ -0,0 +1,452 @@
+//! Encryption at rest for API keys and provider secrets. Secret columns hold
+//! `enc:v1:` + base64(nonce ‖ AES-256-GCM ciphertext) under a data key that lives
+//! in the user's config dir rather than next to `dubverse.db`, so a copied database
+//! carries no usable keys. With a passphrase the key file holds the data key sealed
+//! under an Argon2id-derived key, and secrets stay locked until it's entered.
+//! Plaintext from before encryption reads as-is and is sealed once unlocked.
+//!
+//! The unlocked data key is process-wide so the query functions can seal and open
+//! columns without threading it through every caller.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+const KEY_FILE: &str = "secret.key";
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+/// Masked secrets are shown with this; real keys never contain it.
+const MASK: char = '•';
+
+type DataKey = [u8; 32];
+
+/// The data key while unlocked.
+static DATA_KEY: RwLock<Option<DataKey>> = RwLock::new(None);
+
+/// Shared state: the path of the key file.
+pub struct SecretStoreState(pub PathBuf);
+
+/// Contents of the key file: the data key itself, or sealed with a passphrase.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct KeyFile {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    key: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    sealed_key: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    salt: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretStoreStatus {
+    pub passphrase: bool,
+    pub unlocked: bool,
+    /// Why the key file can't be used (unreadable, damaged); it can then only be reset.
+    pub error: Option<String>,
+}
+
+impl SecretStoreState {
+    /// The key file in `dir`, created with a fresh data key on first run. Without a
+    /// passphrase the store is unlocked right away. A key file that can't be read
+    /// leaves the store locked, with the error reported by `status`.
+    pub fn init(dir: &Path) -> Self {
+        let state = SecretStoreState(dir.join(KEY_FILE));
+        let _ = state.load();
+        state
+    }
+
+    fn load(&self) -> Result<(), String> {
+        let file = match self.read()? {
+            Some(file) => {
+                let _ = restrict_permissions(&self.0);
+                file
+            }
+            None => self.create()?,
+        };
+        if !file.key.is_empty() {
+            set_data_key(Some(decode_key(&file.key)?));
+        }
+        Ok(())
+    }
+
+    /// A key file with a fresh data key and no passphrase.
+    fn create(&self) -> Result<KeyFile, String> {
+        let mut key = DataKey::default();
+        OsRng.fill_bytes(&mut key);
+        let file = KeyFile { key: STANDARD.encode(key), ..KeyFile::default() };
+        self.write(&file)?;
+        Ok(file)
+    }
+
+    fn read(&self) -> Result<Option<KeyFile>, String> {
+        if !self.0.exists() {
+            return Ok(None);
+        }
+        let text =
+            fs::read_to_string(&self.0).map_err(|e| format!("读取密钥文件失败: {e}"))?;
+        let file: KeyFile =
+            serde_json::from_str(&text).map_err(|e| format!("密钥文件已损坏: {e}"))?;
+        if file.sealed_key.is_empty() {
+            decode_key(&file.key)?;
+        }
+        Ok(Some(file))
+    }
+
+    /// Written aside and renamed over the key file, so a crash mid-write never leaves
+    /// it torn, and readable by the current user only.
+    fn write(&self, file: &KeyFile) -> Result<(), String> {
+        if let Some(dir) = self.0.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("创建密钥目录失败: {e}"))?;
+        }
+        let text = serde_json::to_string_pretty(file).map_err(|e| e.to_string())?;
+        let tmp = self.0.with_extension("key.tmp");
+        let _ = fs::remove_file(&tmp);
+        write_private(&tmp, text.as_bytes())
+            .and_then(|()| fs::rename(&tmp, &self.0))
+            .map_err(|e| format!("写入密钥文件失败: {e}"))
+    }
+
+    pub fn status(&self) -> SecretStoreStatus {
+        let (passphrase, error) = match self.read() {
+            Ok(Some(file)) => (!file.sealed_key.is_empty(), None),
+            Ok(None) => (false, Some("密钥文件不存在".to_string())),
+            Err(e) => (false, Some(e)),
+        };
+        SecretStoreStatus { passphrase, unlocked: is_unlocked(), error }
+    }
+
+    /// Replace an unusable key file with a fresh data key, keeping the old file as
+    /// `secret.key.broken` for recovery. Secrets sealed under the old key can't be
+    /// read afterwards and have to be entered again.
+    pub fn reset(&self) -> Result<(), String> {
+        if self.0.exists() {
+            fs::rename(&self.0, self.0.with_extension("key.broken"))
+                .map_err(|e| format!("备份密钥文件失败: {e}"))?;
+        }
+        set_data_key(None);
+        self.load()
+    }
+
+    /// Unlock with `passphrase`; a no-op without one.
+    pub fn unlock(&self, passphrase: &str) -> Result<(), String> {
+        let file = self.read()?.ok_or_else(|| "密钥文件不存在".to_string())?;
+        set_data_key(Some(file.data_key(passphrase)?));
+        Ok(())
+    }
+
+    /// Set, change or (with an empty `new`) remove the passphrase. `current` must
+    /// match the passphrase in use, if any. Only the key file changes: the data key
+    /// stays, so stored secrets aren't re-encrypted.
+    pub fn set_passphrase(&self, current: &str, new: &str) -> Result<(), String> {
+        let file = self.read()?.ok_or_else(|| "密钥文件不存在".to_string())?;
+        let key = file.data_key(current)?;
+        let file = if new.is_empty() {
+            KeyFile { key: STANDARD.encode(key), ..KeyFile::default() }
+        } else {
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            KeyFile {
+                sealed_key: seal_bytes(&derive_key(new, &salt)?, &key)?,
+                salt: STANDARD.encode(salt),
+                ..KeyFile::default()
+            }
+        };
+        self.write(&file)?;
+        set_data_key(Some(key));
+        Ok(())
+    }
+}
+
+impl KeyFile {
+    fn data_key(&self, passphrase: &str) -> Result<DataKey, String> {
+        if self.sealed_key.is_empty() {
+            return decode_key(&self.key);
+        }
+        let salt = STANDARD.decode(&self.salt).map_err(|_| "密钥文件已损坏".to_string())?;
+        let key = open_bytes(&derive_key(passphrase, &salt)?, &self.sealed_key)
+            .map_err(|_| "口令错误".to_string())?;
+        key.try_into().map_err(|_| "密钥文件已损坏".to_string())
+    }
+}
+
+/// Create `path` for writing with permissions for the current user only (0600).
+fn write_private(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+/// Tighten a key file written before permissions were restricted.
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if fs::metadata(path)?.permissions().mode() & 0o077 != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn decode_key(encoded: &str) -> Result<DataKey, String> {
+    STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|k| k.try_into().ok())
+        .ok_or_else(|| "密钥文件已损坏".to_string())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<DataKey, String> {
+    let mut key = DataKey::default();
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("口令派生密钥失败: {e}"))?;
+    Ok(key)
+}
+
+pub fn is_unlocked() -> bool {
+    data_key().is_some()
+}
+
+fn data_key() -> Option<DataKey> {
+    *DATA_KEY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn set_data_key(key: Option<DataKey>) {
+    *DATA_KEY.write().unwrap_or_else(|e| e.into_inner()) = key;
+}
+
+fn seal_bytes(key: &DataKey, plain: &[u8]) -> Result<String, String> {
+    let cipher = Aes256Gcm::new(key.into());
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), plain)
+        .map_err(|_| "加密失败".to_string())?;
+    Ok(STANDARD.encode([nonce.as_slice(), &sealed].concat()))
+}
+
+fn open_bytes(key: &DataKey, encoded: &str) -> Result<Vec<u8>, String> {
+    let bytes = STANDARD.decode(encoded).map_err(|_| "密文格式无效".to_string())?;
+    if bytes.len() < NONCE_LEN {
+        return Err("密文格式无效".to_string());
+    }
+    let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), sealed)
+        .map_err(|_| "解密失败".to_string())
+}
+
+fn locked() -> String {
+    OpenError::Locked.to_string()
+}
+
+/// Why a stored secret can't be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenError {
+    /// A passphrase protects the store and it hasn't been entered yet.
+    Locked,
+    /// Sealed under another data key (a database copied from elsewhere) or damaged:
+    /// the secret is lost and has to be entered again.
+    Unreadable,
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OpenError::Locked => "密钥库已锁定，请先输入口令解锁",
+            OpenError::Unreadable => "已保存的密钥无法解密（可能来自其他设备的数据库），请重新填写",
+        })
+    }
+}
+
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// Encrypt a secret for storage. Empty values stay empty.
+pub fn seal(plain: &str) -> Result<String, String> {
+    if plain.is_empty() {
+        return Ok(String::new());
+    }
+    let key = data_key().ok_or_else(locked)?;
+    Ok(format!("{PREFIX}{}", seal_bytes(&key, plain.as_bytes())?))
+}
+
+/// Decrypt a stored secret; plaintext from before encryption passes through.
+pub fn open(stored: &str) -> Result<String, OpenError> {
+    let Some(encoded) = stored.strip_prefix(PREFIX) else {
+        return Ok(stored.to_string());
+    };
+    let key = data_key().ok_or(OpenError::Locked)?;
+    open_bytes(&key, encoded)
+        .ok()
+        .and_then(|plain| String::from_utf8(plain).ok())
+        .ok_or(OpenError::Unreadable)
+}
+
+/// How a secret is shown to the frontend: its first 3 and last 4 characters at most.
+pub fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    match chars.len() {
+        0 => String::new(),
+        n if n <= 12 => MASK.to_string().repeat(8),
+        n => {
+            let head: String = chars[..3].iter().collect();
+            let tail: String = chars[n - 4..].iter().collect();
+            format!("{head}{}{tail}", MASK.to_string().repeat(8))
+        }
+    }
+}
+
+pub fn is_masked(value: &str) -> bool {
+    value.contains(MASK)
+}
+
+/// A value submitted from the frontend: a masked one stands for `stored`, the
+/// secret of the same field or key slot, and yields "" when there is none. Masks
+/// of short secrets all look alike, so they are never matched by their text.
+pub fn unmask(submitted: &str, stored: Option<&str>) -> String {
+    if !is_masked(submitted) {
+        return submitted.to_string();
+    }
+    stored.unwrap_or_default().to_string()
+}
+
+/// `mask` applied to the string fields of a secret JSON object.
+pub fn mask_json(json: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(json) {
+        Ok(serde_json::Value::Object(mut fields)) => {
+            for value in fields.values_mut() {
+                if let Some(s) = value.as_str() {
+                    *value = mask(s).into();
+                }
+            }
+            serde_json::Value::Object(fields).to_string()
+        }
+        _ => mask(json),
+    }
+}
+
+/// `submitted` secret JSON with masked fields taken from `stored`.
+pub fn unmask_json(submitted: &str, stored: Option<&str>) -> String {
+    let stored: serde_json::Value =
+        stored.and_then(|s| serde_json::from_str(s).ok()).unwrap_or_default();
+    match serde_json::from_str::<serde_json::Value>(submitted) {
+        Ok(serde_json::Value::Object(mut fields)) => {
+            for (name, value) in fields.iter_mut() {
+                if let Some(s) = value.as_str().filter(|s| is_masked(s)) {
+                    *value = unmask(s, stored[name].as_str()).into();
+                }
+            }
+            serde_json::Value::Object(fields).to_string()
+        }
+        _ => submitted.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Serializes the tests that set the process-wide data key.
+    static DATA_KEY_TESTS: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn mask_hides_short_secrets_entirely() {
+        assert_eq!(mask(""), "");
+        assert_eq!(mask("abc"), mask("xyz-123"));
+        assert!(!mask("short-key").contains("short"));
+    }
+
+    #[test]
+    fn mask_keeps_head_and_tail_of_long_secrets() {
+        assert_eq!(mask("sk-abcdefghijklmnop"), "sk-••••••••mnop");
+        assert!(is_masked(&mask("sk-abcdefghijklmnop")));
+    }
+
+    #[test]
+    fn unmask_takes_the_slot_not_a_lookalike() {
+        // Both short keys mask the same; each slot still gets its own secret back
+        let first = mask("key-one");
+        assert_eq!(first, mask("key-two"));
+        assert_eq!(unmask(&first, Some("key-two")), "key-two");
+        assert_eq!(unmask(&first, None), "");
+        assert_eq!(unmask("typed-in", Some("stored")), "typed-in");
+    }
+
+    #[test]
+    fn open_tells_lost_secrets_from_unset_ones() {
+        let _guard = DATA_KEY_TESTS.lock().unwrap_or_else(|e| e.into_inner());
+        set_data_key(Some([1; 32]));
+        let sealed = seal("sk-live-key").unwrap();
+        assert_eq!(open(&sealed).unwrap(), "sk-live-key");
+        assert_eq!(open("").unwrap(), "");
+        // Sealed under another data key, as in a database copied from elsewhere
+        set_data_key(Some([2; 32]));
+        assert_eq!(open(&sealed), Err(OpenError::Unreadable));
+        set_data_key(None);
+        assert_eq!(open(&sealed), Err(OpenError::Locked));
+    }
+
+    #[test]
+    fn unmask_json_restores_masked_fields_only() {
+        let stored = r#"{"apiKey":"secret-value-12345","region":"eu"}"#;
+        let masked: serde_json::Value = serde_json::from_str(&mask_json(stored)).unwrap();
+        let submitted = serde_json::json!({ "apiKey": masked["apiKey"], "region": "us" });
+        let submitted = submitted.to_string();
+        let restored: serde_json::Value =
+            serde_json::from_str(&unmask_json(&submitted, Some(stored))).unwrap();
+        assert_eq!(restored["apiKey"], "secret-value-12345");
+        assert_eq!(restored["region"], "us");
+    }
+
+    #[test]
+    fn damaged_key_file_is_reported_and_can_be_reset() {
+        let _guard = DATA_KEY_TESTS.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("secret-store-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(KEY_FILE), "{ not json").unwrap();
+        set_data_key(None);
+
+        let store = SecretStoreState::init(&dir);
+        let status = store.status();
+        assert!(!status.unlocked);
+        assert!(status.error.is_some());
+
+        store.reset().unwrap();
+        let status = store.status();
+        assert!(status.unlocked && status.error.is_none());
+        assert!(dir.join("secret.key.broken").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dir.join(KEY_FILE)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
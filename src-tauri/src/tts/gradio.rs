@@ -1,13 +1,15 @@
-use super::{TtsSynthRequest, TtsSynthResponse, TtsVoice};
+use super::{SynthError, TtsSynthRequest, TtsSynthResponse, TtsVoice};
+use crate::http::{self, ProxyConfig};
 use base64::{Engine as _, engine::general_purpose::STANDARD};
 
 pub struct GradioProvider {
     pub endpoint: String,
+    pub proxy: ProxyConfig,
 }
 
 impl GradioProvider {
-    pub fn new(endpoint: String) -> Self {
-        GradioProvider { endpoint }
+    pub fn new(endpoint: String, proxy: ProxyConfig) -> Self {
+        GradioProvider { endpoint, proxy }
     }
 
     pub async fn list_voices(&self) -> Result<Vec<TtsVoice>, String> {
@@ -15,7 +17,7 @@ impl GradioProvider {
         Ok(vec![])
     }
 
-    pub async fn synthesize(&self, req: TtsSynthRequest) -> Result<TtsSynthResponse, String> {
+    pub async fn synthesize(&self, req: TtsSynthRequest) -> Result<TtsSynthResponse, SynthError> {
         let reference_path = req.reference_audio_path
             .ok_or_else(|| "Gradio TTS 需要参考音频文件".to_string())?;
 
@@ -32,10 +34,7 @@ impl GradioProvider {
         let ref_data_url = format!("data:audio/{};base64,{}", ref_ext, ref_base64);
 
         // Call Gradio API predict endpoint
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(120))
-            .build()
-            .map_err(|e| e.to_string())?;
+        let client = http::client(&self.proxy, std::time::Duration::from_secs(120))?;
 
         let endpoint = self.endpoint.trim_end_matches('/');
         let url = format!("{}/run/predict", endpoint);
@@ -48,12 +47,12 @@ impl GradioProvider {
             .json(&body)
             .send()
             .await
-            .map_err(|e| format!("Gradio 请求失败: {e}"))?;
+            .map_err(|e| SynthError::endpoint(format!("Gradio 请求失败: {e}")))?;
 
         if !resp.status().is_success() {
             let status = resp.status();
             let text = resp.text().await.unwrap_or_default();
-            return Err(format!("Gradio API 错误 {status}: {text}"));
+            return Err(SynthError::status(status, format!("Gradio API 错误 {status}: {text}")));
         }
 
         let json: serde_json::Value = resp.json().await
@@ -80,10 +79,10 @@ impl GradioProvider {
             if let Some(d) = obj.get("data").and_then(|v| v.as_str()) {
                 STANDARD.decode(d).map_err(|e| format!("解码失败: {e}"))?
             } else {
-                return Err("Gradio 响应格式不支持".to_string());
+                return Err("Gradio 响应格式不支持".to_string().into());
             }
         } else {
-            return Err("Gradio 响应格式不支持".to_string());
+            return Err("Gradio 响应格式不支持".to_string().into());
         };
 
         std::fs::write(&req.output_path, &audio_bytes)
@@ -1,4 +1,5 @@
-use super::{TtsSynthRequest, TtsSynthResponse, TtsVoice};
+use super::{SynthError, TtsSynthRequest, TtsSynthResponse, TtsVoice};
+use crate::http::{self, ProxyConfig};
 use base64::{Engine as _, engine::general_purpose::STANDARD};
 use std::collections::HashMap;
 
@@ -14,10 +15,15 @@ pub struct HttpRestProvider {
     pub headers: HashMap<String, String>,
     #[allow(dead_code)]
     pub requires_ref: bool,
+    pub proxy: ProxyConfig,
 }
 
 impl HttpRestProvider {
-    pub fn from_json(config_json: &str, requires_ref: bool) -> Result<Self, String> {
+    pub fn from_json(
+        config_json: &str,
+        requires_ref: bool,
+        proxy: ProxyConfig,
+    ) -> Result<Self, String> {
         let v: serde_json::Value = serde_json::from_str(config_json)
             .map_err(|e| format!("解析 config_json 失败: {e}"))?;
 
@@ -40,6 +46,7 @@ impl HttpRestProvider {
             response_key: v["responseKey"].as_str().map(|s| s.to_string()),
             headers,
             requires_ref,
+            proxy,
         })
     }
 
@@ -47,11 +54,8 @@ impl HttpRestProvider {
         Ok(vec![])
     }
 
-    pub async fn synthesize(&self, req: TtsSynthRequest) -> Result<TtsSynthResponse, String> {
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(120))
-            .build()
-            .map_err(|e| e.to_string())?;
+    pub async fn synthesize(&self, req: TtsSynthRequest) -> Result<TtsSynthResponse, SynthError> {
+        let client = http::client(&self.proxy, std::time::Duration::from_secs(120))?;
 
         let mut body = serde_json::json!({});
         body[&self.text_key] = serde_json::Value::String(req.text.clone());
@@ -82,12 +86,13 @@ impl HttpRestProvider {
             .json(&body)
             .send()
             .await
-            .map_err(|e| format!("HTTP TTS 请求失败: {e}"))?;
+            .map_err(|e| SynthError::endpoint(format!("HTTP TTS 请求失败: {e}")))?;
 
         if !resp.status().is_success() {
             let status = resp.status();
             let text = resp.text().await.unwrap_or_default();
-            return Err(format!("HTTP TTS API 错误 {status}: {text}"));
+            let message = format!("HTTP TTS API 错误 {status}: {text}");
+            return Err(SynthError::status(status, message));
         }
 
         let audio_bytes = match self.response_type.as_str() {
@@ -109,10 +114,10 @@ impl HttpRestProvider {
                 let url = json[key].as_str()
                     .ok_or_else(|| format!("响应缺少 '{key}' 字段"))?;
                 let file_resp = client.get(url).send().await
-                    .map_err(|e| format!("下载音频文件失败: {e}"))?;
+                    .map_err(|e| SynthError::endpoint(format!("下载音频文件失败: {e}")))?;
                 file_resp.bytes().await.map_err(|e| format!("读取音频失败: {e}"))?.to_vec()
             }
-            other => return Err(format!("不支持的 response_type: {other}")),
+            other => return Err(format!("不支持的 response_type: {other}").into()),
         };
 
         std::fs::write(&req.output_path, &audio_bytes)
@@ -19,6 +19,37 @@ pub struct TtsSynthResponse {
     pub duration_ms: u64,
 }
 
+/// Why a synthesis failed. `endpoint` is set when the service itself failed (no
+/// connection, a 5xx answer); only those count toward the provider's circuit breaker.
+#[derive(Debug)]
+pub struct SynthError {
+    pub message: String,
+    pub endpoint: bool,
+}
+
+impl SynthError {
+    fn endpoint(message: String) -> Self {
+        SynthError { message, endpoint: true }
+    }
+
+    /// An error answer; the endpoint is at fault only for a 5xx.
+    fn status(status: reqwest::StatusCode, message: String) -> Self {
+        SynthError { message, endpoint: status.is_server_error() }
+    }
+}
+
+impl From<String> for SynthError {
+    fn from(message: String) -> Self {
+        SynthError { message, endpoint: false }
+    }
+}
+
+impl From<SynthError> for String {
+    fn from(e: SynthError) -> Self {
+        e.message
+    }
+}
+
 // Enum dispatch to avoid dyn Trait + async complexity
 pub mod ncn;
 pub mod gradio;
@@ -61,7 +92,7 @@ impl TtsProviderImpl {
         }
     }
 
-    pub async fn synthesize(&self, req: TtsSynthRequest) -> Result<TtsSynthResponse, String> {
+    pub async fn synthesize(&self, req: TtsSynthRequest) -> Result<TtsSynthResponse, SynthError> {
         match self {
             Self::Ncn(p) => p.synthesize(req).await,
             Self::Gradio(p) => p.synthesize(req).await,
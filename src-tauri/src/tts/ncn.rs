@@ -1,4 +1,5 @@
-use super::{TtsSynthRequest, TtsSynthResponse, TtsVoice};
+use super::{SynthError, TtsSynthRequest, TtsSynthResponse, TtsVoice};
+use crate::http::{self, ProxyConfig};
 use chrono::Local;
 use md5;
 use rand::Rng;
@@ -11,11 +12,12 @@ const HASH_MASK_2: u64 = 0x0FE00000;
 
 pub struct NcnProvider {
     pub voice_id: String,
+    pub proxy: ProxyConfig,
 }
 
 impl NcnProvider {
-    pub fn new(voice_id: String) -> Self {
-        NcnProvider { voice_id }
+    pub fn new(voice_id: String, proxy: ProxyConfig) -> Self {
+        NcnProvider { voice_id, proxy }
     }
 }
 
@@ -111,10 +113,7 @@ struct PlatformItem {
 impl NcnProvider {
     pub async fn list_voices(&self) -> Result<Vec<TtsVoice>, String> {
         let headers = build_auth_headers();
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .map_err(|e| e.to_string())?;
+        let client = http::client(&self.proxy, std::time::Duration::from_secs(30))?;
         let mut req = client.get("https://bot.n.cn/api/robot/platform");
         for (k, v) in &headers {
             req = req.header(k.as_str(), v.as_str());
@@ -131,7 +130,7 @@ impl NcnProvider {
         }).collect())
     }
 
-    pub async fn synthesize(&self, req: TtsSynthRequest) -> Result<TtsSynthResponse, String> {
+    pub async fn synthesize(&self, req: TtsSynthRequest) -> Result<TtsSynthResponse, SynthError> {
         let text = if req.text.chars().count() > 5000 {
             req.text.chars().take(5000).collect::<String>()
         } else {
@@ -139,28 +138,30 @@ impl NcnProvider {
         };
         let voice = req.voice_id.as_deref().unwrap_or(&self.voice_id);
         if voice.is_empty() {
-            return Err("未指定声音 ID".to_string());
+            return Err("未指定声音 ID".to_string().into());
         }
         let headers = build_auth_headers();
         let url = format!("https://bot.n.cn/api/tts/v1?roleid={}", voice);
         let encoded_text = urlencoding::encode(&text);
         let body = format!("&text={}&audio_type=mp3&format=stream", encoded_text);
-        let client = reqwest::Client::builder()
-            .timeout(std::time::Duration::from_secs(60))
-            .build()
-            .map_err(|e| e.to_string())?;
+        let client = http::client(&self.proxy, std::time::Duration::from_secs(60))?;
         let mut r = client.post(&url);
         for (k, v) in &headers {
             r = r.header(k.as_str(), v.as_str());
         }
         r = r.header("Content-Type", "application/x-www-form-urlencoded");
-        let resp = r.body(body).send().await.map_err(|e| format!("请求失败: {e}"))?;
+        let resp = r
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| SynthError::endpoint(format!("请求失败: {e}")))?;
         if !resp.status().is_success() {
-            return Err(format!("TTS合成失败: {}", resp.status()));
+            let status = resp.status();
+            return Err(SynthError::status(status, format!("TTS合成失败: {status}")));
         }
         let bytes = resp.bytes().await.map_err(|e| format!("读取响应失败: {e}"))?;
         if bytes.is_empty() {
-            return Err("返回的音频数据为空".to_string());
+            return Err("返回的音频数据为空".to_string().into());
         }
         // Write to file
         std::fs::write(&req.output_path, &bytes)
@@ -1,6 +1,8 @@
 import { ref, computed } from 'vue'
 import { invoke } from '@tauri-apps/api/core'
-import type { AiConfig } from '../types/ai-config'
+import type { AiConfig, ModelCapabilities, ModelInfo } from '../types/ai-config'
+import type { ProxyConfig } from '../types/network'
+import { toProxyConfig } from '../types/network'
 
 const aiConfigs = ref<AiConfig[]>([])
 
@@ -20,6 +22,19 @@ function toFrontend(raw: Record<string, unknown>): AiConfig {
     concurrentLimit: raw.concurrent_limit as number,
     requestTimeout: raw.request_timeout as number,
     rateLimit: raw.rate_limit as number,
+    maxInputTokens: (raw.max_input_tokens as number) ?? 0,
+    maxOutputTokens: (raw.max_output_tokens as number) ?? 0,
+    tpmLimit: (raw.tpm_limit as number) ?? 0,
+    apiKeys: ((raw.api_keys as Record<string, unknown>[]) ?? []).map(k => ({
+      apiKey: k.api_key as string,
+      concurrentLimit: (k.concurrent_limit as number) ?? 0,
+      rateLimit: (k.rate_limit as number) ?? 0,
+      tpmLimit: (k.tpm_limit as number) ?? 0,
+      position: (k.position as number | null) ?? null,
+    })),
+    proxy: toProxyConfig(raw.proxy),
+    capabilities: (raw.capabilities as ModelCapabilities | null) ?? null,
+    keysUnreadable: raw.keys_unreadable === true,
   }
 }
 
@@ -35,6 +50,18 @@ function toBackend(c: AiConfig): Record<string, unknown> {
     concurrent_limit: c.concurrentLimit,
     request_timeout: c.requestTimeout,
     rate_limit: c.rateLimit,
+    max_input_tokens: c.maxInputTokens,
+    max_output_tokens: c.maxOutputTokens,
+    tpm_limit: c.tpmLimit,
+    api_keys: c.apiKeys.map(k => ({
+      api_key: k.apiKey,
+      concurrent_limit: k.concurrentLimit,
+      rate_limit: k.rateLimit,
+      tpm_limit: k.tpmLimit,
+      position: k.position,
+    })),
+    proxy: c.proxy,
+    capabilities: c.capabilities,
   }
 }
 
@@ -70,16 +97,59 @@ async function setDefaultAiConfig(id: string): Promise<void> {
   await loadAiConfigs()
 }
 
+/** A config with its API keys unmasked; listed configs only carry masked keys. */
+async function revealAiConfig(id: string): Promise<AiConfig> {
+  return toFrontend(await invoke<Record<string, unknown>>('cmd_reveal_ai_config', { id }))
+}
+
+/** `configId` lets the backend resolve a still-masked `apiKey` to the stored key. */
 async function testAiConnection(
   baseUrl: string,
   apiKey: string,
   model: string,
+  proxy: ProxyConfig,
+  configId?: string,
 ): Promise<string> {
   return invoke<string>('cmd_test_ai_connection', {
     baseUrl,
     apiKey,
     model,
+    proxy,
+    configId: configId ?? null,
+  })
+}
+
+async function listAiModels(
+  baseUrl: string,
+  apiKey: string,
+  proxy: ProxyConfig,
+  configId?: string,
+): Promise<ModelInfo[]> {
+  return invoke<ModelInfo[]>('cmd_list_ai_models', {
+    baseUrl,
+    apiKey,
+    proxy,
+    configId: configId ?? null,
+  })
+}
+
+/** Probe a model; with `configId` the result is also saved on that config. */
+async function probeAiModel(
+  baseUrl: string,
+  apiKey: string,
+  model: string,
+  proxy: ProxyConfig,
+  configId?: string,
+): Promise<ModelCapabilities> {
+  const caps = await invoke<ModelCapabilities>('cmd_probe_ai_model', {
+    baseUrl,
+    apiKey,
+    model,
+    proxy,
+    configId: configId ?? null,
   })
+  if (configId) await loadAiConfigs()
+  return caps
 }
 
 export function useAiConfigs() {
@@ -91,6 +161,9 @@ export function useAiConfigs() {
     updateAiConfig,
     deleteAiConfig,
     setDefaultAiConfig,
+    revealAiConfig,
     testAiConnection,
+    listAiModels,
+    probeAiModel,
   }
 }
@@ -15,3 +15,16 @@ export async function getProviderSecret(providerId: string): Promise<string | nu
 export async function setProviderSecret(providerId: string, secretJson: string): Promise<void> {
   return invoke('cmd_set_provider_secret', { providerId, secretJson })
 }
+
+export interface LlmCacheInfo {
+  entries: number
+  bytes: number
+}
+
+export async function getLlmCacheInfo(): Promise<LlmCacheInfo> {
+  return invoke<LlmCacheInfo>('cmd_get_llm_cache_info')
+}
+
+export async function clearLlmCache(): Promise<void> {
+  return invoke('cmd_clear_llm_cache')
+}
@@ -51,7 +51,9 @@ let unlistenPreprocess: UnlistenFn | null = null
 async function startListening() {
   unlistenProgress = await listen<DubbingProgressEvent>('dubbing:progress', ({ payload }) => {
     stageProgress.value[payload.stage] = payload.percent
-    currentMessage.value = payload.message
+    currentMessage.value = payload.cache?.hits
+      ? `${payload.message}（缓存命中 ${payload.cache.hits}）`
+      : payload.message
   })
   unlistenStageChange = await listen<DubbingStageChangeEvent>('dubbing:stage_change', ({ payload }) => {
     stageStatuses.value[payload.stage] = payload.status as DubbingStatus
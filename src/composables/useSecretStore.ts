@@ -0,0 +1,61 @@
+import { ref, computed } from 'vue'
+import { invoke } from '@tauri-apps/api/core'
+
+export interface SecretStoreStatus {
+  /** A passphrase protects the stored API keys and provider secrets. */
+  passphrase: boolean
+  unlocked: boolean
+  /** The key file can't be used (unreadable, damaged); only a reset helps. */
+  error: string | null
+}
+
+const status = ref<SecretStoreStatus>({ passphrase: false, unlocked: true, error: null })
+const locked = computed(() => !status.value.unlocked)
+
+let resolveUnlocked: (() => void) | null = null
+
+async function loadStatus(): Promise<void> {
+  status.value = await invoke<SecretStoreStatus>('cmd_get_secret_store_status')
+  if (status.value.unlocked && resolveUnlocked) {
+    resolveUnlocked()
+    resolveUnlocked = null
+  }
+}
+
+/** Resolves once secrets are readable; with a passphrase, after it's entered. */
+async function ensureUnlocked(): Promise<void> {
+  await loadStatus()
+  if (status.value.unlocked) return
+  await new Promise<void>(resolve => {
+    resolveUnlocked = resolve
+  })
+}
+
+async function unlock(passphrase: string): Promise<void> {
+  await invoke('cmd_unlock_secret_store', { passphrase })
+  await loadStatus()
+}
+
+/** Replace an unusable key file; secrets sealed under it have to be entered again. */
+async function reset(): Promise<void> {
+  await invoke('cmd_reset_secret_store')
+  await loadStatus()
+}
+
+/** Set, change or (with an empty `newPassphrase`) remove the passphrase. */
+async function setPassphrase(currentPassphrase: string, newPassphrase: string): Promise<void> {
+  await invoke('cmd_set_secret_passphrase', { currentPassphrase, newPassphrase })
+  await loadStatus()
+}
+
+export function useSecretStore() {
+  return {
+    status,
+    locked,
+    loadStatus,
+    ensureUnlocked,
+    unlock,
+    reset,
+    setPassphrase,
+  }
+}
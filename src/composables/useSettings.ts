@@ -1,5 +1,6 @@
 import { ref, watch } from 'vue'
 import { setConfig } from './useDatabase'
+import type { ProxyConfig, ProxyMode } from '../types/network'
 
 type Theme = 'dark' | 'light' | 'system'
 
@@ -7,12 +8,18 @@ interface AppSettings {
   theme: Theme
   closeToTray: boolean
   sidebarCollapsed: boolean
+  proxy: ProxyConfig
+  llmCache: boolean
+  llmCacheMaxMb: number
 }
 
 const defaults: AppSettings = {
   theme: 'dark',
   closeToTray: false,
   sidebarCollapsed: false,
+  proxy: { mode: 'system', url: '', username: '', password: '', noProxy: '' },
+  llmCache: false,
+  llmCacheMaxMb: 200,
 }
 
 const settings = ref<AppSettings>({ ...defaults })
@@ -67,11 +74,32 @@ function setSidebarCollapsed(value: boolean) {
   settings.value.sidebarCollapsed = value
 }
 
+function setProxy(value: ProxyConfig) {
+  settings.value.proxy = value
+}
+
+function setLlmCache(value: boolean) {
+  settings.value.llmCache = value
+}
+
+function setLlmCacheMaxMb(value: number) {
+  settings.value.llmCacheMaxMb = value
+}
+
 export async function initSettings(dbConfig: Record<string, string>) {
   settings.value = {
     theme: (dbConfig['ui.theme'] as Theme) ?? defaults.theme,
     closeToTray: dbConfig['system.close_to_tray'] === 'true',
     sidebarCollapsed: dbConfig['ui.sidebar_collapsed'] === 'true',
+    proxy: {
+      mode: (dbConfig['network.proxy_mode'] as ProxyMode) || defaults.proxy.mode,
+      url: dbConfig['network.proxy_url'] ?? '',
+      username: dbConfig['network.proxy_username'] ?? '',
+      password: dbConfig['network.proxy_password'] ?? '',
+      noProxy: dbConfig['network.no_proxy'] ?? '',
+    },
+    llmCache: dbConfig['llm_cache.enabled'] === 'true',
+    llmCacheMaxMb: Number(dbConfig['llm_cache.max_mb']) || defaults.llmCacheMaxMb,
   }
   isLoaded.value = true
 }
@@ -83,6 +111,13 @@ watch(
     await setConfig('ui.theme', val.theme)
     await setConfig('system.close_to_tray', String(val.closeToTray))
     await setConfig('ui.sidebar_collapsed', String(val.sidebarCollapsed))
+    await setConfig('network.proxy_mode', val.proxy.mode)
+    await setConfig('network.proxy_url', val.proxy.url)
+    await setConfig('network.proxy_username', val.proxy.username)
+    await setConfig('network.proxy_password', val.proxy.password)
+    await setConfig('network.no_proxy', val.proxy.noProxy)
+    await setConfig('llm_cache.enabled', String(val.llmCache))
+    await setConfig('llm_cache.max_mb', String(val.llmCacheMaxMb))
   },
   { deep: true },
 )
@@ -93,6 +128,9 @@ export function useSettings() {
     setTheme,
     setCloseToTray,
     setSidebarCollapsed,
+    setProxy,
+    setLlmCache,
+    setLlmCacheMaxMb,
     applyTheme,
   }
 }
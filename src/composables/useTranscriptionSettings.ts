@@ -31,6 +31,8 @@ const transcriptionSettings = ref<TranscriptionSettings>({
   },
 })
 const isLoaded = ref(false)
+/** Why the stored ElevenLabs key couldn't be read, e.g. it can't be decrypted. */
+const apiKeyError = ref('')
 
 export async function initTranscriptionSettings(dbConfig: Record<string, string>) {
   let bcutConfig = { ...defaults.configs.bcut }
@@ -55,7 +57,9 @@ export async function initTranscriptionSettings(dbConfig: Record<string, string>
     if (secret) {
       apiKey = (JSON.parse(secret) as { apiKey?: string }).apiKey ?? ''
     }
-  } catch { /* ignore */ }
+  } catch (err) {
+    apiKeyError.value = String(err)
+  }
 
   transcriptionSettings.value = {
     activeProviderId: (dbConfig['transcription.active_provider'] as TranscriptionProviderId) ?? defaults.activeProviderId,
@@ -77,7 +81,10 @@ watch(
     await setConfig('transcription.config.bcut', JSON.stringify(val.configs.bcut))
     await setConfig('transcription.config.elevenlabs-free', JSON.stringify(val.configs['elevenlabs-free']))
     await setConfig('transcription.config.elevenlabs-paid', JSON.stringify(paidWithoutKey))
+    // An unreadable key stays stored (and reported) until a new one is entered
+    if (apiKeyError.value && !apiKey) return
     await setProviderSecret('elevenlabs-paid', JSON.stringify({ apiKey }))
+    apiKeyError.value = ''
   },
   { deep: true },
 )
@@ -95,7 +102,11 @@ function validateActive() {
   const provider = getProvider(transcriptionSettings.value.activeProviderId)
   if (!provider) return { valid: false, errors: { _: '无效的 Provider' } }
   const config = transcriptionSettings.value.configs[transcriptionSettings.value.activeProviderId]
-  return provider.validate(config as never)
+  const result = provider.validate(config as never)
+  if (result.errors.apiKey && apiKeyError.value) {
+    result.errors.apiKey = apiKeyError.value
+  }
+  return result
 }
 
 function resetConfig(id: TranscriptionProviderId) {
@@ -117,5 +128,6 @@ export function useTranscriptionSettings() {
     updateActiveConfig,
     validateActive,
     resetConfig,
+    apiKeyError,
   }
 }
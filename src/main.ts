@@ -7,6 +7,7 @@ import { initTranscriptionSettings } from "./composables/useTranscriptionSetting
 import { initTranslationSettings } from "./composables/useTranslationSettings"
 import { getAllConfig } from "./composables/useDatabase"
 import { useAiConfigs } from "./composables/useAiConfigs"
+import { useSecretStore } from "./composables/useSecretStore"
 import { getCurrentWindow } from "@tauri-apps/api/window"
 
 async function bootstrap() {
@@ -19,7 +20,14 @@ async function bootstrap() {
   app.use(router)
   app.mount("#app")
 
-  // 3. Fetch all config from DB in one IPC call, then hydrate composables
+  // 3. Wait for the passphrase if secrets are locked; settings read provider secrets
+  try {
+    await useSecretStore().ensureUnlocked()
+  } catch (err) {
+    console.error("[bootstrap] secret store status failed", err)
+  }
+
+  // 4. Fetch all config from DB in one IPC call, then hydrate composables
   try {
     const dbConfig = await getAllConfig()
     await initSettings(dbConfig)
@@ -30,11 +38,11 @@ async function bootstrap() {
     console.error("[bootstrap] DB load failed, using defaults", err)
   }
 
-  // 4. Load AI configs
+  // 5. Load AI configs
   const { loadAiConfigs } = useAiConfigs()
   await loadAiConfigs()
 
-  // 4. Bind close-to-tray window event
+  // 6. Bind close-to-tray window event
   getCurrentWindow().onCloseRequested(async (event) => {
     if (settings.value.closeToTray) {
       event.preventDefault()
@@ -1,3 +1,16 @@
+import type { ProxyConfig } from './network'
+import { PROXY_INHERIT } from './network'
+
+/** An additional API key of a config; limits of 0 use the config's own. */
+export interface AiConfigKey {
+  apiKey: string
+  concurrentLimit: number
+  rateLimit: number
+  tpmLimit: number
+  /** Stored slot of the key; `null` for keys added in the form. */
+  position: number | null
+}
+
 export interface AiConfig {
   id: string
   title: string
@@ -9,6 +22,34 @@ export interface AiConfig {
   concurrentLimit: number
   requestTimeout: number
   rateLimit: number
+  maxInputTokens: number
+  maxOutputTokens: number
+  tpmLimit: number
+  apiKeys: AiConfigKey[]
+  proxy: ProxyConfig
+  /** Result of the last probe; applies only while `model` matches. */
+  capabilities: ModelCapabilities | null
+  /** A stored key couldn't be decrypted and has to be entered again. */
+  keysUnreadable: boolean
+}
+
+/** One model of an endpoint's listing; limits of 0 are unknown. */
+export interface ModelInfo {
+  id: string
+  contextLength: number
+  maxOutputTokens: number
+}
+
+export interface ModelCapabilities {
+  model: string
+  jsonMode: boolean
+  streaming: boolean
+  contextLength: number
+  maxOutputTokens: number
+  latencyMs: number
+  firstTokenMs: number
+  tokensPerSecond: number
+  probedAt: string
 }
 
 export const AI_CONFIG_DEFAULTS: Omit<AiConfig, 'id'> = {
@@ -21,4 +62,11 @@ export const AI_CONFIG_DEFAULTS: Omit<AiConfig, 'id'> = {
   concurrentLimit: 5,
   requestTimeout: 180,
   rateLimit: 60,
+  maxInputTokens: 0,
+  maxOutputTokens: 0,
+  tpmLimit: 0,
+  apiKeys: [],
+  proxy: PROXY_INHERIT,
+  capabilities: null,
+  keysUnreadable: false,
 }
@@ -58,6 +58,8 @@ export interface DubbingProgressEvent {
   stage: DubbingStage
   percent: number
   message: string
+  /** LLM response cache hits and misses, for stages that call the LLM */
+  cache?: { hits: number; misses: number }
 }
 
 export interface DubbingStageChangeEvent {
@@ -0,0 +1,39 @@
+/** `inherit` uses the global proxy and is only offered for per-provider overrides. */
+export type ProxyMode = 'inherit' | 'system' | 'none' | 'custom'
+
+export interface ProxyConfig {
+  mode: ProxyMode
+  /** http://, https://, socks5:// or socks5h://host:port */
+  url: string
+  username: string
+  password: string
+  /** Comma-separated hosts, .domain suffixes or CIDRs that bypass the proxy */
+  noProxy: string
+}
+
+export const PROXY_INHERIT: ProxyConfig = {
+  mode: 'inherit',
+  url: '',
+  username: '',
+  password: '',
+  noProxy: '',
+}
+
+export const PROXY_MODE_LABELS: Record<ProxyMode, string> = {
+  inherit: '使用全局代理',
+  system: '系统代理',
+  none: '不使用代理',
+  custom: '自定义代理',
+}
+
+/** Read a stored override; anything missing or malformed inherits. */
+export function toProxyConfig(raw: unknown): ProxyConfig {
+  const p = (raw ?? {}) as Partial<ProxyConfig>
+  return {
+    mode: p.mode && p.mode in PROXY_MODE_LABELS ? p.mode : 'inherit',
+    url: p.url ?? '',
+    username: p.username ?? '',
+    password: p.password ?? '',
+    noProxy: p.noProxy ?? '',
+  }
+}
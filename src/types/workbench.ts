@@ -15,6 +15,14 @@ export interface Subtitle {
   startTime: number
   endTime: number
   text: string
+  /** Word timestamps, when the transcription service returns them. */
+  words?: WordTiming[]
+}
+
+export interface WordTiming {
+  start: number
+  end: number
+  text: string
 }
 
 export interface TTSVoice {